@@ -0,0 +1,272 @@
+//! `#[derive(Component)]` for oxidized components, so a component is just the struct
+//! and its tick method.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Field, Fields, GenericArgument, Path, PathArguments, Result, Type};
+
+#[derive(Clone, Copy)]
+enum Tick {
+    Default,
+    Input,
+    Render,
+}
+
+struct Options {
+    tick: Tick,
+    allow_multiple: bool,
+    requires: Vec<Path>,
+    hooks: bool,
+    new: bool,
+}
+
+enum Saved {
+    Value,
+    Link(Box<Type>),
+    Skip,
+}
+
+/// Implements `ComponentTrait` and save/load for a component, adds a `new` that wraps it
+/// in a `ComponentWrapper` and registers it for loading. The struct needs a
+/// `state: ComponentState` field and, depending on its tick type, an impl of
+/// `TickBehavior`, `InputTickBehavior` or `RenderTickBehavior`.
+///
+/// On the struct, `#[component(..)]` takes:
+/// - `input` or `render` to pick the tick type, `default` otherwise
+/// - `requires(Transform, ..)`, components added first with their `new()`
+/// - `allow_multiple`
+/// - `hooks` to run the lifecycle hooks of its `ComponentHooks` impl
+/// - `no_new` to leave out `new`, which takes every field but `state` and skipped ones
+///
+/// On fields, `#[save(skip)]` leaves the field out of saves, it's loaded as its default.
+/// `#[save(link)]` saves a `Link<T>` field as a link to the static component. Loading a
+/// field that doesn't match its type fails with `EngineError::InvalidSave`.
+#[proc_macro_derive(Component, attributes(component, save))]
+pub fn derive_component(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2> {
+    let ident = &input.ident;
+    let name = ident.to_string();
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(&input.generics, "components can't be generic"));
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(Error::new_spanned(ident, "components need named fields")),
+        },
+        _ => return Err(Error::new_spanned(ident, "only structs can be components")),
+    };
+    if !fields.iter().any(|field| field.ident.as_ref().is_some_and(|field| field == "state")) {
+        return Err(Error::new_spanned(ident, "components need a `state: ComponentState` field"));
+    }
+
+    let options = parse_options(input)?;
+    let saved = fields.iter().map(field_saved).collect::<Result<Vec<_>>>()?;
+
+    let ticker = match options.tick {
+        Tick::Default => quote! {
+            crate::engine::component::TickVariant::Default(<#ident as crate::engine::component::TickBehavior>::tick)
+        },
+        Tick::Input => quote! {
+            crate::engine::component::TickVariant::Input(<#ident as crate::engine::component::InputTickBehavior>::tick_with_input)
+        },
+        Tick::Render => quote! {
+            crate::engine::component::TickVariant::Render(<#ident as crate::engine::component::RenderTickBehavior>::render_tick)
+        },
+    };
+
+    let requires = options.requires.iter().map(|path| {
+        quote! { crate::engine::component::Requirement::new::<#path>(#path::new) }
+    });
+    let allow_multiple = options.allow_multiple;
+    let hooks = options.hooks.then(|| {
+        let hooks = ["on_add", "on_start", "on_enable", "on_disable", "on_destroy", "on_shutdown"]
+            .iter()
+            .map(|hook| syn::Ident::new(hook, proc_macro2::Span::call_site()));
+        quote! {
+            #(
+                fn #hooks(&mut self, obj: &mut crate::engine::gameobject::GameObject) {
+                    crate::engine::component::ComponentHooks::#hooks(self, obj)
+                }
+            )*
+        }
+    });
+
+    let mut save = Vec::new();
+    let mut load = Vec::new();
+    for (field, saved) in fields.iter().zip(&saved) {
+        let field_ident = field.ident.as_ref().unwrap();
+        let key = field_ident.to_string();
+        match saved {
+            Saved::Value => {
+                save.push(quote! {
+                    data.insert(#key.to_string(), crate::engine::save::save_field(#name, #key, &self.#field_ident));
+                });
+                load.push(quote! {
+                    #field_ident: crate::engine::save::load_field(#name, #key, &data)?
+                });
+            }
+            Saved::Link(target) => {
+                save.push(quote! {
+                    data.insert(#key.to_string(), crate::engine::save::save_link(&mut self.#field_ident));
+                });
+                load.push(quote! {
+                    #field_ident: crate::engine::save::load_link::<#target>(&data[#key])
+                });
+            }
+            Saved::Skip => load.push(quote! { #field_ident: ::std::default::Default::default() }),
+        }
+    }
+
+    let new = options.new.then(|| {
+        let (args, inits): (Vec<_>, Vec<_>) = fields
+            .iter()
+            .zip(&saved)
+            .map(|(field, saved)| {
+                let field_ident = field.ident.as_ref().unwrap();
+                let ty = &field.ty;
+                match saved {
+                    _ if field_ident == "state" => {
+                        (None, quote! { #field_ident: crate::engine::component::ComponentState::new() })
+                    }
+                    Saved::Skip => (None, quote! { #field_ident: ::std::default::Default::default() }),
+                    _ => (Some(quote! { #field_ident: #ty }), quote! { #field_ident }),
+                }
+            })
+            .unzip();
+        let args = args.into_iter().flatten();
+        let vis = &input.vis;
+        quote! {
+            impl #ident {
+                #vis fn new(#(#args),*) -> crate::engine::component::ComponentWrapper {
+                    Self { #(#inits),* }.into()
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl crate::engine::component::ComponentTrait for #ident {
+            fn name(&self) -> &str {
+                #name
+            }
+
+            fn state(&mut self) -> &mut crate::engine::component::ComponentState {
+                &mut self.state
+            }
+
+            fn requires() -> ::std::vec::Vec<crate::engine::component::Requirement> {
+                ::std::vec![#(#requires),*]
+            }
+
+            fn allow_multiple() -> bool {
+                #allow_multiple
+            }
+
+            #hooks
+        }
+
+        impl crate::engine::save::ComponentSaveLoad for #ident {
+            fn to_save_data(&mut self) -> ::serde_json::Value {
+                let mut data = ::serde_json::Map::new();
+                #(#save)*
+                ::serde_json::Value::Object(data)
+            }
+
+            fn from_save_data(
+                data: ::serde_json::Value,
+            ) -> crate::engine::error::EngineResult<crate::engine::component::ComponentWrapper> {
+                ::std::result::Result::Ok(Self { #(#load),* }.into())
+            }
+        }
+
+        impl ::std::convert::From<#ident> for crate::engine::component::ComponentWrapper {
+            fn from(component: #ident) -> Self {
+                crate::engine::component::ComponentWrapper::new(component, #ticker)
+            }
+        }
+
+        #new
+
+        ::inventory::submit! {
+            crate::engine::save::ComponentRegistration {
+                name: #name,
+                from_save_data: <#ident as crate::engine::save::ComponentSaveLoad>::from_save_data,
+            }
+        }
+    })
+}
+
+fn parse_options(input: &DeriveInput) -> Result<Options> {
+    let mut options = Options {
+        tick: Tick::Default,
+        allow_multiple: false,
+        requires: Vec::new(),
+        hooks: false,
+        new: true,
+    };
+
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("component")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("default") {
+                options.tick = Tick::Default;
+            } else if meta.path.is_ident("input") {
+                options.tick = Tick::Input;
+            } else if meta.path.is_ident("render") {
+                options.tick = Tick::Render;
+            } else if meta.path.is_ident("allow_multiple") {
+                options.allow_multiple = true;
+            } else if meta.path.is_ident("hooks") {
+                options.hooks = true;
+            } else if meta.path.is_ident("no_new") {
+                options.new = false;
+            } else if meta.path.is_ident("requires") {
+                meta.parse_nested_meta(|requirement| {
+                    options.requires.push(requirement.path);
+                    Ok(())
+                })?;
+            } else {
+                return Err(meta.error("unknown component option"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(options)
+}
+
+fn field_saved(field: &Field) -> Result<Saved> {
+    let mut saved = Saved::Value;
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("save")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                saved = Saved::Skip;
+            } else if meta.path.is_ident("link") {
+                saved = Saved::Link(Box::new(link_target(&field.ty)?));
+            } else {
+                return Err(meta.error("expected `skip` or `link`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(saved)
+}
+
+// The `T` of a `Link<T>` field.
+fn link_target(ty: &Type) -> Result<Type> {
+    if let Type::Path(path) = ty {
+        if let Some(segment) = path.path.segments.last().filter(|segment| segment.ident == "Link") {
+            if let PathArguments::AngleBracketed(args) = &segment.arguments {
+                if let Some(GenericArgument::Type(target)) = args.args.first() {
+                    return Ok(target.clone());
+                }
+            }
+        }
+    }
+    Err(Error::new_spanned(ty, "`#[save(link)]` fields have to be a `Link<T>`"))
+}
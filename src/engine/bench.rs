@@ -0,0 +1,141 @@
+//! Tick cost with 10k objects. Run with
+//! `cargo test --release bench_ -- --ignored --nocapture --test-threads=1`.
+
+use crate::engine::component::{ComponentState, ComponentTrait, ComponentWrapper, TickBehavior, TickVariant, Transform};
+use crate::engine::gameobject::{make_base_game_object, GameObject};
+use crate::engine::error::EngineResult;
+use crate::engine::save::ComponentSaveLoad;
+use crate::engine::state::Engine;
+use crate::engine::system::{QuerySystem, Scheduler};
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+const OBJECTS: usize = 10_000;
+const TICKS: u32 = 100;
+
+struct Mover {
+    state: ComponentState,
+    velocity: [f32; 3],
+}
+
+impl Mover {
+    fn new() -> ComponentWrapper {
+        let component = Self { state: ComponentState::new(), velocity: [1.0, 0.5, 0.0] };
+        ComponentWrapper::new(component, TickVariant::Default(Self::tick))
+    }
+}
+
+impl ComponentTrait for Mover {
+    fn name(&self) -> &str { "Mover" }
+    fn state(&mut self) -> &mut ComponentState { &mut self.state }
+}
+
+impl ComponentSaveLoad for Mover {
+    fn to_save_data(&mut self) -> Value { Value::Null }
+    fn from_save_data(_data: Value) -> EngineResult<ComponentWrapper> { Ok(Mover::new()) }
+}
+
+impl TickBehavior for Mover {
+    fn tick(&mut self, obj: &mut GameObject, dt: Duration) {
+        let velocity = self.velocity;
+        obj.get_component_closure::<Transform>(|transform| {
+            for (pos, velocity) in transform.pos.iter_mut().zip(velocity) {
+                *pos += velocity * dt.as_secs_f32();
+            }
+        });
+    }
+}
+
+fn populate() -> Engine {
+    let mut engine = Engine::new_headless();
+    engine.dt = Some(Duration::from_millis(16));
+    let world = engine.world();
+
+    for i in 0..OBJECTS {
+        let obj = engine.add_object(make_base_game_object(&world, format!("object {}", i)));
+        obj.get().lock().unwrap().add_component(Mover::new()).unwrap();
+    }
+
+    engine
+}
+
+#[test]
+#[ignore]
+fn bench_tick_10k_objects() {
+    let mut engine = populate();
+    engine.tick();
+
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        engine.tick();
+    }
+    let per_tick = start.elapsed() / TICKS;
+
+    println!("tick, {} objects: {:?} per tick", OBJECTS, per_tick);
+}
+
+#[test]
+#[ignore]
+fn bench_query_10k_objects() {
+    let engine = populate();
+    let dt = Duration::from_millis(16).as_secs_f32();
+
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        engine.query::<(Transform, Mover)>().for_each(|_, (transform, mover)| {
+            for (pos, velocity) in transform.pos.iter_mut().zip(mover.velocity) {
+                *pos += velocity * dt;
+            }
+        });
+    }
+    let per_pass = start.elapsed() / TICKS;
+
+    println!("query, {} objects: {:?} per pass", OBJECTS, per_pass);
+}
+
+#[test]
+#[ignore]
+fn bench_systems_10k_objects() {
+    let engine = populate();
+    let world = engine.world();
+    let dt = Duration::from_millis(16);
+
+    for threads in [1, rayon::current_num_threads().max(2)] {
+        let mut scheduler = Scheduler::with_threads(threads);
+        scheduler.add(QuerySystem::<(Transform, Mover), _>::new("move", |_, (transform, mover), dt| {
+            for (pos, velocity) in transform.pos.iter_mut().zip(mover.velocity) {
+                *pos += velocity * dt.as_secs_f32();
+            }
+        }));
+        scheduler.run(&world, dt);
+
+        let start = Instant::now();
+        for _ in 0..TICKS {
+            scheduler.run(&world, dt);
+        }
+        let per_run = start.elapsed() / TICKS;
+
+        println!("systems, {} objects, {} threads: {:?} per run", OBJECTS, threads, per_run);
+    }
+}
+
+#[test]
+#[ignore]
+fn bench_find_by_tag_10k_objects() {
+    let engine = populate();
+    let world = engine.world();
+    for (i, (_, obj)) in world.objects().into_iter().enumerate() {
+        if i % 100 == 0 {
+            obj.lock().unwrap().add_tag("rare");
+        }
+    }
+
+    let start = Instant::now();
+    let mut found = 0;
+    for _ in 0..TICKS {
+        found += world.find_all_by_tag("rare").len();
+    }
+    let per_lookup = start.elapsed() / TICKS;
+
+    println!("find_all_by_tag, {} of {} objects: {:?} per lookup", found / TICKS as usize, OBJECTS, per_lookup);
+}
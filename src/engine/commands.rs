@@ -0,0 +1,251 @@
+use crate::engine::collider::Collider;
+use crate::engine::component::{ComponentTrait, ComponentWrapper};
+use crate::engine::gameobject::{self, GameObject, GameObjectState, MutexdGameObject};
+use crate::engine::world::{ObjectId, World};
+use log::{error, warn};
+use std::any::TypeId;
+use std::sync::{Arc, Mutex, Weak};
+
+/// A change to the world recorded by [`Commands`].
+pub enum Command {
+    Spawn {
+        id: ObjectId,
+        name: String,
+        components: Vec<ComponentWrapper>,
+    },
+    Destroy(ObjectId),
+    AddComponent(ObjectId, ComponentWrapper),
+    RemoveComponent(ObjectId, TypeId),
+    AddCollider(ObjectId, Arc<Mutex<Box<dyn Collider>>>),
+    Reparent {
+        parent: ObjectId,
+        child: ObjectId,
+    },
+}
+
+/// Structural changes recorded during a tick and applied in order at the end of it,
+/// once nothing is being ticked. Recording never locks an object, so it is safe from
+/// any component or static component.
+pub struct Commands {
+    queue: Mutex<Vec<Command>>,
+    world: Weak<World>,
+}
+
+impl Commands {
+    pub(crate) fn new(world: Weak<World>) -> Self {
+        Self {
+            queue: Mutex::new(Vec::new()),
+            world,
+        }
+    }
+
+    pub fn push(&self, command: Command) {
+        self.queue.lock().unwrap().push(command);
+    }
+
+    /// Spawns an object with `components`. The id is reserved straight away, so it can
+    /// be used in later commands of the same tick.
+    pub fn spawn(&self, name: &str, components: Vec<ComponentWrapper>) -> ObjectId {
+        let id = self.world.upgrade().expect("Commands outlived their world").next_id();
+        self.push(Command::Spawn {
+            id,
+            name: name.to_string(),
+            components,
+        });
+        id
+    }
+
+    pub fn destroy(&self, id: ObjectId) {
+        self.push(Command::Destroy(id));
+    }
+
+    pub fn add_component(&self, id: ObjectId, component: ComponentWrapper) {
+        self.push(Command::AddComponent(id, component));
+    }
+
+    pub fn remove_component<T: ComponentTrait>(&self, id: ObjectId) {
+        self.push(Command::RemoveComponent(id, TypeId::of::<T>()));
+    }
+
+    pub fn add_collider(&self, id: ObjectId, collider: Arc<Mutex<Box<dyn Collider>>>) {
+        self.push(Command::AddCollider(id, collider));
+    }
+
+    pub fn reparent(&self, parent: ObjectId, child: ObjectId) {
+        self.push(Command::Reparent { parent, child });
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops every recorded command without applying it. Ids reserved by
+    /// [`Commands::spawn`] go back to the world.
+    pub fn clear(&self) {
+        let world = self.world.upgrade();
+        for command in self.take() {
+            if let (Command::Spawn { id, .. }, Some(world)) = (&command, &world) {
+                world.release_id(*id);
+            }
+        }
+    }
+
+    pub(crate) fn take(&self) -> Vec<Command> {
+        std::mem::take(&mut *self.queue.lock().unwrap())
+    }
+}
+
+impl Command {
+    /// Applies the command, returning the object it spawned. Commands on objects that
+    /// are gone are skipped with a warning.
+    pub(crate) fn apply(self, world: &Arc<World>) -> Option<MutexdGameObject> {
+        match self {
+            Command::Spawn { id, name, components } => {
+                return Some(GameObject::new_with_id(world, id, name, components, GameObjectState::new(true, None, vec![])));
+            }
+            Command::Destroy(id) => world.destroy(id),
+            Command::AddComponent(id, component) => {
+                if let Err(err) = gameobject::add_component(world, id, component) {
+                    error!("{}", err);
+                }
+            }
+            Command::RemoveComponent(id, type_id) => match world.find_by_id(id) {
+                Some(obj) => {
+                    obj.lock().unwrap().remove_component_type(type_id);
+                }
+                None => warn!("Can't remove a component from {}, it is gone", id),
+            },
+            Command::AddCollider(id, collider) => match world.find_by_id(id) {
+                Some(obj) => obj.lock().unwrap().add_collider(collider),
+                None => warn!("Can't add a collider to {}, it is gone", id),
+            },
+            Command::Reparent { parent, child } => {
+                if let Err(err) = gameobject::reparent(world, parent, child) {
+                    warn!("Can't make {} a child of {}: {}", child, parent, err);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::collider::CubeCollider;
+    use crate::engine::component::{ComponentState, TickBehavior, TickVariant, Transform};
+    use crate::engine::components::RenderComponent;
+    use crate::engine::error::EngineResult;
+    use crate::engine::gameobject::make_base_game_object;
+    use crate::engine::graphics_backend::primitives::Primitives;
+    use crate::engine::save::ComponentSaveLoad;
+    use crate::engine::state::Engine;
+    use serde_json::Value;
+    use std::time::Duration;
+
+    // Spawns a child and destroys `target` on its first tick
+    struct Spawner {
+        state: ComponentState,
+        target: ObjectId,
+        spawned: Option<ObjectId>,
+        seen_objects: usize,
+    }
+
+    impl Spawner {
+        fn new(target: ObjectId) -> ComponentWrapper {
+            let component = Self { state: ComponentState::new(), target, spawned: None, seen_objects: 0 };
+            ComponentWrapper::new(component, TickVariant::Default(Self::tick))
+        }
+    }
+
+    impl ComponentTrait for Spawner {
+        fn name(&self) -> &str { "Spawner" }
+        fn state(&mut self) -> &mut ComponentState { &mut self.state }
+    }
+
+    impl ComponentSaveLoad for Spawner {
+        fn to_save_data(&mut self) -> Value { Value::Null }
+        fn from_save_data(_data: Value) -> EngineResult<ComponentWrapper> { Ok(Spawner::new(ObjectId::default())) }
+    }
+
+    impl TickBehavior for Spawner {
+        fn tick(&mut self, obj: &mut GameObject, _dt: Duration) {
+            let world = obj.world();
+            if self.spawned.is_none() {
+                let commands = world.commands();
+                let child = commands.spawn("child", vec![Transform::new()]);
+                commands.add_component(child, RenderComponent::new(Primitives::Cube(0.1, [1.0, 0.0, 0.0])));
+                commands.add_collider(child, Arc::new(Mutex::new(CubeCollider::new(0.1))));
+                commands.reparent(obj.id(), child);
+                commands.destroy(self.target);
+                self.spawned = Some(child);
+            }
+            self.seen_objects = world.len();
+        }
+    }
+
+    #[test]
+    fn test_commands_apply_at_the_end_of_the_tick() {
+        let mut engine = Engine::new_headless();
+        let world = engine.world();
+        let target = engine.add_object(make_base_game_object(&world, "target".to_string())).get_id();
+        let parent = engine.add_object(make_base_game_object(&world, "parent".to_string())).get();
+        parent.lock().unwrap().add_component(Spawner::new(target)).unwrap();
+
+        engine.tick();
+
+        let parent = parent.lock().unwrap();
+        let spawners = world.storage::<Spawner>().unwrap();
+        let (spawned, seen_objects) = {
+            let spawners = spawners.borrow();
+            let spawner = spawners.get(parent.id()).unwrap();
+            (spawner.spawned.unwrap(), spawner.seen_objects)
+        };
+
+        // Nothing changed while the tick was running
+        assert_eq!(seen_objects, 2);
+        assert!(engine.commands().is_empty());
+        assert!(!world.contains(target));
+        assert_eq!(parent.state.child_ids, vec![spawned]);
+        assert!(engine.state().objects().contains(&spawned));
+
+        let child = world.find_by_id(spawned).unwrap();
+        let child = child.lock().unwrap();
+        assert_eq!(child.state.parent_id, Some(parent.id()));
+        assert!(child.has_component::<RenderComponent>());
+        assert_eq!(child.colliders().len(), 1);
+    }
+
+    #[test]
+    fn test_cleared_spawns_give_their_ids_back() {
+        let world = World::new();
+        let id = world.commands().spawn("never", vec![]);
+        world.commands().clear();
+
+        assert!(world.commands().is_empty());
+        assert_eq!(world.next_id(), id);
+    }
+
+    #[test]
+    fn test_commands_on_missing_objects_are_skipped() {
+        let mut engine = Engine::new_headless();
+        let world = engine.world();
+        let obj = engine.add_object(make_base_game_object(&world, "obj".to_string())).get_id();
+        let gone = engine.add_object(make_base_game_object(&world, "gone".to_string())).get_id();
+        engine.destroy(gone);
+
+        engine.commands().add_component(gone, RenderComponent::new(Primitives::Cube(0.1, [1.0, 0.0, 0.0])));
+        engine.commands().reparent(gone, obj);
+        engine.commands().remove_component::<Transform>(obj);
+        engine.apply_commands();
+
+        let obj = world.find_by_id(obj).unwrap();
+        let obj = obj.lock().unwrap();
+        assert_eq!(obj.state.parent_id, None);
+        assert!(!obj.has_component::<Transform>());
+    }
+}
@@ -1,6 +1,7 @@
 use crate::engine::gameobject::GameObject;
 use crate::engine::graphics_backend::object::Object;
 use crate::engine::graphics_backend::vertex::Vertex;
+use crate::engine::input::InputMap;
 use crate::impl_save_load;
 use downcast_rs::impl_downcast;
 use downcast_rs::Downcast;
@@ -13,7 +14,12 @@ use serde::{Serialize, Deserialize};
 use super::bounds;
 use super::bounds::Bounds2D;
 use super::camera;
-use crate::engine::save::{ComponentSaveLoad};
+use crate::engine::error::EngineResult;
+use crate::engine::save::{self, ComponentSaveLoad};
+use crate::engine::storage::{ComponentStorage, Storage};
+use crate::engine::transform::Pose;
+use crate::engine::world::{ObjectId, World};
+pub use oxidized_macros::Component;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ComponentState {
@@ -50,6 +56,92 @@ where
         ComponentSaveLoad::to_save_data(self)
     }
     // fn tick_type(&mut self) -> &mut TickVariant;
+
+    /// Called when the component is attached to `obj`, before it is stored, so `obj`
+    /// doesn't have it yet.
+    fn on_add(&mut self, obj: &mut GameObject) {}
+    /// Called right before the component's first tick.
+    fn on_start(&mut self, obj: &mut GameObject) {}
+    /// Called when `obj.state.active` flips to `true`.
+    fn on_enable(&mut self, obj: &mut GameObject) {}
+    /// Called when `obj.state.active` flips to `false`.
+    fn on_disable(&mut self, obj: &mut GameObject) {}
+    /// Called when `obj` is destroyed, before the component is dropped.
+    fn on_destroy(&mut self, obj: &mut GameObject) {}
+    /// Called once when the engine shuts down, see
+    /// [`Engine::shutdown`](crate::engine::state::Engine::shutdown).
+    fn on_shutdown(&mut self, obj: &mut GameObject) {}
+
+    /// Components that get added first if the object doesn't have them yet.
+    fn requires() -> Vec<Requirement>
+    where
+        Self: Sized,
+    {
+        Vec::new()
+    }
+
+    /// Whether the component is saved with its object.
+    fn persistent(&self) -> bool {
+        true
+    }
+
+    /// Whether an object can hold more than one instance.
+    fn allow_multiple() -> bool
+    where
+        Self: Sized,
+    {
+        false
+    }
+}
+
+/// Lifecycle hooks for components deriving [`Component`] with `#[component(hooks)]`, see
+/// [`ComponentTrait`] for when they run.
+pub trait ComponentHooks {
+    fn on_add(&mut self, obj: &mut GameObject) {}
+    fn on_start(&mut self, obj: &mut GameObject) {}
+    fn on_enable(&mut self, obj: &mut GameObject) {}
+    fn on_disable(&mut self, obj: &mut GameObject) {}
+    fn on_destroy(&mut self, obj: &mut GameObject) {}
+    fn on_shutdown(&mut self, obj: &mut GameObject) {}
+}
+
+/// A component type another one depends on, and how to build a default instance.
+#[derive(Clone, Copy)]
+pub struct Requirement {
+    type_id: TypeId,
+    name: &'static str,
+    make: fn() -> ComponentWrapper,
+}
+
+impl Requirement {
+    pub fn new<T: ComponentTrait>(make: fn() -> ComponentWrapper) -> Self {
+        Self {
+            type_id: TypeId::of::<T>(),
+            name: any::type_name::<T>(),
+            make,
+        }
+    }
+
+    pub fn component_type(&self) -> TypeId {
+        self.type_id
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn make(&self) -> ComponentWrapper {
+        (self.make)()
+    }
+}
+
+/// Hooks the world and objects fire on every component of an object.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lifecycle {
+    Enable,
+    Disable,
+    Destroy,
+    Shutdown,
 }
 
 impl_downcast!(ComponentTrait);
@@ -65,6 +157,19 @@ pub struct InputData {
     pub keys_pressed: Vec<winit::event::VirtualKeyCode>,
     pub mouse_buttons_pressed: Vec<winit::event::MouseButton>,
     pub mouse_position: (f64, f64),
+    pub bindings: Arc<InputMap>,
+}
+
+impl InputData {
+    /// Whether any key or mouse button bound to `action` is held.
+    pub fn action(&self, action: &str) -> bool {
+        self.bindings.action(action, self)
+    }
+
+    /// Between -1 and 1, 0 for axes without bindings.
+    pub fn axis(&self, axis: &str) -> f32 {
+        self.bindings.axis(axis, self)
+    }
 }
 
 // #[derive(Clone)]
@@ -104,13 +209,14 @@ pub trait RenderTickBehavior: Send + Sync {
 //     }
 // }
 
-pub enum TickVariant {
-    Input(Arc<Mutex<dyn InputTickBehavior>>),
-    Render(Arc<Mutex<dyn RenderTickBehavior>>),
-    Default(Arc<Mutex<dyn TickBehavior>>),
+/// How the components of one type are ticked, picked by the component's constructor.
+pub enum TickVariant<T> {
+    Input(fn(&mut T, &InputData, &mut GameObject, Duration)),
+    Render(fn(&mut T, &mut GameObject, Duration, camera::Camera) -> RenderOutput),
+    Default(fn(&mut T, &mut GameObject, Duration)),
 }
 
-impl TickVariant {
+impl<T> TickVariant<T> {
     pub fn component_type(&self) -> ComponentType {
         match self {
             TickVariant::Input(_) => ComponentType::Input,
@@ -119,122 +225,92 @@ impl TickVariant {
         }
     }
 
-    // pub fn _underlying(&self) -> Arc<Mutex<&dyn ComponentTrait>> {
-    //     match self {
-    //         TickVariant::Input(ref behavior) => behavior.underlying::<dyn ComponentTrait>(),
-    //         TickVariant::Render(ref behavior) => Arc::clone(behavior),
-    //         TickVariant::Default(ref behavior) => Arc::clone(behavior),
-    //     }
-    // }
-
     pub fn tick(
-        &mut self,
+        &self,
+        component: &mut T,
         input: Option<&InputData>,
         obj: &mut GameObject,
         dt: Duration,
         cam: camera::Camera
     ) -> Option<RenderOutput> {
         match self {
-            TickVariant::Input(behavior) => {
+            TickVariant::Input(tick) => {
                 if let Some(input) = input {
-                    behavior.try_lock().unwrap().tick_with_input(input, obj, dt);
+                    tick(component, input, obj, dt);
                 }
                 None
             }
-            TickVariant::Render(behavior) => Some(behavior.lock().unwrap().render_tick(obj, dt, cam)),
-            TickVariant::Default(behavior) => {
-                behavior.lock().unwrap().tick(obj, dt);
+            TickVariant::Render(tick) => Some(tick(component, obj, dt, cam)),
+            TickVariant::Default(tick) => {
+                tick(component, obj, dt);
                 None
             }
         }
     }
 }
 
-#[derive(Clone)]
+/// A component that hasn't been added to an object yet. Adding it moves the component
+/// into the world's storage for its type.
 pub struct ComponentWrapper {
-    pub component: Arc<Mutex<dyn ComponentTrait>>,
-    pub ticker: Arc<Mutex<TickVariant>>,
+    name: String,
+    type_id: TypeId,
+    requires: Vec<Requirement>,
+    allow_multiple: bool,
+    insert: Box<dyn FnOnce(&mut GameObject) -> Arc<dyn ComponentStorage> + Send + Sync>,
 }
 
 impl ComponentWrapper {
-    pub fn new(component: Arc<Mutex<dyn ComponentTrait>>, ticker: Arc<Mutex<TickVariant>>) -> Self {
-        Self { component, ticker }
+    pub fn new<T: ComponentTrait>(component: T, ticker: TickVariant<T>) -> Self {
+        Self {
+            name: component.name().to_string(),
+            type_id: TypeId::of::<T>(),
+            requires: T::requires(),
+            allow_multiple: T::allow_multiple(),
+            insert: Box::new(move |obj: &mut GameObject| -> Arc<dyn ComponentStorage> {
+                let mut component = component;
+                component.on_add(obj);
+
+                let storage = obj.world().storage_or_insert_with(|| Storage::new(ticker));
+                storage.insert(obj.id, component);
+                storage
+            }),
+        }
     }
 
-    pub fn tick(
-        &self,
-        input: Option<&InputData>,
-        obj: &mut GameObject,
-        dt: Duration,
-        cam: camera::Camera
-    ) -> Option<RenderOutput> {
-        let mut ticker = self.ticker.lock().unwrap();
-        ticker.tick(input, obj, dt, cam)
+    pub fn name(&self) -> &str {
+        &self.name
     }
-}
 
-pub fn create_component_wrapper(
-    component: Arc<Mutex<dyn ComponentTrait>>,
-    tick_variant: Arc<Mutex<TickVariant>>,
-) -> Arc<Mutex<ComponentWrapper>> {
-    Arc::new(Mutex::new(ComponentWrapper::new(component, tick_variant)))
-}
+    pub fn component_type(&self) -> TypeId {
+        self.type_id
+    }
 
-// pub struct LambdaComponent<F>
-// where
-//     F: FnMut(),
-// {
-//     pub name: String,
-//     pub state: ComponentState,
-//     pub tick_behavior: F,
-// }
-//
-// impl<F> LambdaComponent<F>
-// where
-//     F: FnMut() + Send + Sync + 'static,
-// {
-//     pub fn new(name: String, tick_behavior: F) -> Arc<Mutex<ComponentWrapper>> {
-//         let lambda_component = Arc::new(Mutex::new(Self {
-//             name,
-//             tick_behavior,
-//             state: ComponentState::new(),
-//         }));
-//         let tick_variant = Arc::new(Mutex::new(TickVariant::Default(lambda_component.clone())));
-//
-//         Arc::new(Mutex::new(ComponentWrapper {
-//             component: lambda_component as Arc<Mutex<dyn ComponentTrait>>,
-//             ticker: tick_variant,
-//         }))
-//     }
-// }
-//
-// impl<F> ComponentTrait for LambdaComponent<F>
-// where
-//     F: FnMut() + Send + Sync + 'static,
-// {
-//     fn name(&self) -> &str {
-//         &self.name
-//     }
-//
-//     fn state(&mut self) -> &mut ComponentState {
-//         &mut self.state
-//     }
-// }
-//
-// impl<F> TickBehavior for LambdaComponent<F>
-// where
-//     F: FnMut() + Send + Sync + 'static,
-// {
-//     fn tick(&mut self, obj: &mut GameObject, dt: Duration) {
-//         (self.tick_behavior)();
-//     }
-// }
+    pub fn requires(&self) -> &[Requirement] {
+        &self.requires
+    }
+
+    pub fn allow_multiple(&self) -> bool {
+        self.allow_multiple
+    }
 
+    /// Runs [`ComponentTrait::on_add`] and moves the component into its storage.
+    pub(crate) fn insert(self, obj: &mut GameObject) -> Arc<dyn ComponentStorage> {
+        (self.insert)(obj)
+    }
+}
+
+/// Position, rotation and scale relative to the parent object, or to the world for
+/// root objects. The world pose is cached from the parent chain every tick.
 #[derive(Clone)]
 pub struct Transform {
     pub state: ComponentState,
     pub pos: [f32; 3],
     pub rot: [f32; 3],
+    pub scale: [f32; 3],
+    // World pose of the parent as of the last propagation
+    parent_world: Pose,
+    // World pose at the start of the current simulation step
+    previous: Option<Pose>,
 }
 
 impl ComponentTrait for Transform {
@@ -251,19 +327,121 @@ impl TickBehavior for Transform {
     fn tick(&mut self, obj: &mut GameObject, dt: Duration) {}
 }
 
-impl Transform {
-    pub fn new() -> Arc<Mutex<ComponentWrapper>> {
-        let transform = Arc::new(Mutex::new(Self {
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
             state: ComponentState::new(),
             pos: [0.0, 0.0, 0.0],
             rot: [0.0, 0.0, 0.0],
-        }));
-        let tick_variant = Arc::new(Mutex::new(TickVariant::Default(transform.clone())));
+            scale: [1.0, 1.0, 1.0],
+            parent_world: Pose::IDENTITY,
+            previous: None,
+        }
+    }
+}
+
+impl Transform {
+    pub fn new() -> ComponentWrapper {
+        ComponentWrapper::new(Self::default(), TickVariant::Default(Self::tick))
+    }
+
+    pub fn local(&self) -> Pose {
+        Pose {
+            pos: self.pos,
+            rot: self.rot,
+            scale: self.scale,
+        }
+    }
+
+    /// Pose in world space. Changes to this transform show up straight away, changes
+    /// to its parents once transforms are propagated at the start of the next tick.
+    pub fn world(&self) -> Pose {
+        self.parent_world.compose(&self.local())
+    }
+
+    pub fn parent_world(&self) -> Pose {
+        self.parent_world
+    }
+
+    pub(crate) fn set_parent_world(&mut self, parent_world: Pose) {
+        self.parent_world = parent_world;
+    }
+
+    /// World pose blended between the start and the end of the last simulation step,
+    /// `alpha` is usually [`World::interpolation`](crate::engine::world::World::interpolation).
+    pub fn interpolated(&self, alpha: f32) -> Pose {
+        match self.previous {
+            Some(previous) => previous.lerp(&self.world(), alpha),
+            None => self.world(),
+        }
+    }
+
+    pub(crate) fn store_previous(&mut self) {
+        self.previous = Some(self.world());
+    }
+
+    /// Skips interpolation until the next step, so a teleport doesn't render as a
+    /// quick slide.
+    pub fn reset_interpolation(&mut self) {
+        self.previous = None;
+    }
 
-        Arc::new(Mutex::new(ComponentWrapper {
-            component: transform as Arc<Mutex<dyn ComponentTrait>>,
-            ticker: tick_variant,
-        }))
+    pub fn world_pos(&self) -> [f32; 3] {
+        self.world().pos
+    }
+
+    /// Moves the object so it ends up at `pos` in world space.
+    pub fn set_world_pos(&mut self, pos: [f32; 3]) {
+        self.pos = self.parent_world.inverse_transform_point(pos);
+    }
+
+    /// Maps a point relative to this object into world space.
+    pub fn local_to_world(&self, point: [f32; 3]) -> [f32; 3] {
+        self.world().transform_point(point)
+    }
+
+    /// Maps a world space point into this object's space.
+    pub fn world_to_local(&self, point: [f32; 3]) -> [f32; 3] {
+        self.world().inverse_transform_point(point)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TransformSaveData {
+    pos: [f32; 3],
+    rot: [f32; 3],
+    // Saves from before transforms had a scale don't have one
+    #[serde(default = "unit_scale")]
+    scale: [f32; 3],
+    state: ComponentState,
+}
+
+fn unit_scale() -> [f32; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+impl ComponentSaveLoad for Transform {
+    fn to_save_data(&mut self) -> Value {
+        let save_data = TransformSaveData {
+            pos: self.pos,
+            rot: self.rot,
+            scale: self.scale,
+            state: self.state.clone(),
+        };
+        serde_json::to_value(save_data).unwrap()
+    }
+
+    fn from_save_data(data: Value) -> EngineResult<ComponentWrapper> {
+        let save_data: TransformSaveData = save::parse_save_data("Transform", data)?;
+        let transform = Self {
+            state: save_data.state,
+            pos: save_data.pos,
+            rot: save_data.rot,
+            scale: save_data.scale,
+            parent_world: Pose::IDENTITY,
+            previous: None,
+        };
+        Ok(ComponentWrapper::new(transform, TickVariant::Default(Self::tick)))
     }
 }
 
@@ -282,20 +460,11 @@ impl InputTickBehavior for CharacterController2D {
         obj.get_component_closure::<Transform>(|transform| {
             let mut new = transform.pos;
             let mut new_rot = transform.rot;
-            // let dt_conv = (dt.as_millis() as f32) / 100.0;
-            let dt_conv = 1.0;
-            for key in input.keys_pressed.iter() {
-                match key {
-                    winit::event::VirtualKeyCode::W => new[1] += self.moveamt / dt_conv,
-                    winit::event::VirtualKeyCode::S => new[1] -= self.moveamt / dt_conv,
-                    winit::event::VirtualKeyCode::A => new[0] -= self.moveamt / dt_conv,
-                    winit::event::VirtualKeyCode::D => new[0] += self.moveamt / dt_conv,
-
-                    winit::event::VirtualKeyCode::Left => new_rot[2] += self.rotamt / dt_conv,
-                    winit::event::VirtualKeyCode::Right => new_rot[2] -= self.rotamt / dt_conv,
-                    _ => {}
-                }
-            }
+            // `moveamt` and `rotamt` are per 1/60th of a second
+            let dt_conv = dt.as_secs_f32() * 60.0;
+            new[0] += input.axis("move_x") * self.moveamt * dt_conv;
+            new[1] += input.axis("move_y") * self.moveamt * dt_conv;
+            new_rot[2] -= input.axis("rotate") * self.rotamt * dt_conv;
 
             if (bound.is_some()) {
                 let clipped = bound.as_mut().expect("no bounds").clip(new[0], new[1]);
@@ -327,22 +496,22 @@ impl ComponentTrait for CharacterController2D {
     fn state(&mut self) -> &mut ComponentState {
         &mut self.state
     }
+
+    fn requires() -> Vec<Requirement> {
+        vec![Requirement::new::<Transform>(Transform::new)]
+    }
 }
 
 impl CharacterController2D {
-    pub fn new(bounds: Option<Bounds2D>) -> Arc<Mutex<ComponentWrapper>> {
-        let controller = Arc::new(Mutex::new(Self {
+    pub fn new(bounds: Option<Bounds2D>) -> ComponentWrapper {
+        let controller = Self {
             moveamt: 0.01,
             rotamt: 2.0 /*0.01*/,
             state: ComponentState::new(),
             bounds
-        }));
-        let tick_variant = Arc::new(Mutex::new(TickVariant::Input(controller.clone())));
+        };
 
-        Arc::new(Mutex::new(ComponentWrapper {
-            component: controller as Arc<Mutex<dyn ComponentTrait>>,
-            ticker: tick_variant,
-        }))
+        ComponentWrapper::new(controller, TickVariant::Input(Self::tick_with_input))
     }
 }
 
@@ -384,3 +553,66 @@ pub struct Rigidbody {
 //         }))
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::error::EngineError;
+    use crate::engine::gameobject::GameObjectState;
+    use crate::engine::save::ComponentSaveData;
+    use crate::engine::state::Engine;
+    use serde_json::json;
+
+    #[derive(Component)]
+    #[component(requires(Transform), allow_multiple, hooks)]
+    struct Mover {
+        state: ComponentState,
+        speed: f32,
+        #[save(skip)]
+        ticks: u32,
+    }
+
+    impl TickBehavior for Mover {
+        fn tick(&mut self, obj: &mut GameObject, dt: Duration) {
+            self.ticks += 1;
+            let speed = self.speed;
+            obj.get_component_closure::<Transform>(|transform| transform.pos[0] += speed);
+        }
+    }
+
+    impl ComponentHooks for Mover {
+        fn on_start(&mut self, obj: &mut GameObject) {
+            self.speed *= 2.0;
+        }
+    }
+
+    #[test]
+    fn test_derived_components_tick_save_and_load() {
+        let mut engine = Engine::new_headless();
+        let obj = engine
+            .add_object(GameObject::new(&engine.world(), "mover".to_string(), vec![], GameObjectState::new(true, None, vec![])))
+            .get();
+        obj.lock().unwrap().add_component(Mover::new(1.0)).unwrap();
+        obj.lock().unwrap().add_component(Mover::new(0.5)).unwrap();
+        assert!(obj.lock().unwrap().has_component::<Transform>());
+
+        engine.tick();
+        let mut x = 0.0;
+        obj.lock().unwrap().get_component_closure::<Transform>(|transform| x = transform.pos[0]);
+        assert_eq!(x, 3.0);
+
+        let saved = ComponentSaveData::from_game_object(&obj.lock().unwrap());
+        assert!(saved.iter().any(|comp| comp.id == "Mover" && comp.data == json!({ "state": { "_state": null }, "speed": 2.0 })));
+
+        // Registered by the derive, without save::init()
+        let loaded = ComponentSaveData { id: "Mover".to_string(), data: json!({ "state": { "_state": null }, "speed": 4.0 }) };
+        let world = engine.world();
+        let restored = GameObject::new(&world, "restored".to_string(), vec![loaded.to_component().unwrap()], GameObjectState::new(true, None, vec![]));
+        let mut speed = 0.0;
+        restored.lock().unwrap().get_component_closure::<Mover>(|mover| speed = mover.speed);
+        assert_eq!(speed, 4.0);
+
+        let broken = ComponentSaveData { id: "Mover".to_string(), data: json!({ "state": { "_state": null }, "speed": "fast" }) };
+        assert!(matches!(broken.to_component(), Err(EngineError::InvalidSave(reason)) if reason.starts_with("speed of Mover: ")));
+    }
+}
@@ -1,7 +1,7 @@
 use crate::engine::{component, camera};
 use crate::engine::component::{
     ComponentState, ComponentTrait, ComponentWrapper, InputData, InputTickBehavior, RenderOutput,
-    RenderTickBehavior, TickBehavior, TickVariant,
+    RenderTickBehavior, Requirement, TickBehavior, TickVariant, Transform,
 };
 use crate::engine::gameobject::GameObject;
 use crate::engine::graphics_backend::color::Colors;
@@ -32,6 +32,10 @@ impl ComponentTrait for RenderComponent {
     fn state(&mut self) -> &mut ComponentState {
         &mut self.state
     }
+
+    fn requires() -> Vec<Requirement> {
+        vec![Requirement::new::<Transform>(Transform::new)]
+    }
 }
 
 impl RenderTickBehavior for RenderComponent {
@@ -55,12 +59,16 @@ impl RenderTickBehavior for RenderComponent {
         let mut pos: Option<[f32; 3]> = None;
         let mut rot: Option<[f32; 3]> = None;
 
+        let alpha = obj.world().interpolation();
         obj.get_component_closure::<component::Transform>(|comp| {
-            out.obj.as_mut().expect("").move_vertexes(comp.pos.clone());
-            pos = Some(comp.pos.clone());
+            let world = comp.interpolated(alpha);
+
+            out.obj.as_mut().expect("").scale_vertexes_arr(world.scale);
+            out.obj.as_mut().expect("").move_vertexes(world.pos);
+            pos = Some(world.pos);
 
-            out.obj.as_mut().expect("").rotate_vertexes_arr(comp.rot.clone(), cam.position.into());
-            rot = Some(comp.rot.clone());
+            out.obj.as_mut().expect("").rotate_vertexes_arr(world.rot, cam.position.into());
+            rot = Some(world.rot);
         });
 
         // println!("{} performed a render tick on {} with transform position: {:?} and rotation: {:?}", self.name, obj.name(), pos, rot);
@@ -70,15 +78,14 @@ impl RenderTickBehavior for RenderComponent {
 }
 
 impl RenderComponent {
-    pub fn new(obj: Primitives) -> Arc<Mutex<ComponentWrapper>> {
-        let component = Arc::new(Mutex::new(Self {
+    pub fn new(obj: Primitives) -> ComponentWrapper {
+        let component = Self {
             name: "RenderComponent".to_string(),
             state: ComponentState::new(),
             obj,
-        }));
-        let tick_variant = Arc::new(Mutex::new(TickVariant::Render(component.clone())));
+        };
 
-        Arc::new(Mutex::new(ComponentWrapper::new(component, tick_variant)))
+        ComponentWrapper::new(component, TickVariant::Render(Self::render_tick))
     }
 }
 
@@ -103,13 +110,12 @@ impl InputTickBehavior for InputComponent {
 }
 
 impl InputComponent {
-    pub fn new(name: String) -> Arc<Mutex<ComponentWrapper>> {
-        let component = Arc::new(Mutex::new(Self {
+    pub fn new(name: String) -> ComponentWrapper {
+        let component = Self {
             name,
             state: ComponentState::new(),
-        }));
-        let tick_variant = Arc::new(Mutex::new(TickVariant::Input(component.clone())));
+        };
 
-        Arc::new(Mutex::new(ComponentWrapper::new(component, tick_variant)))
+        ComponentWrapper::new(component, TickVariant::Input(Self::tick_with_input))
     }
 }
@@ -0,0 +1,79 @@
+use crate::engine::world::ObjectId;
+use std::fmt;
+
+/// What can go wrong in the engine's fallible APIs, e.g. loading a save or reaching
+/// an object through a stale id.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EngineError {
+    /// No live object with this id.
+    MissingObject(ObjectId),
+    /// The object is locked, usually because it's the one currently ticking.
+    ObjectLocked(ObjectId),
+    /// The object already has one and the type doesn't allow multiple instances.
+    DuplicateComponent { object: String, component: String },
+    /// The component requires one of the components that (indirectly) require it.
+    RequirementCycle { object: String, component: String },
+    /// The new parent is the child itself or one of its descendants.
+    ParentCycle { parent: ObjectId, child: ObjectId },
+    /// No component registered under this name.
+    UnknownComponent(String),
+    /// No static component registered under this name.
+    UnknownStaticComponent(String),
+    /// A collider type saves don't know about.
+    UnknownCollider,
+    /// No prefab registered under this name.
+    UnknownPrefab(String),
+    /// The save data couldn't be parsed.
+    InvalidSave(String),
+    /// No scene registered under this name.
+    UnknownScene(String),
+    /// The scene isn't loaded.
+    SceneNotLoaded(String),
+    /// No pool created for this prefab.
+    UnknownPool(String),
+    /// The object isn't handed out by a pool.
+    NotPooled(ObjectId),
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::MissingObject(id) => write!(f, "No object with id {}", id),
+            EngineError::ObjectLocked(id) => write!(f, "GameObject {} is already locked", id),
+            EngineError::DuplicateComponent { object, component } => write!(
+                f,
+                "GameObject {} already has a {} and it doesn't allow multiple instances",
+                object, component
+            ),
+            EngineError::RequirementCycle { object, component } => write!(
+                f,
+                "{} on GameObject {} requires a component that requires it",
+                component, object
+            ),
+            EngineError::ParentCycle { parent, child } => write!(
+                f,
+                "GameObject {} can't be a child of {}, that is itself or one of its descendants",
+                child, parent
+            ),
+            EngineError::UnknownComponent(name) => write!(f, "Unknown component type: {}", name),
+            EngineError::UnknownStaticComponent(name) => write!(f, "Unknown static component type: {}", name),
+            EngineError::UnknownCollider => write!(f, "Unknown collider type"),
+            EngineError::UnknownPrefab(name) => write!(f, "No prefab called {}", name),
+            EngineError::InvalidSave(reason) => write!(f, "Invalid save data: {}", reason),
+            EngineError::UnknownScene(name) => write!(f, "No scene called {}", name),
+            EngineError::SceneNotLoaded(name) => write!(f, "Scene {} isn't loaded", name),
+            EngineError::UnknownPool(name) => write!(f, "No pool for prefab {}", name),
+            EngineError::NotPooled(id) => write!(f, "GameObject {} isn't handed out by a pool", id),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+impl From<serde_json::Error> for EngineError {
+    fn from(err: serde_json::Error) -> Self {
+        EngineError::InvalidSave(err.to_string())
+    }
+}
+
+pub type EngineResult<T> = Result<T, EngineError>;
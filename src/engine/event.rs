@@ -0,0 +1,301 @@
+use crate::engine::component::ComponentTrait;
+use crate::engine::static_component::StaticComponent;
+use crate::engine::world::{ObjectId, World};
+use log::warn;
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+
+/// Anything that can be sent over an [`EventBus`], e.g. `struct EnemyKilled { by: ObjectId }`.
+pub trait Event: Any + Send + Sync {}
+
+impl<T: Any + Send + Sync> Event for T {}
+
+/// When a subscriber sees an event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Delivery {
+    /// Inside [`EventBus::send`]. If the subscriber is busy (its component is being
+    /// ticked, or its static component is locked) the event waits for the end of the tick.
+    Immediate,
+    /// When the engine flushes the bus at the end of the tick.
+    EndOfTick,
+}
+
+/// Handle returned by the `subscribe` functions, pass it to [`EventBus::unsubscribe`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+enum Handled {
+    Done,
+    Busy,
+    /// The subscriber is gone, drop the subscription.
+    Gone,
+}
+
+type Handler = dyn Fn(&(dyn Any + Send + Sync)) -> Handled + Send + Sync;
+
+struct Subscriber {
+    id: SubscriptionId,
+    delivery: Delivery,
+    handler: Arc<Handler>,
+}
+
+struct Queued {
+    subscription: SubscriptionId,
+    handler: Arc<Handler>,
+    event: Arc<dyn Any + Send + Sync>,
+}
+
+/// Typed publish/subscribe between components, static components and plain closures.
+/// Every [`World`] has one, reachable through [`Engine::events`](crate::engine::state::Engine::events)
+/// or [`GameObject::send`](crate::engine::gameobject::GameObject::send).
+pub struct EventBus {
+    subscribers: RwLock<HashMap<TypeId, Vec<Subscriber>>>,
+    queue: Mutex<Vec<Queued>>,
+    next_id: AtomicU64,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self {
+            subscribers: RwLock::new(HashMap::new()),
+            queue: Mutex::new(Vec::new()),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    fn add<E: Event>(&self, delivery: Delivery, handler: Arc<Handler>) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        self.subscribers
+            .write()
+            .unwrap()
+            .entry(TypeId::of::<E>())
+            .or_default()
+            .push(Subscriber { id, delivery, handler });
+        id
+    }
+
+    /// Calls `f` for every `E`.
+    pub fn subscribe<E: Event>(
+        &self,
+        delivery: Delivery,
+        f: impl Fn(&E) + Send + Sync + 'static,
+    ) -> SubscriptionId {
+        self.add::<E>(
+            delivery,
+            Arc::new(move |event| {
+                f(event.downcast_ref::<E>().unwrap());
+                Handled::Done
+            }),
+        )
+    }
+
+    /// Calls `f` with the `T` of object `id` for every `E`. The subscription ends when
+    /// the object loses its `T` or is destroyed.
+    pub fn subscribe_component<E: Event, T: ComponentTrait>(
+        &self,
+        world: &Arc<World>,
+        id: ObjectId,
+        delivery: Delivery,
+        f: impl Fn(&mut T, &E) + Send + Sync + 'static,
+    ) -> SubscriptionId {
+        let world: Weak<World> = Arc::downgrade(world);
+        self.add::<E>(
+            delivery,
+            Arc::new(move |event| {
+                let Some(storage) = world.upgrade().and_then(|world| world.storage::<T>()) else {
+                    return Handled::Gone;
+                };
+                let Some(mut set) = storage.try_borrow_mut() else {
+                    return Handled::Busy;
+                };
+                match set.get_mut(id) {
+                    Some(component) => {
+                        f(component, event.downcast_ref::<E>().unwrap());
+                        Handled::Done
+                    }
+                    None => Handled::Gone,
+                }
+            }),
+        )
+    }
+
+    /// Calls `f` with the static component for every `E`, e.g. with the `data` of a
+    /// [`Link`](crate::engine::save::Link).
+    pub fn subscribe_static<E: Event, S: StaticComponent>(
+        &self,
+        component: Arc<Mutex<S>>,
+        delivery: Delivery,
+        f: impl Fn(&mut S, &E) + Send + Sync + 'static,
+    ) -> SubscriptionId {
+        let component = Arc::downgrade(&component);
+        self.add::<E>(
+            delivery,
+            Arc::new(move |event| {
+                let Some(component) = component.upgrade() else {
+                    return Handled::Gone;
+                };
+                let Ok(mut component) = component.try_lock() else {
+                    return Handled::Busy;
+                };
+                f(&mut component, event.downcast_ref::<E>().unwrap());
+                Handled::Done
+            }),
+        )
+    }
+
+    pub fn unsubscribe(&self, subscription: SubscriptionId) {
+        for subscribers in self.subscribers.write().unwrap().values_mut() {
+            subscribers.retain(|subscriber| subscriber.id != subscription);
+        }
+        self.queue.lock().unwrap().retain(|queued| queued.subscription != subscription);
+    }
+
+    pub fn subscriber_count<E: Event>(&self) -> usize {
+        self.subscribers
+            .read()
+            .unwrap()
+            .get(&TypeId::of::<E>())
+            .map_or(0, |subscribers| subscribers.len())
+    }
+
+    /// Delivers `event` to every subscriber of `E`, right away or at the end of the
+    /// tick depending on how they subscribed.
+    pub fn send<E: Event>(&self, event: E) {
+        // Handlers may subscribe or send themselves, so don't hold the lock while calling them
+        let subscribers: Vec<(SubscriptionId, Delivery, Arc<Handler>)> = match self.subscribers.read().unwrap().get(&TypeId::of::<E>()) {
+            Some(subscribers) => subscribers
+                .iter()
+                .map(|subscriber| (subscriber.id, subscriber.delivery, subscriber.handler.clone()))
+                .collect(),
+            None => return,
+        };
+
+        let event: Arc<dyn Any + Send + Sync> = Arc::new(event);
+        for (subscription, delivery, handler) in subscribers {
+            let handled = match delivery {
+                Delivery::Immediate => handler(&*event),
+                Delivery::EndOfTick => Handled::Busy,
+            };
+
+            match handled {
+                Handled::Done => {}
+                Handled::Busy => self.queue.lock().unwrap().push(Queued {
+                    subscription,
+                    handler,
+                    event: event.clone(),
+                }),
+                Handled::Gone => self.unsubscribe(subscription),
+            }
+        }
+    }
+
+    /// Delivers everything queued since the last flush. Events sent while flushing are
+    /// delivered by the next one.
+    pub fn flush(&self) {
+        let queued = std::mem::take(&mut *self.queue.lock().unwrap());
+
+        let mut retry = Vec::new();
+        for queued in queued {
+            match (queued.handler)(&*queued.event) {
+                Handled::Done => {}
+                Handled::Busy => {
+                    warn!("Event subscriber is busy, retrying next tick");
+                    retry.push(queued);
+                }
+                Handled::Gone => self.unsubscribe(queued.subscription),
+            }
+        }
+
+        if !retry.is_empty() {
+            let mut queue = self.queue.lock().unwrap();
+            retry.append(&mut queue);
+            *queue = retry;
+        }
+    }
+
+    pub fn pending(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::component::Transform;
+    use crate::engine::gameobject::make_base_game_object;
+
+    struct Hit {
+        damage: u32,
+    }
+
+    #[test]
+    fn test_immediate_and_buffered_delivery() {
+        let bus = EventBus::new();
+        let immediate = Arc::new(Mutex::new(0));
+        let buffered = Arc::new(Mutex::new(0));
+
+        let total = immediate.clone();
+        bus.subscribe::<Hit>(Delivery::Immediate, move |hit| *total.lock().unwrap() += hit.damage);
+        let total = buffered.clone();
+        bus.subscribe::<Hit>(Delivery::EndOfTick, move |hit| *total.lock().unwrap() += hit.damage);
+
+        bus.send(Hit { damage: 3 });
+        bus.send(5u32);
+        assert_eq!(*immediate.lock().unwrap(), 3);
+        assert_eq!(*buffered.lock().unwrap(), 0);
+
+        bus.flush();
+        assert_eq!(*buffered.lock().unwrap(), 3);
+        assert_eq!(bus.pending(), 0);
+    }
+
+    #[test]
+    fn test_component_subscribers_wait_while_borrowed_and_end_with_object() {
+        let world = World::new();
+        let obj = make_base_game_object(&world, "target".to_string());
+        let id = obj.lock().unwrap().id();
+
+        world.events().subscribe_component::<Hit, Transform>(&world, id, Delivery::Immediate, |transform, hit| {
+            transform.pos[0] += hit.damage as f32;
+        });
+
+        // Sent while the object is ticking its transform
+        let storage = world.storage::<Transform>().unwrap();
+        let guard = storage.borrow_mut();
+        world.events().send(Hit { damage: 2 });
+        drop(guard);
+        assert_eq!(world.events().pending(), 1);
+
+        world.events().flush();
+        let mut x = 0.0;
+        obj.lock().unwrap().get_component_closure::<Transform>(|transform| x = transform.pos[0]);
+        assert_eq!(x, 2.0);
+
+        world.destroy(id);
+        world.flush_destroyed();
+        world.events().send(Hit { damage: 2 });
+        assert_eq!(world.events().subscriber_count::<Hit>(), 0);
+    }
+
+    #[test]
+    fn test_unsubscribe_drops_queued_events() {
+        let bus = EventBus::new();
+        let seen = Arc::new(Mutex::new(0));
+
+        let count = seen.clone();
+        let subscription = bus.subscribe::<Hit>(Delivery::EndOfTick, move |_| *count.lock().unwrap() += 1);
+        bus.send(Hit { damage: 1 });
+        bus.unsubscribe(subscription);
+        bus.flush();
+
+        assert_eq!(*seen.lock().unwrap(), 0);
+    }
+}
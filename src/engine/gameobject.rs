@@ -1,57 +1,36 @@
 use crate::engine::collider;
 use crate::engine::collider::Collider;
+use crate::engine::error::{EngineError, EngineResult};
 use crate::engine::component;
-use crate::engine::component::{ComponentTrait, TickBehavior, Transform};
+use crate::engine::component::{ComponentTrait, ComponentType, Lifecycle, TickBehavior, Transform};
+use crate::engine::event::{Delivery, Event, SubscriptionId};
 use crate::engine::state::Engine;
+use crate::engine::storage::{ComponentStorage, Storage};
+use crate::engine::task::{TaskContext, TaskHandle};
+use crate::engine::world::{ObjectId, World};
 use downcast_rs::Downcast;
-use lazy_static::lazy_static;
 use rocket::form::validate::Contains;
-use std::any::Any;
+use std::any::{type_name, Any};
 use std::any::TypeId;
-use std::collections::HashMap;
-use std::process::exit;
-use std::sync::{Arc, Mutex};
+use std::collections::{BTreeSet, HashMap};
+use std::future::Future;
+use std::sync::{Arc, Mutex, Weak};
 use std::time::Duration;
+use log::{error, info, warn};
 
 use super::collider::Point;
 
 pub type MutexdGameObject = Arc<Mutex<GameObject>>;
 
-lazy_static! {
-    pub static ref GAME_OBJECT_REGISTRY: Mutex<HashMap<i32, Arc<Mutex<GameObject>>>> =
-        Mutex::new(HashMap::new());
-    static ref GAME_OBJECT_COUNT: Mutex<Count> = Mutex::new(Count::new());
-    pub static ref GAME_OBJECT_DESTROYED: Mutex<Vec<i32>> = Mutex::new(Vec::new());
-}
-
-#[derive(Clone)]
-struct Count {
-    internal: i32,
-}
-
-impl Count {
-    pub fn new() -> Self {
-        Self { internal: 0 }
-    }
-
-    pub fn increment(&mut self) {
-        self.internal += 1;
-    }
-
-    pub fn get(&mut self) -> i32 {
-        self.internal
-    }
-}
-
 #[derive(Clone)]
 pub struct GameObjectState {
     pub active: bool,
-    pub parent_id: Option<i32>,
-    pub child_ids: Vec<i32>,
+    pub parent_id: Option<ObjectId>,
+    pub child_ids: Vec<ObjectId>,
 }
 
 impl GameObjectState {
-    pub fn new(active: bool, parent_id: Option<i32>, child_ids: Vec<i32>) -> Self {
+    pub fn new(active: bool, parent_id: Option<ObjectId>, child_ids: Vec<ObjectId>) -> Self {
         Self {
             active,
             parent_id,
@@ -59,18 +38,18 @@ impl GameObjectState {
         }
     }
 
-    pub fn parent(&self) -> Option<Arc<Mutex<GameObject>>> {
-        self.parent_id.and_then(GameObject::find_by_id)
+    pub fn parent(&self, world: &World) -> Option<Arc<Mutex<GameObject>>> {
+        self.parent_id.and_then(|id| world.find_by_id(id))
     }
 
-    pub fn children(&self) -> Vec<Arc<Mutex<GameObject>>> {
+    pub fn children(&self, world: &World) -> Vec<Arc<Mutex<GameObject>>> {
         self.child_ids
             .iter()
-            .filter_map(|&id| GameObject::find_by_id(id))
+            .filter_map(|&id| world.find_by_id(id))
             .collect()
     }
 
-    pub fn child_ids(&self) -> Vec<i32> {
+    pub fn child_ids(&self) -> Vec<ObjectId> {
         self.child_ids.clone()
     }
 
@@ -78,66 +57,79 @@ impl GameObjectState {
         self.active
     }
 
-    pub fn add_child(&mut self, child_id: i32) {
+    pub fn add_child(&mut self, child_id: ObjectId) {
         if !self.child_ids.contains(&child_id) {
             self.child_ids.push(child_id);
         }
     }
 
-    pub fn set_parent(&mut self, parent_id: i32) {
+    pub fn set_parent(&mut self, parent_id: ObjectId) {
         self.parent_id = Some(parent_id);
     }
 }
 
 #[derive(Clone)]
 pub struct GameObject {
-    pub name: String,
-    pub id: i32,
-    pub components: Vec<Arc<Mutex<component::ComponentWrapper>>>,
+    name: String,
+    pub id: ObjectId,
+    tags: BTreeSet<String>,
+    layer: u32,
+    storages: Vec<Arc<dyn ComponentStorage>>,
     pub colliders: Vec<Arc<Mutex<Box<dyn Collider>>>>,
     pub state: GameObjectState,
     pub render_references: Vec<usize>,
+    world: Weak<World>,
+    // `state.active` as of the last enable/disable hooks
+    was_active: bool,
 }
 
 impl GameObject {
-    pub unsafe fn new(
+    /// Components that can't be added, e.g. duplicates, are left out with an error in
+    /// the log. Add them with [`GameObject::add_components`] to handle the errors.
+    pub fn new(
+        world: &Arc<World>,
         name: String,
-        components: Vec<Arc<Mutex<component::ComponentWrapper>>>,
+        components: Vec<component::ComponentWrapper>,
         state: GameObjectState,
     ) -> Arc<Mutex<Self>> {
-        let id = GAME_OBJECT_COUNT.lock().unwrap().get();
-        // let id = *_id;
+        let id = world.next_id();
+        Self::new_with_id(world, id, name, components, state)
+    }
+
+    pub(crate) fn new_with_id(
+        world: &Arc<World>,
+        id: ObjectId,
+        name: String,
+        components: Vec<component::ComponentWrapper>,
+        state: GameObjectState,
+    ) -> Arc<Mutex<Self>> {
+        world.index_name(id, None, &name);
+        world.index_layer(id, None, 0);
+        world.index_parent(id, state.parent_id);
         let game_object = Arc::new(Mutex::new(Self {
             name,
             id,
-            components,
+            tags: BTreeSet::new(),
+            layer: 0,
+            storages: Vec::new(),
+            was_active: state.active,
             state,
             render_references: Vec::new(),
             colliders: Vec::new(),
+            world: Arc::downgrade(world),
         }));
-        GAME_OBJECT_REGISTRY
-            .try_lock()
-            .unwrap()
-            .insert(id, game_object.clone());
-        GAME_OBJECT_COUNT.lock().unwrap().increment();
-        game_object
-    }
+        world.insert(id, game_object.clone());
 
-    pub fn find_by_id(id: i32) -> Option<Arc<Mutex<Self>>> {
-        if GAME_OBJECT_DESTROYED.lock().unwrap().contains(id) {
-            return None;
+        // Already logged by add_components, see `new`
+        if game_object.lock().unwrap().add_components(components).is_err() {
+            warn!("GameObject {} was created without some of its components", id);
         }
 
-        let obj = GAME_OBJECT_REGISTRY.lock().unwrap().get(&id).cloned();
+        game_object
+    }
 
-        if obj.is_some() {
-            obj
-        } else {
-            let text = format!("No object with id {}", id);
-            eprintln!("ERROR: {}", text);
-            None
-            // exit(1);
-        }
+    pub fn world(&self) -> Arc<World> {
+        self.world.upgrade().expect("GameObject outlived its world")
     }
 
     pub fn name(&self) -> &str {
@@ -145,19 +137,150 @@ impl GameObject {
     }
 
     pub fn update_name(&mut self, new: String) {
-        self.name = new.to_string();
+        self.world().index_name(self.id, Some(&self.name), &new);
+        self.name = new;
+    }
+
+    pub fn tags(&self) -> &BTreeSet<String> {
+        &self.tags
     }
 
-    pub fn id(&self) -> i32 {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
+    pub fn add_tag(&mut self, tag: &str) {
+        if self.tags.insert(tag.to_string()) {
+            self.world().index_tag(self.id, tag, true);
+        }
+    }
+
+    pub fn remove_tag(&mut self, tag: &str) {
+        if self.tags.remove(tag) {
+            self.world().index_tag(self.id, tag, false);
+        }
+    }
+
+    /// Layer the object is in, `0` unless set.
+    pub fn layer(&self) -> u32 {
+        self.layer
+    }
+
+    pub fn set_layer(&mut self, layer: u32) {
+        if layer != self.layer {
+            self.world().index_layer(self.id, Some(self.layer), layer);
+            self.layer = layer;
+        }
+    }
+
+    pub fn id(&self) -> ObjectId {
         self.id
     }
 
-    pub fn components(&self) -> &[Arc<Mutex<component::ComponentWrapper>>] {
-        &self.components
+    /// Storages of the component types this object has, in the order they were added.
+    pub fn storages(&self) -> &[Arc<dyn ComponentStorage>] {
+        &self.storages
+    }
+
+    fn storage<T: ComponentTrait>(&self) -> Option<&Storage<T>> {
+        self.storages
+            .iter()
+            .find(|storage| storage.component_type() == TypeId::of::<T>())
+            .and_then(|storage| (**storage).as_any().downcast_ref::<Storage<T>>())
+    }
+
+    fn has_component_type(&self, type_id: TypeId) -> bool {
+        self.storages.iter().any(|storage| storage.component_type() == type_id)
+    }
+
+    /// Adds `component`, after adding defaults for any of its
+    /// [`requires`](ComponentTrait::requires) the object doesn't have yet.
+    pub fn add_component(&mut self, component: component::ComponentWrapper) -> EngineResult<()> {
+        self.add_component_requiring(component, &[], &mut Vec::new())
+    }
+
+    /// Adds a batch of components, e.g. a restored save. Requirements are only filled
+    /// in when no component of the batch provides them, whatever the order.
+    pub fn add_components(&mut self, components: Vec<component::ComponentWrapper>) -> EngineResult<()> {
+        let provided: Vec<TypeId> = components.iter().map(|component| component.component_type()).collect();
+        let mut result = Ok(());
+        for component in components {
+            if let Err(err) = self.add_component_requiring(component, &provided, &mut Vec::new()) {
+                error!("{}", err);
+                result = result.and(Err(err));
+            }
+        }
+        result
+    }
+
+    // `adding` holds the components whose requirements are being filled in, meeting one
+    // of them again means the requirements go round in a circle
+    fn add_component_requiring(
+        &mut self,
+        component: component::ComponentWrapper,
+        provided: &[TypeId],
+        adding: &mut Vec<TypeId>,
+    ) -> EngineResult<()> {
+        if !component.allow_multiple() && self.has_component_type(component.component_type()) {
+            return Err(EngineError::DuplicateComponent {
+                object: self.name.clone(),
+                component: component.name().to_string(),
+            });
+        }
+
+        adding.push(component.component_type());
+        for requirement in component.requires() {
+            let type_id = requirement.component_type();
+            if adding.contains(&type_id) {
+                return Err(EngineError::RequirementCycle {
+                    object: self.name.clone(),
+                    component: component.name().to_string(),
+                });
+            }
+            if !provided.contains(&type_id) && !self.has_component_type(type_id) {
+                info!("Adding {} to {}, {} requires it", requirement.name(), self.name, component.name());
+                self.add_component_requiring(requirement.make(), provided, adding)?;
+            }
+        }
+        adding.pop();
+
+        let storage = component.insert(self);
+        if !self.storages.iter().any(|existing| Arc::ptr_eq(existing, &storage)) {
+            self.storages.push(storage);
+        }
+        Ok(())
+    }
+
+    /// Removes every `T` from the object, running their
+    /// [`on_destroy`](ComponentTrait::on_destroy) first. Returns whether it had one.
+    pub fn remove_component<T: ComponentTrait>(&mut self) -> bool {
+        self.remove_component_type(TypeId::of::<T>())
     }
 
-    pub fn add_component(&mut self, component: Arc<Mutex<component::ComponentWrapper>>) {
-        self.components.push(component);
+    pub(crate) fn remove_component_type(&mut self, type_id: TypeId) -> bool {
+        let storage = match self.storages.iter().position(|storage| storage.component_type() == type_id) {
+            Some(index) => self.storages.remove(index),
+            None => return false,
+        };
+
+        storage.lifecycle(self.id, self, Lifecycle::Destroy);
+        storage.remove(self.id);
+        true
+    }
+
+    /// Sends `event` over the world's [`EventBus`](crate::engine::event::EventBus).
+    pub fn send<E: Event>(&self, event: E) {
+        self.world().events().send(event);
+    }
+
+    /// Calls `f` with this object's `T` for every `E` until the object is destroyed.
+    pub fn subscribe<E: Event, T: ComponentTrait>(
+        &self,
+        delivery: Delivery,
+        f: impl Fn(&mut T, &E) + Send + Sync + 'static,
+    ) -> SubscriptionId {
+        let world = self.world();
+        world.events().subscribe_component(&world, self.id, delivery, f)
     }
 
     pub fn colliders(&self) -> &[Arc<Mutex<Box<dyn Collider>>>] {
@@ -168,15 +291,19 @@ impl GameObject {
         self.colliders.push(coll);
     }
 
+    /// World position of the object's [`Transform`], the origin if it has none.
+    pub fn world_pos(&mut self) -> [f32; 3] {
+        let mut pos = [0.0, 0.0, 0.0];
+        self.get_component_closure::<Transform>(|transform| pos = transform.world_pos());
+        pos
+    }
+
     pub fn colliding_with(
         &mut self,
         other: Arc<Mutex<Box<dyn Collider>>>,
         other_pos: collider::Point,
     ) -> bool {
-        let mut current_pos: [f32; 3] = [0.0, 0.0, 0.0];
-        self.get_component_closure::<Transform>(|transform| {
-            let current_pos = transform.pos.clone();
-        });
+        let current_pos = self.world_pos();
 
         for coll in self.colliders.iter_mut() {
             if coll.lock().unwrap().colliding_with(
@@ -196,10 +323,7 @@ impl GameObject {
     }
 
     pub fn colliding_point(&mut self, other: collider::Point) -> bool {
-        let mut current_pos: [f32; 3] = [0.0, 0.0, 0.0];
-        self.get_component_closure::<Transform>(|transform| {
-            let current_pos = transform.pos.clone();
-        });
+        let current_pos = self.world_pos();
 
         for coll in self.colliders.iter_mut() {
             if coll.lock().unwrap().colliding_point(
@@ -218,10 +342,7 @@ impl GameObject {
     }
 
     pub fn intersects(&mut self, segment: &mut (Point, Point)) -> bool {
-        let mut current_pos: [f32; 3] = [0.0, 0.0, 0.0];
-        self.get_component_closure::<Transform>(|transform| {
-            current_pos = transform.pos.clone();
-        });
+        let current_pos = self.world_pos();
 
         for coll in self.colliders.iter_mut() {
             if coll.try_lock().unwrap().intersects(
@@ -239,90 +360,110 @@ impl GameObject {
         false
     }
 
-    pub fn get_component_closure<T>(&mut self, mut f: impl FnMut(&mut T)) -> Option<()>
+    pub fn get_component_closure<T>(&mut self, f: impl FnOnce(&mut T)) -> Option<()>
     where
         T: ComponentTrait + 'static,
     {
-        // Iterate over components
-        let comps = self.components.clone();
-        for comp_arc in comps {
-            let mut _comp_lock = comp_arc.try_lock()/*.ok()?*/;
-
-            let mut comp_lock = match _comp_lock {
-                Ok(lock) => lock,
-                Err(_) => {
-                    println!("couldn't get lock");
-                    continue;
-                }
-            };
-
-            // println!("could get lock");
-
-            // Attempt to downcast the component under the MutexGuard's scope
-            // println!("{}", comp_lock.component);
-            if let Some(component) = (&mut *comp_lock.component.lock().unwrap())
-                .as_any_mut()
-                .downcast_mut::<T>()
-            {
-                f(component); // Execute the closure with the mutable reference
-                              // drop(comp_lock);
-                return Some(()); // Return early on success
+        let storage = self.storage::<T>()?;
+        let mut set = match storage.try_borrow_mut() {
+            Some(set) => set,
+            None => {
+                warn!("couldn't borrow {} on {}", type_name::<T>(), self.name);
+                return None;
             }
+        };
+
+        f(set.get_mut(self.id)?);
+        Some(())
+    }
+
+    pub fn has_component<T: ComponentTrait + 'static>(&self) -> bool {
+        self.storage::<T>().is_some()
+    }
+
+    /// Sets `state.active` and runs the `on_enable`/`on_disable` hooks if it changed.
+    /// Writing `state.active` directly also works, the hooks then run before the
+    /// object's next tick.
+    pub fn set_active(&mut self, active: bool) {
+        self.state.active = active;
+        self.sync_active();
+    }
 
-            drop(comp_lock);
+    /// Runs the `on_shutdown` hook of every component.
+    pub(crate) fn shutdown(&mut self) {
+        for storage in self.storages.clone() {
+            storage.lifecycle(self.id, self, Lifecycle::Shutdown);
         }
+    }
 
-        // If no component of type T was found and processed
-        None
+    fn sync_active(&mut self) {
+        if self.state.active == self.was_active {
+            return;
+        }
+        self.was_active = self.state.active;
+
+        let event = if self.state.active { Lifecycle::Enable } else { Lifecycle::Disable };
+        for storage in self.storages.clone() {
+            storage.lifecycle(self.id, self, event);
+        }
     }
 
-    pub fn has_component<T: ComponentTrait + 'static>(&self) -> bool {
-        for comp_arc in self.components.clone() {
-            let comp_lock = comp_arc.lock().unwrap();
-            if (&*comp_lock.component.lock().unwrap()).as_any().downcast_ref::<T>().is_some() {
-                return true;
+    /// Runs one simulation step of the input components, then the default ones.
+    pub fn tick_self(&mut self, engine: &mut Engine) {
+        self.sync_active();
+        // Deactivated objects, e.g. the free ones in a pool, sit out
+        if !self.state.active {
+            return;
+        }
+
+        let input = engine.input_data();
+        let dt = engine.delta();
+        let cam = engine.camera();
+
+        let storages = self.storages.clone();
+        for tick_type in [ComponentType::Input, ComponentType::Default] {
+            for storage in storages.iter().filter(|storage| storage.tick_type() == tick_type) {
+                storage.tick(self.id, Some(&input), self, dt, cam.clone());
             }
         }
-        false
     }
 
-    pub fn tick_self(&mut self, engine: &mut Engine) {
-        for component in self.components.clone() {
-            // println!("{}", component.lock().unwrap().component.lock().unwrap().name().clone());
-            let mut comp = component.lock().unwrap();
-
-            let mut render_data = comp.tick(
-                Some(&engine.input_data()),
-                self,
-                engine.dt.unwrap_or(Duration::from_secs(0)),
-                engine.renderer.lock().unwrap().backend.camera.clone()
-            );
-
-            drop(comp);
-
-            if render_data.is_some() {
-                if self.state.active {
-                    // if self.render_reference.is_some() {
-                    //     engine.remove_from_render_queue(
-                    //         self.render_reference.expect("no render reference"),
-                    //     );
-                    // }
-
-                    self.render_references.push(engine.render(render_data.take().expect("get good")));
-                } else {
-                    // engine.remove_from_render_queue(
-                    //     self.render_reference.expect("no render reference"),
-                    // );
+    /// Runs the render components, queueing their output if the object is active. The
+    /// output of the previous render update is forgotten, the engine rebuilds the whole
+    /// queue.
+    pub fn render_self(&mut self, engine: &mut Engine) {
+        self.render_references.clear();
+        let storages: Vec<_> = self
+            .storages
+            .iter()
+            .filter(|storage| storage.tick_type() == ComponentType::Render)
+            .cloned()
+            .collect();
+        if storages.is_empty() {
+            return;
+        }
+
+        let dt = engine.frame_time();
+        let cam = engine.camera();
+        for storage in storages {
+            let render_data = storage.tick(self.id, None, self, dt, cam.clone());
+            if self.state.active {
+                for render in render_data {
+                    self.render_references.push(engine.render(render));
                 }
             }
+        }
+    }
 
-            // match comp
-            // comp.tick();
+    pub fn render_all(&mut self, engine: &mut Engine) {
+        self.render_self(engine);
+        for child_arc in self.state.children(&self.world()) {
+            child_arc.lock().unwrap().render_all(engine);
         }
     }
 
     pub fn tick_children(&mut self, engine: &mut Engine) {
-        let children = self.state.children();
+        let children = self.state.children(&self.world());
         for child_arc in children {
             let mut child = child_arc.lock().unwrap();
             child.tick_all(engine);
@@ -335,59 +476,90 @@ impl GameObject {
     }
 
     pub fn destroy(&mut self) {
-        GAME_OBJECT_DESTROYED.lock().unwrap().push(self.id); 
-    } 
+        self.world().destroy(self.id);
+    }
+
+    /// Deactivates the object and puts it back into the pool it was acquired from,
+    /// see [`Engine::acquire`].
+    pub fn release(&mut self) -> EngineResult<()> {
+        self.world().pools().release(self.id)?;
+        self.set_active(false);
+        Ok(())
+    }
+
+    /// Starts an async task owned by this object, it's dropped when the object is
+    /// destroyed. See [`Tasks`](crate::engine::task::Tasks).
+    pub fn start_task<F, Fut>(&self, f: F) -> TaskHandle
+    where
+        F: FnOnce(TaskContext) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.world().tasks().start(Some(self.id), f)
+    }
 }
 
-pub fn make_base_game_object(name: String) -> Arc<Mutex<GameObject>> {
-    let g = unsafe { GameObject::new(name, vec![], GameObjectState::new(true, None, vec![])) };
+pub fn make_base_game_object(world: &Arc<World>, name: String) -> Arc<Mutex<GameObject>> {
+    let g = GameObject::new(world, name, vec![], GameObjectState::new(true, None, vec![]));
 
     let id = g.clone().lock().unwrap().id().clone();
-    add_component(id, component::Transform::new());
+    add_component(world, id, component::Transform::new()).expect("new objects have no components");
 
     g
 }
 
 pub fn colliding_with(
-    obj_id: i32,
+    world: &World,
+    obj_id: ObjectId,
     other: Arc<Mutex<Box<dyn Collider>>>,
     other_pos: collider::Point,
-) -> bool {
-    let mut obj_op = GameObject::find_by_id(obj_id);
-    let mut obj = obj_op.expect("no object");
-
-    let mut lock = obj.lock().unwrap();
-
-    lock.colliding_with(other, other_pos)
+) -> EngineResult<bool> {
+    to_object(world, obj_id, |obj| obj.colliding_with(other, other_pos))
 }
 
-pub fn colliding_point(obj_id: i32, other: collider::Point) -> bool {
-    let mut obj_op = GameObject::find_by_id(obj_id);
-    let mut obj = obj_op.expect("no object");
-
-    let mut lock = obj.lock().unwrap();
-
-    lock.colliding_point(other)
+pub fn colliding_point(world: &World, obj_id: ObjectId, other: collider::Point) -> EngineResult<bool> {
+    to_object(world, obj_id, |obj| obj.colliding_point(other))
 }
 
-pub fn add_collider(obj_id: i32, coll: Arc<Mutex<Box<dyn Collider>>>) {
-    let mut obj_op = GameObject::find_by_id(obj_id);
-    let obj = obj_op.expect("no object");
-
-    let mut lock = obj.lock().unwrap();
-
-    lock.add_collider(coll);
+pub fn add_collider(world: &World, obj_id: ObjectId, coll: Arc<Mutex<Box<dyn Collider>>>) -> EngineResult<()> {
+    to_object(world, obj_id, |obj| obj.add_collider(coll))
 }
 
-pub fn reparent(parent_id: i32, child_id: i32) {
-    let registry = GAME_OBJECT_REGISTRY.lock().unwrap();
-    if let (Some(parent_arc), Some(child_arc)) = (registry.get(&parent_id), registry.get(&child_id))
-    {
+/// Makes `child_id` a child of `parent_id`, taking it away from its previous parent.
+/// The child keeps its local transform, so it moves to the same offset from its new
+/// parent. Its descendants pick up the change when transforms are propagated at the
+/// start of the next tick. Fails if the new parent is the child or one of its
+/// descendants.
+pub fn reparent(world: &World, parent_id: ObjectId, child_id: ObjectId) -> EngineResult<()> {
+    let parents = world.parents();
+    let mut ancestors = std::iter::successors(Some(parent_id), |id| parents.get(id).copied()).take(parents.len() + 1);
+    if ancestors.any(|id| id == child_id) {
+        return Err(EngineError::ParentCycle { parent: parent_id, child: child_id });
+    }
+
+    let parent_arc = world.find_by_id(parent_id).ok_or(EngineError::MissingObject(parent_id))?;
+    let child_arc = world.find_by_id(child_id).ok_or(EngineError::MissingObject(child_id))?;
+    let previous = {
         let mut parent = parent_arc.lock().unwrap();
         let mut child = child_arc.lock().unwrap();
+        let previous = child.state.parent_id.filter(|id| *id != parent_id);
         child.state.set_parent(parent_id);
         parent.state.add_child(child_id);
+        world.index_parent(child_id, Some(parent_id));
+        previous
+    };
+
+    if let Some(previous) = previous.and_then(|id| world.find_by_id(id)) {
+        previous.lock().unwrap().state.child_ids.retain(|id| *id != child_id);
+    }
+
+    if let Some(mut transforms) = world.storage::<Transform>().as_deref().and_then(|storage| storage.try_borrow_mut()) {
+        if let Some(parent_world) = transforms.get(parent_id).map(|parent| parent.world()) {
+            if let Some(child) = transforms.get_mut(child_id) {
+                child.set_parent_world(parent_world);
+            }
+        }
     }
+    Ok(())
 }
 
 pub fn safe_to_object<F, T>(object: Arc<Mutex<GameObject>>, f: F) -> T
@@ -398,18 +570,18 @@ where
     f(&mut game_object)
 }
 
-pub fn to_object<F, T>(object: i32, f: F) -> T
+/// Runs `f` on the object, failing instead of blocking if it's already locked.
+pub fn to_object<F, T>(world: &World, object: ObjectId, f: F) -> EngineResult<T>
 where
     F: FnOnce(&mut GameObject) -> T,
 {
-    let game_object = GameObject::find_by_id(object).expect("Nothing found");
-    let mut g = game_object.try_lock().unwrap();
-    f(&mut g)
+    let game_object = world.find_by_id(object).ok_or(EngineError::MissingObject(object))?;
+    let mut g = game_object.try_lock().map_err(|_| EngineError::ObjectLocked(object))?;
+    Ok(f(&mut g))
 }
 
-pub fn _internal_to_object<T, F: FnOnce(&GameObject) -> T>(obj_id: i32, func: F) -> Option<T> {
-    let game_objects = GAME_OBJECT_REGISTRY.lock().unwrap();
-    if let Some(obj) = game_objects.get(&obj_id) {
+pub fn _internal_to_object<T, F: FnOnce(&GameObject) -> T>(world: &World, obj_id: ObjectId, func: F) -> Option<T> {
+    if let Some(obj) = world.find_by_id(obj_id) {
         let obj = obj.lock().unwrap();
         return Some(func(&obj));
     }
@@ -418,52 +590,12 @@ pub fn _internal_to_object<T, F: FnOnce(&GameObject) -> T>(obj_id: i32, func: F)
 
 // pub fn add_component(object: i32, comp: Arc<Mutex<dyn component::TickVariant>>) {
 
-pub fn add_component(object: i32, comp: Arc<Mutex<component::ComponentWrapper>>) {
-    let comp_type_id = comp.type_id();
-
-    let game_objects = GAME_OBJECT_REGISTRY
-        .try_lock()
-        .expect("Registry lock failed");
-    let game_object = match game_objects.get(&object) {
-        Some(obj_arc) => obj_arc.lock().expect("GameObject lock failed"),
-        None => {
-            eprintln!("ERROR: No object with id {}", object);
-            return;
-        }
-    };
-
-    let already_exists = game_object.components.iter().any(|comp_arc| {
-        let comp = comp_arc.lock().unwrap();
-        TypeId::of::<dyn ComponentTrait>() == comp_type_id
-    });
-
-    drop(game_object);
-    drop(game_objects);
-
-    if already_exists {
-        let game_object_name = GameObject::find_by_id(object)
-            .unwrap()
-            .try_lock()
-            .unwrap()
-            .name()
-            .to_string();
-        eprintln!(
-            "ERROR: GameObject {} already has component {}",
-            game_object_name,
-            comp.lock().unwrap().component.lock().unwrap().name()
-        );
-        exit(1);
-    } else {
-        to_object(object, |obj| {
-            obj.add_component(comp);
-        });
-    }
+pub fn add_component(world: &World, object: ObjectId, comp: component::ComponentWrapper) -> EngineResult<()> {
+    to_object(world, object, |game_object| game_object.add_component(comp))?
 }
 
-pub fn has_component<T: component::ComponentTrait + 'static>(obj_id: i32) -> bool {
-    let game_object = GameObject::find_by_id(obj_id).expect("No object found");
-    let lock = game_object.lock().unwrap();
-    lock.has_component::<T>().clone()
+pub fn has_component<T: component::ComponentTrait + 'static>(world: &World, obj_id: ObjectId) -> EngineResult<bool> {
+    to_object(world, obj_id, |obj| obj.has_component::<T>())
 }
 
 #[cfg(test)]
@@ -471,15 +603,72 @@ mod tests {
     use super::*;
     use crate::engine::collider::Point;
     use crate::engine::component::{ComponentWrapper, Transform};
+    use crate::engine::component::{Component, ComponentState, TickBehavior, TickVariant};
     use crate::engine::components::{RenderComponent, InputComponent};
+    use crate::engine::graphics_backend::primitives::Primitives;
+    use crate::engine::save::{self, ComponentSaveLoad};
+    use lazy_static::lazy_static;
+    use serde_json::Value;
     use std::sync::{Arc, Mutex};
 
+    lazy_static! {
+        static ref HOOK_LOG: Mutex<Vec<(String, &'static str)>> = Mutex::new(Vec::new());
+    }
+
+    fn hooks_of(name: &str) -> Vec<&'static str> {
+        HOOK_LOG
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(object, _)| object == name)
+            .map(|(_, hook)| *hook)
+            .collect()
+    }
+
+    struct Hooks {
+        state: ComponentState,
+    }
+
+    impl Hooks {
+        fn new() -> ComponentWrapper {
+            ComponentWrapper::new(Self { state: ComponentState::new() }, TickVariant::Default(Self::tick))
+        }
+
+        fn log(obj: &GameObject, hook: &'static str) {
+            HOOK_LOG.lock().unwrap().push((obj.name.clone(), hook));
+        }
+    }
+
+    impl ComponentTrait for Hooks {
+        fn name(&self) -> &str { "Hooks" }
+        fn state(&mut self) -> &mut ComponentState { &mut self.state }
+
+        fn on_add(&mut self, obj: &mut GameObject) { Self::log(obj, "add") }
+        fn on_start(&mut self, obj: &mut GameObject) { Self::log(obj, "start") }
+        fn on_enable(&mut self, obj: &mut GameObject) { Self::log(obj, "enable") }
+        fn on_disable(&mut self, obj: &mut GameObject) { Self::log(obj, "disable") }
+        fn on_destroy(&mut self, obj: &mut GameObject) { Self::log(obj, "destroy") }
+        fn on_shutdown(&mut self, obj: &mut GameObject) { Self::log(obj, "shutdown") }
+    }
+
+    impl ComponentSaveLoad for Hooks {
+        fn to_save_data(&mut self) -> Value { Value::Null }
+        fn from_save_data(_data: Value) -> EngineResult<ComponentWrapper> { Ok(Hooks::new()) }
+    }
+
+    impl TickBehavior for Hooks {
+        fn tick(&mut self, obj: &mut GameObject, _dt: Duration) {
+            Self::log(obj, "tick");
+        }
+    }
+
     #[test]
     fn test_game_object_creation() {
         let name = "TestObject".to_string();
         let components = vec![];
         let state = GameObjectState::new(true, None, vec![]);
-        let game_object = unsafe{ GameObject::new(name.clone(), components, state) };
+        let world = World::new();
+        let game_object = GameObject::new(&world, name.clone(), components, state);
 
         let game_object = game_object.lock().unwrap();
         assert_eq!(game_object.name(), name);
@@ -489,13 +678,14 @@ mod tests {
     #[test]
     fn test_add_component() {
         let name = "TestObject".to_string();
-        let game_object = make_base_game_object(name);
+        let world = World::new();
+        let game_object = make_base_game_object(&world, name);
 
         // let id = game_object.lock().unwrap().id();
         let transform_component = InputComponent::new(String::from("name"));
 
         // add_component(id, transform_component.clone());
-        game_object.lock().unwrap().add_component(transform_component.clone());
+        game_object.lock().unwrap().add_component(transform_component).unwrap();
         assert!(game_object.lock().unwrap().has_component::<InputComponent>());
 
         let mut has = false;
@@ -523,13 +713,14 @@ mod tests {
         let parent_name = "ParentObject".to_string();
         let child_name = "ChildObject".to_string();
 
-        let parent_object = make_base_game_object(parent_name);
-        let child_object = make_base_game_object(child_name);
+        let world = World::new();
+        let parent_object = make_base_game_object(&world, parent_name);
+        let child_object = make_base_game_object(&world, child_name);
 
         let parent_id = parent_object.lock().unwrap().id();
         let child_id = child_object.lock().unwrap().id();
 
-        reparent(parent_id, child_id);
+        reparent(&world, parent_id, child_id).unwrap();
 
         let child_object = child_object.lock().unwrap();
         assert_eq!(child_object.state.parent_id, Some(parent_id));
@@ -538,13 +729,33 @@ mod tests {
         assert!(parent_object.state.child_ids.contains(&child_id));
     }
 
+    #[test]
+    fn test_reparent_rejects_cycles() {
+        let world = World::new();
+        let [root, middle, leaf] = ["root", "middle", "leaf"].map(|name| {
+            make_base_game_object(&world, name.to_string()).lock().unwrap().id()
+        });
+        reparent(&world, root, middle).unwrap();
+        reparent(&world, middle, leaf).unwrap();
+
+        assert_eq!(reparent(&world, root, root), Err(EngineError::ParentCycle { parent: root, child: root }));
+        assert_eq!(reparent(&world, leaf, root), Err(EngineError::ParentCycle { parent: leaf, child: root }));
+        assert_eq!(world.find_by_id(root).unwrap().lock().unwrap().state.parent_id, None);
+        assert!(world.find_by_id(leaf).unwrap().lock().unwrap().state.child_ids.is_empty());
+
+        // Moving a subtree further up is fine
+        reparent(&world, root, leaf).unwrap();
+        assert_eq!(world.find_by_id(root).unwrap().lock().unwrap().state.child_ids, vec![middle, leaf]);
+    }
+
     #[test]
     fn test_colliding_with() {
         let name1 = "Object1".to_string();
         let name2 = "Object2".to_string();
 
-        let object1 = make_base_game_object(name1);
-        let object2 = make_base_game_object(name2);
+        let world = World::new();
+        let object1 = make_base_game_object(&world, name1);
+        let object2 = make_base_game_object(&world, name2);
 
         let id1 = object1.lock().unwrap().id();
         let id2 = object2.lock().unwrap().id();
@@ -552,31 +763,176 @@ mod tests {
         let collider1 = Arc::new(Mutex::new(collider::PointCollider::new(Point { x: 0.0, y: 0.0, z: 0.0 })));
         let collider2 = Arc::new(Mutex::new(collider::PointCollider::new(Point { x: 0.0, y: 0.0, z: 0.0 })));
 
-        add_collider(id1, collider1.clone());
-        add_collider(id2, collider2.clone());
+        add_collider(&world, id1, collider1.clone()).unwrap();
+        add_collider(&world, id2, collider2.clone()).unwrap();
 
-        assert!(colliding_with(id1, collider2, Point { x: 0.0, y: 0.0, z: 0.0 }));
+        assert!(colliding_with(&world, id1, collider2, Point { x: 0.0, y: 0.0, z: 0.0 }).unwrap());
     }
 
     #[test]
     fn test_colliding_point() {
         let name = "Object".to_string();
-        let object = make_base_game_object(name);
+        let world = World::new();
+        let object = make_base_game_object(&world, name);
 
         let id = object.lock().unwrap().id();
         let collider = Arc::new(Mutex::new(collider::PointCollider::new(Point { x: 0.0, y: 0.0, z: 0.0 })));
 
-        add_collider(id, collider.clone());
-        assert!(colliding_point(id, Point { x: 0.0, y: 0.0, z: 0.0 }));
+        add_collider(&world, id, collider.clone()).unwrap();
+        assert!(colliding_point(&world, id, Point { x: 0.0, y: 0.0, z: 0.0 }).unwrap());
     }
 
     #[test]
     fn test_update_name() {
         let name = "TestObject".to_string();
         let new_name = "UpdatedObject".to_string();
-        let game_object = make_base_game_object(name);
+        let world = World::new();
+        let game_object = make_base_game_object(&world, name);
 
         game_object.lock().unwrap().update_name(new_name.clone());
         assert_eq!(game_object.lock().unwrap().name(), &new_name);
     }
+
+    #[test]
+    fn test_lifecycle_hooks() {
+        let mut engine = Engine::new_headless();
+        let obj = engine.add_object(make_base_game_object(&engine.world(), "hooked".to_string()));
+        obj.get().lock().unwrap().add_component(Hooks::new()).unwrap();
+        assert_eq!(hooks_of("hooked"), vec!["add"]);
+
+        engine.tick();
+        engine.tick();
+        assert_eq!(hooks_of("hooked"), vec!["add", "start", "tick", "tick"]);
+
+        obj.get().lock().unwrap().set_active(false);
+        obj.get().lock().unwrap().set_active(false);
+        // Flipped without set_active, picked up before the next tick
+        obj.get().lock().unwrap().state.active = true;
+        engine.tick();
+        obj.get().lock().unwrap().destroy();
+        engine.tick();
+
+        assert_eq!(
+            hooks_of("hooked"),
+            vec!["add", "start", "tick", "tick", "disable", "enable", "tick", "destroy"]
+        );
+    }
+
+    #[test]
+    fn test_shutdown_hooks_run_once() {
+        let mut engine = Engine::new_headless();
+        let obj = engine.add_object(make_base_game_object(&engine.world(), "shutting down".to_string()));
+        obj.get().lock().unwrap().add_component(Hooks::new()).unwrap();
+
+        engine.quit();
+        assert!(engine.is_quitting());
+        engine.shutdown();
+        engine.shutdown();
+        assert_eq!(hooks_of("shutting down"), vec!["add", "shutdown"]);
+    }
+
+    #[test]
+    fn test_restored_components_get_lifecycle_hooks() {
+        save::init();
+        save::register_component::<Hooks>("Hooks");
+
+        let mut engine = Engine::new_headless();
+        let obj = engine.add_object(make_base_game_object(&engine.world(), "restored".to_string()));
+        obj.get().lock().unwrap().add_component(Hooks::new()).unwrap();
+        let json = engine.export_raw();
+        HOOK_LOG.lock().unwrap().retain(|(object, _)| object != "restored");
+
+        let mut restored = Engine::import_from_json_headless(json);
+        assert_eq!(hooks_of("restored"), vec!["add"]);
+        restored.tick();
+        assert_eq!(hooks_of("restored"), vec!["add", "start", "tick"]);
+    }
+
+    #[test]
+    fn test_add_component_adds_requirements_and_rejects_duplicates() {
+        let world = World::new();
+        let obj = GameObject::new(&world, "bare".to_string(), vec![], GameObjectState::new(true, None, vec![]));
+        let mut obj = obj.lock().unwrap();
+
+        obj.add_component(RenderComponent::new(Primitives::Cube(0.1, [1.0, 0.0, 0.0]))).unwrap();
+        assert!(obj.has_component::<Transform>());
+
+        let err = obj.add_component(Transform::new()).unwrap_err();
+        assert_eq!(
+            err,
+            EngineError::DuplicateComponent { object: "bare".to_string(), component: "Transform".to_string() }
+        );
+        assert_eq!(
+            add_component(&world, ObjectId::new(7, 0), Transform::new()),
+            Err(EngineError::MissingObject(ObjectId::new(7, 0)))
+        );
+    }
+
+    #[derive(Component)]
+    #[component(requires(Egg))]
+    struct Chicken {
+        state: ComponentState,
+    }
+
+    #[derive(Component)]
+    #[component(requires(Chicken))]
+    struct Egg {
+        state: ComponentState,
+    }
+
+    impl TickBehavior for Chicken {
+        fn tick(&mut self, _obj: &mut GameObject, _dt: Duration) {}
+    }
+
+    impl TickBehavior for Egg {
+        fn tick(&mut self, _obj: &mut GameObject, _dt: Duration) {}
+    }
+
+    #[test]
+    fn test_requirement_cycles_are_rejected() {
+        let world = World::new();
+        let obj = GameObject::new(&world, "farm".to_string(), vec![], GameObjectState::new(true, None, vec![]));
+        let mut obj = obj.lock().unwrap();
+
+        assert_eq!(
+            obj.add_component(Chicken::new()),
+            Err(EngineError::RequirementCycle { object: "farm".to_string(), component: "Egg".to_string() })
+        );
+        assert!(!obj.has_component::<Chicken>() && !obj.has_component::<Egg>());
+
+        // Fine when the batch brings both
+        obj.add_components(vec![Chicken::new(), Egg::new()]).unwrap();
+        assert!(obj.has_component::<Chicken>() && obj.has_component::<Egg>());
+    }
+
+    #[test]
+    fn test_utilities_fail_on_stale_and_locked_objects() {
+        let world = World::new();
+        let obj = make_base_game_object(&world, "stale".to_string());
+        let id = obj.lock().unwrap().id();
+
+        {
+            let _ticking = obj.lock().unwrap();
+            assert_eq!(has_component::<Transform>(&world, id), Err(EngineError::ObjectLocked(id)));
+        }
+        assert_eq!(has_component::<Transform>(&world, id), Ok(true));
+
+        world.destroy(id);
+        world.flush_destroyed();
+        assert_eq!(to_object(&world, id, |obj| obj.name().to_string()), Err(EngineError::MissingObject(id)));
+    }
+
+    #[test]
+    fn test_batches_only_fill_requirements_they_dont_provide() {
+        let world = World::new();
+        let obj = GameObject::new(
+            &world,
+            "batch".to_string(),
+            vec![RenderComponent::new(Primitives::Cube(0.1, [1.0, 0.0, 0.0])), Transform::new()],
+            GameObjectState::new(true, None, vec![]),
+        );
+        let id = obj.lock().unwrap().id();
+
+        assert_eq!(world.storage::<Transform>().unwrap().borrow().count(id), 1);
+    }
 }
@@ -104,6 +104,16 @@ pub trait Object: Send + Sync {
         self.desc()
     }
 
+    fn scale_vertexes_arr(&mut self, scale: [f32; 3]) {
+        let mut vertexes = self.get_vertexes();
+        for v in vertexes.iter_mut() {
+            for (position, scale) in v.position.iter_mut().zip(scale) {
+                *position *= scale;
+            }
+        }
+        self.set_vertexes(vertexes);
+    }
+
     fn get_vertexes(&mut self) -> Vec<Vertex>;
     fn set_vertexes(&mut self, vertexes: Vec<Vertex>);
     fn desc_raw(&mut self) -> (Vec<Vertex>, Vec<u16>) {
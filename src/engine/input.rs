@@ -0,0 +1,181 @@
+use crate::engine::component::InputData;
+use crate::engine::error::EngineResult;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use winit::event::{MouseButton, VirtualKeyCode};
+
+/// A key or mouse button an action or axis is bound to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+impl Binding {
+    pub fn is_held(&self, input: &InputData) -> bool {
+        match self {
+            Binding::Key(key) => input.keys_pressed.contains(key),
+            Binding::Mouse(button) => input.mouse_buttons_pressed.contains(button),
+        }
+    }
+}
+
+/// Two bindings that push an axis towards -1 and 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AxisBinding {
+    pub negative: Binding,
+    pub positive: Binding,
+}
+
+impl AxisBinding {
+    fn value(&self, input: &InputData) -> f32 {
+        self.positive.is_held(input) as i32 as f32 - self.negative.is_held(input) as i32 as f32
+    }
+}
+
+/// Named actions and axes and what they're bound to, so components ask for
+/// `input.action("fire")` instead of key codes. Saved with the engine and editable at
+/// runtime through [`Engine::input_map_mut`](crate::engine::state::Engine::input_map_mut).
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputMap {
+    actions: BTreeMap<String, Vec<Binding>>,
+    axes: BTreeMap<String, Vec<AxisBinding>>,
+}
+
+impl InputMap {
+    /// A map without any bindings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The bindings engines start with: `move_x` and `move_y` on WASD, `rotate` on the
+    /// left and right arrows and `fire` on Space and the left mouse button.
+    pub fn defaults() -> Self {
+        let mut map = Self::new();
+        map.bind_axis("move_x", Binding::Key(VirtualKeyCode::A), Binding::Key(VirtualKeyCode::D));
+        map.bind_axis("move_y", Binding::Key(VirtualKeyCode::S), Binding::Key(VirtualKeyCode::W));
+        map.bind_axis("rotate", Binding::Key(VirtualKeyCode::Left), Binding::Key(VirtualKeyCode::Right));
+        map.bind("fire", Binding::Key(VirtualKeyCode::Space));
+        map.bind("fire", Binding::Mouse(MouseButton::Left));
+        map
+    }
+
+    /// Adds `binding` to the action, it's held while any of its bindings is.
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        let bindings = self.actions.entry(action.to_string()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    /// Adds a pair of bindings to the axis, the values of its pairs are added up.
+    pub fn bind_axis(&mut self, axis: &str, negative: Binding, positive: Binding) {
+        let binding = AxisBinding { negative, positive };
+        let bindings = self.axes.entry(axis.to_string()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    /// Replaces every binding of the action.
+    pub fn rebind(&mut self, action: &str, bindings: Vec<Binding>) {
+        self.actions.insert(action.to_string(), bindings);
+    }
+
+    /// Replaces every binding of the axis.
+    pub fn rebind_axis(&mut self, axis: &str, bindings: Vec<AxisBinding>) {
+        self.axes.insert(axis.to_string(), bindings);
+    }
+
+    /// Removes the action or axis called `name`.
+    pub fn unbind(&mut self, name: &str) {
+        self.actions.remove(name);
+        self.axes.remove(name);
+    }
+
+    pub fn bindings(&self, action: &str) -> &[Binding] {
+        self.actions.get(action).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn axis_bindings(&self, axis: &str) -> &[AxisBinding] {
+        self.axes.get(axis).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn actions(&self) -> impl Iterator<Item = &str> {
+        self.actions.keys().map(String::as_str)
+    }
+
+    pub fn axes(&self) -> impl Iterator<Item = &str> {
+        self.axes.keys().map(String::as_str)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> EngineResult<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub(crate) fn action(&self, action: &str, input: &InputData) -> bool {
+        self.bindings(action).iter().any(|binding| binding.is_held(input))
+    }
+
+    pub(crate) fn axis(&self, axis: &str, input: &InputData) -> f32 {
+        self.axis_bindings(axis)
+            .iter()
+            .map(|binding| binding.value(input))
+            .sum::<f32>()
+            .clamp(-1.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::state::Engine;
+    use std::sync::Arc;
+
+    fn input(keys: Vec<VirtualKeyCode>, buttons: Vec<MouseButton>) -> InputData {
+        InputData {
+            keys_pressed: keys,
+            mouse_buttons_pressed: buttons,
+            mouse_position: (0.0, 0.0),
+            bindings: Arc::new(InputMap::defaults()),
+        }
+    }
+
+    #[test]
+    fn test_actions_and_axes_follow_their_bindings() {
+        let held = input(vec![VirtualKeyCode::D, VirtualKeyCode::W, VirtualKeyCode::S], vec![MouseButton::Left]);
+        assert_eq!(held.axis("move_x"), 1.0);
+        assert_eq!(held.axis("move_y"), 0.0);
+        assert!(held.action("fire"));
+        assert!(!held.action("jump"));
+        assert_eq!(held.axis("zoom"), 0.0);
+
+        let mut map = InputMap::defaults();
+        map.bind_axis("move_x", Binding::Key(VirtualKeyCode::Left), Binding::Key(VirtualKeyCode::Right));
+        let both = input(vec![VirtualKeyCode::D, VirtualKeyCode::Right], vec![]);
+        assert_eq!(map.axis("move_x", &both), 1.0);
+        assert!(!map.action("fire", &both));
+    }
+
+    #[test]
+    fn test_bindings_are_rebound_and_saved() {
+        let mut engine = Engine::new_headless();
+        engine.input_map_mut().rebind("fire", vec![Binding::Key(VirtualKeyCode::F)]);
+        engine.input_map_mut().unbind("rotate");
+
+        let loaded = Engine::import_from_json_headless(engine.export_raw());
+        assert_eq!(loaded.input_map().bindings("fire"), &[Binding::Key(VirtualKeyCode::F)]);
+        assert_eq!(loaded.input_map().axes().collect::<Vec<_>>(), vec!["move_x", "move_y"]);
+        assert_eq!(InputMap::from_json(&loaded.input_map().to_json()).unwrap(), *loaded.input_map());
+
+        // Saves from before input maps start with the defaults
+        let mut data = serde_json::to_value(engine.export()).unwrap();
+        data.as_object_mut().unwrap().remove("input");
+        let old = Engine::import_from_json_headless(data.to_string());
+        assert_eq!(*old.input_map(), InputMap::defaults());
+    }
+}
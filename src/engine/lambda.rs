@@ -0,0 +1,230 @@
+use crate::engine::camera;
+use crate::engine::component::{ComponentState, ComponentTrait, ComponentWrapper, InputData, RenderOutput, TickVariant};
+use crate::engine::gameobject::GameObject;
+use crate::engine::error::{EngineError, EngineResult};
+use crate::engine::save::ComponentSaveLoad;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+type SharedTick = Arc<dyn Fn(&mut GameObject, Duration) + Send + Sync>;
+type SharedInputTick = Arc<dyn Fn(&InputData, &mut GameObject, Duration) + Send + Sync>;
+type SharedRenderTick = Arc<dyn Fn(&mut GameObject, Duration, camera::Camera) -> RenderOutput + Send + Sync>;
+
+#[derive(Clone)]
+enum Registered {
+    Default(SharedTick),
+    Input(SharedInputTick),
+    Render(SharedRenderTick),
+}
+
+lazy_static! {
+    static ref LAMBDAS: RwLock<HashMap<String, Registered>> = RwLock::new(HashMap::new());
+}
+
+/// Registers a closure [`LambdaComponent::named`] can refer to, so the component is saved.
+pub fn register_lambda(name: &str, tick: impl Fn(&mut GameObject, Duration) + Send + Sync + 'static) {
+    LAMBDAS.write().unwrap().insert(name.to_string(), Registered::Default(Arc::new(tick)));
+    info!("Lambda registered with name: {}", name);
+}
+
+pub fn register_input_lambda(name: &str, tick: impl Fn(&InputData, &mut GameObject, Duration) + Send + Sync + 'static) {
+    LAMBDAS.write().unwrap().insert(name.to_string(), Registered::Input(Arc::new(tick)));
+    info!("Input lambda registered with name: {}", name);
+}
+
+pub fn register_render_lambda(
+    name: &str,
+    tick: impl Fn(&mut GameObject, Duration, camera::Camera) -> RenderOutput + Send + Sync + 'static,
+) {
+    LAMBDAS.write().unwrap().insert(name.to_string(), Registered::Render(Arc::new(tick)));
+    info!("Render lambda registered with name: {}", name);
+}
+
+macro_rules! lambda_component {
+    (
+        $(#[$meta:meta])*
+        $component:ident, $variant:ident, ($($arg:ident: $arg_type:ty),*) $(-> $ret:ty)?, $noop:expr
+    ) => {
+        $(#[$meta])*
+        pub struct $component {
+            state: ComponentState,
+            // Registered name, lambdas without one aren't saved
+            key: Option<String>,
+            tick: Box<dyn FnMut($($arg_type),*) $(-> $ret)? + Send + Sync>,
+        }
+
+        impl $component {
+            /// Runs `tick` like the component's tick method. It isn't saved.
+            pub fn new(tick: impl FnMut($($arg_type),*) $(-> $ret)? + Send + Sync + 'static) -> ComponentWrapper {
+                Self::wrap(None, Box::new(tick))
+            }
+
+            /// Runs the closure registered under `name`, saved by its name and bound to
+            /// whatever is registered under it when loaded. Does nothing if no closure of
+            /// this kind is registered under `name`.
+            pub fn named(name: &str) -> ComponentWrapper {
+                Self::registered(name).unwrap_or_else(|_| {
+                    warn!("No {} registered with name: {}", stringify!($component), name);
+                    Self::wrap(Some(name.to_string()), Box::new(|$($arg),*| $noop))
+                })
+            }
+
+            fn registered(name: &str) -> EngineResult<ComponentWrapper> {
+                let registered = LAMBDAS.read().unwrap().get(name).cloned();
+                match registered {
+                    Some(Registered::$variant(tick)) => {
+                        Ok(Self::wrap(Some(name.to_string()), Box::new(move |$($arg),*| tick($($arg),*))))
+                    }
+                    Some(_) => Err(EngineError::InvalidSave(format!(
+                        "{} is registered for another kind of lambda than {}",
+                        name,
+                        stringify!($component)
+                    ))),
+                    None => Err(EngineError::UnknownComponent(format!("{} {}", stringify!($component), name))),
+                }
+            }
+
+            /// The registered name, if it has one.
+            pub fn key(&self) -> Option<&str> {
+                self.key.as_deref()
+            }
+
+            fn wrap(key: Option<String>, tick: Box<dyn FnMut($($arg_type),*) $(-> $ret)? + Send + Sync>) -> ComponentWrapper {
+                let component = Self { state: ComponentState::new(), key, tick };
+                ComponentWrapper::new(component, TickVariant::$variant(Self::run))
+            }
+
+            fn run(&mut self, $($arg: $arg_type),*) $(-> $ret)? {
+                (self.tick)($($arg),*)
+            }
+        }
+
+        impl ComponentTrait for $component {
+            fn name(&self) -> &str {
+                stringify!($component)
+            }
+
+            fn state(&mut self) -> &mut ComponentState {
+                &mut self.state
+            }
+
+            fn persistent(&self) -> bool {
+                self.key.is_some()
+            }
+
+            fn allow_multiple() -> bool {
+                true
+            }
+        }
+
+        impl ComponentSaveLoad for $component {
+            fn to_save_data(&mut self) -> Value {
+                json!({ "key": self.key })
+            }
+
+            fn from_save_data(data: Value) -> EngineResult<ComponentWrapper> {
+                let key = data["key"].as_str().ok_or_else(|| {
+                    EngineError::InvalidSave(format!("{} has no key", stringify!($component)))
+                })?;
+                Self::registered(key)
+            }
+        }
+    };
+}
+
+lambda_component!(
+    /// A component made of a closure, run every tick with the object and `dt`.
+    ///
+    /// ```ignore
+    /// obj.add_component(LambdaComponent::new(|obj, dt| { /* ... */ }))?;
+    /// ```
+    LambdaComponent, Default, (obj: &mut GameObject, dt: Duration), ()
+);
+
+lambda_component!(
+    /// Like [`LambdaComponent`], also given the input. Runs before the object's other components.
+    InputLambdaComponent, Input, (input: &InputData, obj: &mut GameObject, dt: Duration), ()
+);
+
+lambda_component!(
+    /// Like [`LambdaComponent`], run with the render components and returning what to draw.
+    RenderLambdaComponent, Render, (obj: &mut GameObject, dt: Duration, cam: camera::Camera) -> RenderOutput,
+    RenderOutput { obj: None }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::gameobject::make_base_game_object;
+    use crate::engine::save;
+    use crate::engine::state::Engine;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_lambdas_tick_with_their_flavour() {
+        let mut engine = Engine::new_headless();
+        let obj = engine.add_object(make_base_game_object(&engine.world(), "lambdas".to_string())).get();
+        let log = Arc::new(RwLock::new(Vec::new()));
+
+        let (default_log, input_log) = (log.clone(), log.clone());
+        let mut ticks = 0;
+        let mut lock = obj.lock().unwrap();
+        lock.add_component(LambdaComponent::new(move |obj, _| {
+            ticks += 1;
+            default_log.write().unwrap().push(format!("{} {}", obj.name(), ticks));
+        }))
+        .unwrap();
+        lock.add_component(LambdaComponent::new(|_, _| {})).unwrap();
+        lock.add_component(InputLambdaComponent::new(move |_, _, _| input_log.write().unwrap().push("input".to_string())))
+            .unwrap();
+        lock.add_component(RenderLambdaComponent::new(|_, _, _| RenderOutput { obj: None })).unwrap();
+        drop(lock);
+
+        engine.tick();
+        engine.tick();
+        assert_eq!(*log.read().unwrap(), vec!["input", "lambdas 1", "input", "lambdas 2"]);
+        assert_eq!(engine.render_queue().lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_named_lambdas_are_rebound_on_load() {
+        static TICKS: AtomicUsize = AtomicUsize::new(0);
+        save::init();
+        register_lambda("test_named_lambdas_are_rebound_on_load", |_, _| {
+            TICKS.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let mut engine = Engine::new_headless();
+        let obj = engine.add_object(make_base_game_object(&engine.world(), "saved".to_string())).get();
+        obj.lock().unwrap().add_component(LambdaComponent::named("test_named_lambdas_are_rebound_on_load")).unwrap();
+        obj.lock().unwrap().add_component(LambdaComponent::new(|_, _| {})).unwrap();
+
+        let data = engine.export();
+        let saved: Vec<&str> = data.objects[0].components.iter().map(|comp| comp.id.as_str()).collect();
+        assert_eq!(saved, vec!["Transform", "LambdaComponent"]);
+
+        let mut loaded = Engine::import_headless(data);
+        loaded.tick();
+        assert_eq!(TICKS.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_lambdas_that_cant_be_bound_fail_to_load() {
+        register_render_lambda("test_lambdas_that_cant_be_bound_fail_to_load", |_, _, _| RenderOutput { obj: None });
+
+        assert!(matches!(LambdaComponent::from_save_data(json!({})), Err(EngineError::InvalidSave(_))));
+        assert!(matches!(
+            LambdaComponent::from_save_data(json!({ "key": "test_lambdas_that_cant_be_bound_fail_to_load" })),
+            Err(EngineError::InvalidSave(_))
+        ));
+        assert_eq!(
+            LambdaComponent::from_save_data(json!({ "key": "missing" })).err(),
+            Some(EngineError::UnknownComponent("LambdaComponent missing".to_string()))
+        );
+        assert!(RenderLambdaComponent::from_save_data(json!({ "key": "test_lambdas_that_cant_be_bound_fail_to_load" })).is_ok());
+    }
+}
@@ -15,3 +15,21 @@ pub mod bounds;
 pub mod raycast;
 pub mod save;
 pub mod time;
+pub mod world;
+pub mod query;
+pub mod storage;
+pub mod system;
+pub mod event;
+pub mod transform;
+pub mod prefab;
+pub mod commands;
+pub mod timestep;
+pub mod timer;
+pub mod task;
+pub mod error;
+pub mod lambda;
+pub mod scene;
+pub mod pool;
+pub mod input;
+#[cfg(test)]
+mod bench;
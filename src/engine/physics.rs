@@ -1,12 +1,13 @@
 use crate::engine::collider::{Collider, Point};
 use crate::engine::component::{Rigidbody, Transform};
 use crate::engine::gameobject::{colliding_point, colliding_with, GameObject};
+use crate::engine::world::ObjectId;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
 pub struct PhysicsEngine {
-    pub game_objects: Vec<i32>,
+    pub game_objects: Vec<ObjectId>,
     pub friction: f32,
 }
 
@@ -18,10 +19,14 @@ impl PhysicsEngine {
         }
     }
 
-    pub fn add_object(&mut self, obj: i32) {
+    pub fn add_object(&mut self, obj: ObjectId) {
         self.game_objects.push(obj);
     }
 
+    pub fn remove_object(&mut self, obj: ObjectId) {
+        self.game_objects.retain(|id| *id != obj);
+    }
+
     pub fn tick(&mut self, dt: f32) {
         // const GRAVITY: f32 = -9.8; // Gravity constant
         //
@@ -0,0 +1,269 @@
+use crate::engine::error::{EngineError, EngineResult};
+use crate::engine::world::{ObjectId, World};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, Weak};
+
+/// How a pool has been used since it was created.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Acquires handed an object that was waiting in the pool
+    pub hits: u64,
+    /// Acquires that had to create a new object
+    pub misses: u64,
+    /// Most objects handed out at once
+    pub high_water: usize,
+    pub in_use: usize,
+    pub free: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PoolSaveData {
+    prefab: String,
+    free: Vec<ObjectId>,
+    in_use: Vec<ObjectId>,
+}
+
+#[derive(Default)]
+struct Pool {
+    free: Vec<ObjectId>,
+    in_use: HashSet<ObjectId>,
+    stats: PoolStats,
+}
+
+impl Pool {
+    // Objects destroyed behind the pool's back, e.g. by a scene change, are dropped
+    fn prune(&mut self, world: &World) {
+        self.free.retain(|id| world.contains(*id));
+        self.in_use.retain(|id| world.contains(*id));
+        self.stats.in_use = self.in_use.len();
+        self.stats.free = self.free.len();
+    }
+}
+
+/// Deactivated copies of prefabs waiting to be handed out again, one pool per prefab.
+/// Objects are taken out with [`Engine::acquire`](crate::engine::state::Engine::acquire)
+/// and put back with [`GameObject::release`](crate::engine::gameobject::GameObject::release)
+/// instead of being created and destroyed.
+pub struct Pools {
+    pools: Mutex<HashMap<String, Pool>>,
+    world: Weak<World>,
+}
+
+impl Pools {
+    pub(crate) fn new(world: Weak<World>) -> Self {
+        Self {
+            pools: Mutex::new(HashMap::new()),
+            world,
+        }
+    }
+
+    fn world(&self) -> Arc<World> {
+        self.world.upgrade().expect("Pools outlived their world")
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.pools.lock().unwrap().contains_key(name)
+    }
+
+    pub(crate) fn create(&self, name: &str) {
+        self.pools.lock().unwrap().entry(name.to_string()).or_default();
+    }
+
+    /// Adds a deactivated object to the free ones of `name`.
+    pub(crate) fn put_free(&self, name: &str, id: ObjectId) {
+        if let Some(pool) = self.pools.lock().unwrap().get_mut(name) {
+            pool.free.push(id);
+        }
+    }
+
+    /// Takes a free object out of `name`, `None` when every object is in use.
+    pub(crate) fn take_free(&self, name: &str) -> EngineResult<Option<ObjectId>> {
+        let world = self.world();
+        let mut pools = self.pools.lock().unwrap();
+        let pool = pools.get_mut(name).ok_or_else(|| EngineError::UnknownPool(name.to_string()))?;
+        pool.prune(&world);
+        Ok(pool.free.pop())
+    }
+
+    /// Marks `id` as handed out, `hit` when it came from the free ones.
+    pub(crate) fn hand_out(&self, name: &str, id: ObjectId, hit: bool) {
+        if let Some(pool) = self.pools.lock().unwrap().get_mut(name) {
+            pool.in_use.insert(id);
+            match hit {
+                true => pool.stats.hits += 1,
+                false => pool.stats.misses += 1,
+            }
+            pool.stats.high_water = pool.stats.high_water.max(pool.in_use.len());
+        }
+    }
+
+    /// Whether `id` was handed out by a pool and hasn't been released.
+    pub fn in_use(&self, id: ObjectId) -> bool {
+        self.pools.lock().unwrap().values().any(|pool| pool.in_use.contains(&id))
+    }
+
+    /// Puts `id` back with the free objects of its pool. It doesn't deactivate the
+    /// object, [`GameObject::release`](crate::engine::gameobject::GameObject::release)
+    /// does both.
+    pub fn release(&self, id: ObjectId) -> EngineResult<()> {
+        let mut pools = self.pools.lock().unwrap();
+        let pool = pools
+            .values_mut()
+            .find(|pool| pool.in_use.contains(&id))
+            .ok_or(EngineError::NotPooled(id))?;
+        pool.in_use.remove(&id);
+        pool.free.push(id);
+        Ok(())
+    }
+
+    pub fn stats(&self, name: &str) -> Option<PoolStats> {
+        let world = self.world();
+        let mut pools = self.pools.lock().unwrap();
+        let pool = pools.get_mut(name)?;
+        pool.prune(&world);
+        Some(pool.stats)
+    }
+
+    pub(crate) fn save(&self) -> Vec<PoolSaveData> {
+        let world = self.world();
+        let mut pools = self.pools.lock().unwrap();
+        let mut out: Vec<PoolSaveData> = pools
+            .iter_mut()
+            .map(|(name, pool)| {
+                pool.prune(&world);
+                let mut in_use: Vec<ObjectId> = pool.in_use.iter().copied().collect();
+                in_use.sort();
+                PoolSaveData { prefab: name.clone(), free: pool.free.clone(), in_use }
+            })
+            .collect();
+        out.sort_by(|a, b| a.prefab.cmp(&b.prefab));
+        out
+    }
+
+    /// Puts saved pools back, their stats start over.
+    pub(crate) fn restore(&self, pools: &[PoolSaveData]) {
+        let mut restored = self.pools.lock().unwrap();
+        for data in pools {
+            let pool = restored.entry(data.prefab.clone()).or_default();
+            pool.free.extend(data.free.iter().copied());
+            pool.in_use.extend(data.in_use.iter().copied());
+            pool.stats.in_use = pool.in_use.len();
+            pool.stats.free = pool.free.len();
+            pool.stats.high_water = pool.stats.high_water.max(pool.in_use.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::component::Transform;
+    use crate::engine::gameobject::{self, make_base_game_object};
+    use crate::engine::lambda::LambdaComponent;
+    use crate::engine::prefab::Prefab;
+    use crate::engine::save::{self, EngineSaveData};
+    use crate::engine::state::Engine;
+
+    fn bullet_prefab() -> Prefab {
+        save::init();
+        let template = World::new();
+        let bullet = make_base_game_object(&template, "bullet".to_string());
+        let id = bullet.lock().unwrap().id();
+        Prefab::from_object(&template, id).unwrap()
+    }
+
+    #[test]
+    fn test_pools_hand_out_and_take_back_objects() {
+        let mut engine = Engine::new_headless();
+        assert_eq!(engine.acquire("bullet"), Err(EngineError::UnknownPool("bullet".to_string())));
+        engine.register_prefab("bullet", bullet_prefab());
+        engine.create_pool("bullet", 2).unwrap();
+
+        let world = engine.world();
+        let active = |id| gameobject::to_object(&world, id, |obj| obj.state.active()).unwrap();
+        assert_eq!(engine.state().objects().len(), 2);
+        assert!(engine.state().objects().iter().all(|id| !active(*id)));
+
+        let first = engine.acquire("bullet").unwrap();
+        let second = engine.acquire("bullet").unwrap();
+        let third = engine.acquire("bullet").unwrap();
+        assert!(active(first) && active(second) && active(third));
+        assert_eq!(engine.state().objects().len(), 3);
+
+        engine.release(first).unwrap();
+        assert!(!active(first));
+        assert_eq!(engine.release(first), Err(EngineError::NotPooled(first)));
+
+        // Objects can put themselves back, e.g. from one of their components
+        gameobject::to_object(&world, second, |obj| obj.release()).unwrap().unwrap();
+        assert_eq!(engine.acquire("bullet").unwrap(), second);
+
+        engine.destroy(third);
+        assert_eq!(
+            engine.pool_stats("bullet"),
+            Some(PoolStats { hits: 3, misses: 1, high_water: 3, in_use: 1, free: 1 })
+        );
+    }
+
+    #[test]
+    fn test_reacquired_objects_dont_interpolate_from_where_they_were_released() {
+        let mut engine = Engine::new_headless();
+        engine.register_prefab("bullet", bullet_prefab());
+        engine.create_pool("bullet", 1).unwrap();
+        let world = engine.world();
+        let transform = |id, f: &mut dyn FnMut(&mut Transform)| {
+            gameobject::to_object(&world, id, |obj| obj.get_component_closure::<Transform>(|transform| f(transform))).unwrap();
+        };
+
+        let id = engine.acquire("bullet").unwrap();
+        engine.tick();
+        engine.release(id).unwrap();
+        engine.tick();
+
+        assert_eq!(engine.acquire("bullet").unwrap(), id);
+        transform(id, &mut |transform| transform.pos = [5.0, 0.0, 0.0]);
+        let mut pos = [0.0; 3];
+        transform(id, &mut |transform| pos = transform.interpolated(0.0).pos);
+        assert_eq!(pos, [5.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_pools_survive_a_save() {
+        let mut engine = Engine::new_headless();
+        engine.register_prefab("bullet", bullet_prefab());
+        engine.create_pool("bullet", 2).unwrap();
+        let acquired = engine.acquire("bullet").unwrap();
+
+        let mut engine = EngineSaveData::from_engine(&mut engine).to_headless_engine().unwrap();
+        engine.register_prefab("bullet", bullet_prefab());
+        assert_eq!(
+            engine.pool_stats("bullet"),
+            Some(PoolStats { hits: 0, misses: 0, high_water: 1, in_use: 1, free: 1 })
+        );
+
+        engine.release(acquired).unwrap();
+        engine.acquire("bullet").unwrap();
+        engine.acquire("bullet").unwrap();
+        assert_eq!(engine.state().objects().len(), 2);
+    }
+
+    #[test]
+    fn test_free_objects_sit_out_ticks() {
+        let mut engine = Engine::new_headless();
+        engine.register_prefab("bullet", bullet_prefab());
+        engine.create_pool("bullet", 1).unwrap();
+        let id = engine.acquire("bullet").unwrap();
+        let world = engine.world();
+
+        let ticks = Arc::new(Mutex::new(0));
+        let counter = ticks.clone();
+        let ticker = LambdaComponent::new(move |_, _| *counter.lock().unwrap() += 1);
+        gameobject::to_object(&world, id, |obj| obj.add_component(ticker)).unwrap().unwrap();
+
+        engine.tick();
+        engine.release(id).unwrap();
+        engine.tick();
+        assert_eq!(*ticks.lock().unwrap(), 1);
+    }
+}
@@ -0,0 +1,544 @@
+use crate::engine::error::{EngineError, EngineResult};
+use crate::engine::gameobject::{GameObject, MutexdGameObject};
+use crate::engine::save::{ComponentSaveData, GameObjectSaveData};
+use crate::engine::world::{ObjectId, World};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// A reusable object tree, stored in the same shape as saved objects. The root is the
+/// first object whose parent isn't part of the tree.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "PrefabRepr", into = "Vec<GameObjectSaveData>")]
+pub struct Prefab {
+    objects: Vec<GameObjectSaveData>,
+    root: usize,
+}
+
+// A prefab file holds either a single object or a whole tree.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PrefabRepr {
+    One(GameObjectSaveData),
+    Many(Vec<GameObjectSaveData>),
+}
+
+impl TryFrom<PrefabRepr> for Prefab {
+    type Error = String;
+
+    fn try_from(repr: PrefabRepr) -> Result<Self, Self::Error> {
+        let objects = match repr {
+            PrefabRepr::One(object) => vec![object],
+            PrefabRepr::Many(objects) => objects,
+        };
+        Prefab::new(objects)
+    }
+}
+
+impl From<Prefab> for Vec<GameObjectSaveData> {
+    fn from(prefab: Prefab) -> Self {
+        prefab.objects
+    }
+}
+
+impl Prefab {
+    pub fn new(objects: Vec<GameObjectSaveData>) -> Result<Self, String> {
+        let root = objects
+            .iter()
+            .position(|object| match object.parent {
+                Some(parent) => !objects.iter().any(|other| other.id == parent),
+                None => true,
+            })
+            .ok_or_else(|| "a prefab needs a root object".to_string())?;
+
+        Ok(Self { objects, root })
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    /// Snapshot of `id` and all of its descendants.
+    pub fn from_object(world: &World, id: ObjectId) -> Option<Self> {
+        let mut objects = Vec::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let obj = world.find_by_id(id)?;
+            let obj = obj.lock().unwrap();
+            stack.extend(obj.state.child_ids.iter().rev());
+            objects.push(GameObjectSaveData::from_game_object(&obj));
+        }
+
+        objects[0].parent = None;
+        Self::new(objects).ok()
+    }
+
+    fn root(&self) -> &GameObjectSaveData {
+        &self.objects[self.root]
+    }
+
+    fn get(&self, id: ObjectId) -> Option<&GameObjectSaveData> {
+        self.objects.iter().find(|object| object.id == id)
+    }
+
+    /// Builds a copy of the tree in `world` with fresh ids, `overrides` applied to the
+    /// root. Returns the template id to new id mapping and the new objects.
+    fn spawn(&self, world: &Arc<World>, overrides: &Overrides) -> EngineResult<(HashMap<ObjectId, ObjectId>, Vec<MutexdGameObject>)> {
+        let root = self.root().id;
+        spawn_copies(world, &self.objects, |template, data| {
+            if template.id == root {
+                overrides.apply(data);
+            }
+        })
+    }
+}
+
+/// Builds copies of saved objects in `world` with fresh ids, parents and children
+/// remapped to the copies. `edit` gets each template and its copy before it's built.
+pub(crate) fn spawn_copies(
+    world: &Arc<World>,
+    templates: &[GameObjectSaveData],
+    mut edit: impl FnMut(&GameObjectSaveData, &mut GameObjectSaveData),
+) -> EngineResult<(HashMap<ObjectId, ObjectId>, Vec<MutexdGameObject>)> {
+    let ids: HashMap<ObjectId, ObjectId> = templates.iter().map(|object| (object.id, world.next_id())).collect();
+
+    let objects: EngineResult<Vec<MutexdGameObject>> = templates
+        .iter()
+        .map(|template| {
+            let mut data = template.clone();
+            data.id = ids[&template.id];
+            data.parent = template.parent.and_then(|parent| ids.get(&parent).copied());
+            data.children = template.children.iter().filter_map(|child| ids.get(child).copied()).collect();
+            edit(template, &mut data);
+            data.to_game_object(world)
+        })
+        .collect();
+
+    if objects.is_err() {
+        // Don't leave the part of the tree that was built behind in the world, and
+        // give back the ids nothing was built under
+        for id in ids.values() {
+            world.destroy(*id);
+            world.release_id(*id);
+        }
+    }
+    Ok((ids, objects?))
+}
+
+/// Values that replace the template's on the root of one instance. Component fields
+/// are merged over the template's, so only the given fields change.
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+    name: Option<String>,
+    active: Option<bool>,
+    tags: Vec<String>,
+    layer: Option<u32>,
+    components: HashMap<String, Value>,
+}
+
+impl Overrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn active(mut self, active: bool) -> Self {
+        self.active = Some(active);
+        self
+    }
+
+    /// Adds a tag on top of the template's.
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    pub fn layer(mut self, layer: u32) -> Self {
+        self.layer = Some(layer);
+        self
+    }
+
+    /// Merges `fields` over the saved data of the root's first component called `name`.
+    pub fn component(mut self, name: &str, fields: Value) -> Self {
+        merge(self.components.entry(name.to_string()).or_insert(json!({})), &fields);
+        self
+    }
+
+    /// Shorthand for overriding the root's `Transform` position.
+    pub fn pos(self, pos: [f32; 3]) -> Self {
+        self.component("Transform", json!({ "pos": pos }))
+    }
+
+    fn apply(&self, data: &mut GameObjectSaveData) {
+        if let Some(name) = &self.name {
+            data.name = name.clone();
+        }
+        if let Some(active) = self.active {
+            data.active = active;
+        }
+        for tag in &self.tags {
+            if !data.tags.contains(tag) {
+                data.tags.push(tag.clone());
+            }
+        }
+        if let Some(layer) = self.layer {
+            data.layer = layer;
+        }
+        for (name, fields) in &self.components {
+            if let Some(component) = data.components.iter_mut().find(|component| &component.id == name) {
+                merge(&mut component.data, fields);
+            }
+        }
+    }
+
+    fn overrides_field(&self, component: &str, field: &str) -> bool {
+        self.components
+            .get(component)
+            .and_then(|fields| fields.get(field))
+            .is_some()
+    }
+}
+
+fn merge(target: &mut Value, patch: &Value) {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (key, value) in patch {
+                merge(target.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+        (target, patch) => *target = patch.clone(),
+    }
+}
+
+// Saved components get a fresh uuid every time, it never counts as a change.
+fn changed_fields(old: &Value, new: &Value) -> Vec<String> {
+    match new.as_object() {
+        Some(fields) => fields
+            .iter()
+            .filter(|(key, value)| *key != "uuid" && old.get(key.as_str()) != Some(*value))
+            .map(|(key, _)| key.clone())
+            .collect(),
+        None if old != new => vec![String::new()],
+        None => Vec::new(),
+    }
+}
+
+struct Instance {
+    prefab: String,
+    root: ObjectId,
+    ids: HashMap<ObjectId, ObjectId>,
+    overrides: Overrides,
+}
+
+/// Registered prefabs and the live instances of each, so template changes can be
+/// pushed to them.
+#[derive(Default)]
+pub struct Prefabs {
+    templates: HashMap<String, Prefab>,
+    instances: Vec<Instance>,
+}
+
+impl Prefabs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Prefab> {
+        self.templates.get(name)
+    }
+
+    /// Root ids of the live instances of `name`.
+    pub fn instances(&self, world: &World, name: &str) -> Vec<ObjectId> {
+        self.instances
+            .iter()
+            .filter(|instance| instance.prefab == name && world.contains(instance.root))
+            .map(|instance| instance.root)
+            .collect()
+    }
+
+    /// Registers `prefab` under `name`. When replacing a template, every field that
+    /// changed between the two is copied to the live instances, unless the instance
+    /// overrides it.
+    pub fn register(&mut self, world: &Arc<World>, name: &str, prefab: Prefab) {
+        self.instances.retain(|instance| world.contains(instance.root));
+
+        if let Some(old) = self.templates.insert(name.to_string(), prefab) {
+            let new = &self.templates[name];
+            for instance in self.instances.iter().filter(|instance| instance.prefab == name) {
+                info!("Updating instance {} of prefab {}", instance.root, name);
+                update_instance(world, &old, new, instance);
+            }
+        }
+    }
+
+    /// Builds a new instance of `name` in `world`, returning its root id and objects.
+    pub fn instantiate(
+        &mut self,
+        world: &Arc<World>,
+        name: &str,
+        overrides: Overrides,
+    ) -> EngineResult<(ObjectId, Vec<MutexdGameObject>)> {
+        let prefab = self.templates.get(name).ok_or_else(|| EngineError::UnknownPrefab(name.to_string()))?;
+        let (ids, objects) = prefab.spawn(world, &overrides)?;
+        let root = ids[&prefab.root().id];
+
+        self.instances.push(Instance {
+            prefab: name.to_string(),
+            root,
+            ids,
+            overrides,
+        });
+        Ok((root, objects))
+    }
+}
+
+fn update_instance(world: &World, old: &Prefab, new: &Prefab, instance: &Instance) {
+    for template in new.objects.iter() {
+        let (previous, id) = match (old.get(template.id), instance.ids.get(&template.id)) {
+            (Some(previous), Some(id)) if world.contains(*id) => (previous, *id),
+            _ => continue,
+        };
+        let overrides = if template.id == new.root().id { instance.overrides.clone() } else { Overrides::new() };
+
+        let obj = match world.find_by_id(id) {
+            Some(obj) => obj,
+            None => continue,
+        };
+        let mut obj = obj.lock().unwrap();
+
+        if previous.name != template.name && overrides.name.is_none() {
+            obj.update_name(template.name.clone());
+        }
+        if previous.active != template.active && overrides.active.is_none() {
+            obj.set_active(template.active);
+        }
+        if previous.layer != template.layer && overrides.layer.is_none() {
+            obj.set_layer(template.layer);
+        }
+        for tag in previous.tags.iter().filter(|tag| !template.tags.contains(tag) && !overrides.tags.contains(tag)) {
+            obj.remove_tag(tag);
+        }
+        for tag in template.tags.iter().filter(|tag| !previous.tags.contains(tag)) {
+            obj.add_tag(tag);
+        }
+        if previous.colliders != template.colliders {
+            obj.colliders = template
+                .colliders
+                .iter()
+                .map(|collider| Arc::new(Mutex::new(collider.to_collider())))
+                .collect();
+        }
+
+        update_components(&mut obj, &previous.components, &template.components, &overrides);
+    }
+}
+
+// Components are matched by name, then by position for types that allow several.
+// Changed types are rebuilt from their patched save data, a type keeps its old
+// components if any of the new ones fails to load.
+fn update_components(obj: &mut GameObject, old: &[ComponentSaveData], new: &[ComponentSaveData], overrides: &Overrides) {
+    let mut current: Vec<(TypeId, String, Value)> = Vec::new();
+    for storage in obj.storages() {
+        for (name, data) in storage.save(obj.id()) {
+            current.push((storage.component_type(), name, data));
+        }
+    }
+
+    let mut names: Vec<&String> = Vec::new();
+    for component in old.iter().chain(new) {
+        if !names.contains(&&component.id) {
+            names.push(&component.id);
+        }
+    }
+
+    for name in names {
+        let olds: Vec<&Value> = old.iter().filter(|c| &c.id == name).map(|c| &c.data).collect();
+        let news: Vec<&Value> = new.iter().filter(|c| &c.id == name).map(|c| &c.data).collect();
+        let existing: Vec<&(TypeId, String, Value)> = current.iter().filter(|(_, n, _)| n == name).collect();
+
+        let unchanged = olds.len() == news.len()
+            && olds.iter().zip(&news).all(|(old, new)| changed_fields(old, new).is_empty());
+        if unchanged {
+            continue;
+        }
+
+        let patched: Vec<ComponentSaveData> = news
+            .iter()
+            .enumerate()
+            .map(|(i, new)| {
+                let data = match (olds.get(i), existing.get(i)) {
+                    (Some(old), Some((_, _, data))) => {
+                        let mut data = data.clone();
+                        for field in changed_fields(old, new) {
+                            if field.is_empty() {
+                                data = (*new).clone();
+                            } else if !overrides.overrides_field(name, &field) {
+                                data[field.as_str()] = new[field.as_str()].clone();
+                            }
+                        }
+                        data
+                    }
+                    _ => (*new).clone(),
+                };
+                ComponentSaveData { id: name.clone(), data }
+            })
+            .collect();
+
+        let components = match patched.iter().map(|component| component.to_component()).collect::<EngineResult<Vec<_>>>() {
+            Ok(components) => components,
+            Err(err) => {
+                error!("Couldn't update {} on GameObject {}, keeping the old one: {}", name, obj.id(), err);
+                continue;
+            }
+        };
+
+        if let Some((type_id, _, _)) = existing.first() {
+            obj.remove_component_type(*type_id);
+        }
+        if let Err(err) = obj.add_components(components) {
+            error!("Couldn't update {} on GameObject {}: {}", name, obj.id(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::collider::OctagonCollider;
+    use crate::engine::component::Transform;
+    use crate::engine::components::RenderComponent;
+    use crate::engine::gameobject::{make_base_game_object, reparent};
+    use crate::engine::graphics_backend::primitives::Primitives;
+    use crate::engine::save;
+    use crate::engine::state::Engine;
+
+    fn enemy() -> Prefab {
+        let template = World::new();
+        let root = make_base_game_object(&template, "enemy".to_string());
+        let eye = make_base_game_object(&template, "eye".to_string());
+
+        let mut lock = root.lock().unwrap();
+        lock.add_tag("enemy");
+        lock.add_component(RenderComponent::new(Primitives::Octagon(0.1, [1.0, 0.0, 0.0]))).unwrap();
+        lock.add_collider(Arc::new(Mutex::new(OctagonCollider::new(0.1))));
+        lock.get_component_closure::<Transform>(|transform| transform.rot = [0.0, 0.0, 45.0]);
+        let root_id = lock.id();
+        drop(lock);
+
+        let eye_id = eye.lock().unwrap().id();
+        eye.lock().unwrap().get_component_closure::<Transform>(|transform| transform.pos = [0.0, 0.1, 0.0]);
+        reparent(&template, root_id, eye_id).unwrap();
+
+        Prefab::from_object(&template, root_id).unwrap()
+    }
+
+    fn transform(engine: &Engine, id: ObjectId) -> ([f32; 3], [f32; 3]) {
+        let obj = engine.world().find_by_id(id).unwrap();
+        let mut values = ([0.0; 3], [0.0; 3]);
+        obj.lock().unwrap().get_component_closure::<Transform>(|transform| values = (transform.pos, transform.rot));
+        values
+    }
+
+    #[test]
+    fn test_instances_get_fresh_ids_and_overrides() {
+        save::init();
+        let mut engine = Engine::new_headless();
+        engine.register_prefab("enemy", Prefab::from_json(&enemy().to_json()).unwrap());
+
+        let first = engine.instantiate("enemy", Overrides::new().name("first").pos([1.0, 2.0, 0.0])).unwrap();
+        let second = engine.instantiate("enemy", Overrides::new()).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(engine.world().len(), 4);
+        assert_eq!(engine.world().find_all_by_tag("enemy").len(), 2);
+
+        assert_eq!(transform(&engine, first), ([1.0, 2.0, 0.0], [0.0, 0.0, 45.0]));
+        assert_eq!(transform(&engine, second), ([0.0; 3], [0.0, 0.0, 45.0]));
+
+        let first = engine.world().find_by_id(first).unwrap();
+        let first = first.lock().unwrap();
+        assert_eq!(first.name(), "first");
+        assert_eq!(first.colliders().len(), 1);
+        assert!(first.has_component::<RenderComponent>());
+
+        let eye = engine.world().find_by_id(first.state.child_ids[0]).unwrap();
+        assert_eq!(eye.lock().unwrap().state.parent_id, Some(first.id()));
+        assert_eq!(engine.world().parents().get(&first.state.child_ids[0]), Some(&first.id()));
+
+        assert!(matches!(engine.instantiate("missing", Overrides::new()), Err(EngineError::UnknownPrefab(_))));
+    }
+
+    #[test]
+    fn test_failed_instances_give_their_ids_back() {
+        save::init();
+        let mut engine = Engine::new_headless();
+        engine.register_prefab("enemy", enemy());
+
+        let broken = Overrides::new().component("Transform", json!({ "pos": "nowhere" }));
+        assert!(engine.instantiate("enemy", broken).is_err());
+        engine.flush_destroyed();
+        assert_eq!(engine.world().len(), 0);
+
+        let enemy = engine.instantiate("enemy", Overrides::new()).unwrap();
+        let eye = engine.world().find_by_id(enemy).unwrap().lock().unwrap().state.child_ids[0];
+        let mut indices = vec![enemy.index, eye.index];
+        indices.sort();
+        assert_eq!(indices, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_instances_pick_up_template_changes() {
+        save::init();
+        let mut engine = Engine::new_headless();
+        engine.register_prefab("enemy", enemy());
+
+        let moved = engine.instantiate("enemy", Overrides::new()).unwrap();
+        let pinned = engine.instantiate("enemy", Overrides::new().component("Transform", json!({ "rot": [0.0, 0.0, 90.0] }))).unwrap();
+        engine.world().find_by_id(moved).unwrap().lock().unwrap().get_component_closure::<Transform>(|transform| transform.pos = [5.0, 0.0, 0.0]);
+
+        let mut changed = enemy();
+        changed.objects[changed.root].layer = 2;
+        for component in changed.objects[changed.root].components.iter_mut() {
+            if component.id == "Transform" {
+                component.data["rot"] = json!([0.0, 0.0, 180.0]);
+            }
+        }
+        engine.register_prefab("enemy", changed);
+
+        // Only the changed field is copied, and not over an override
+        assert_eq!(transform(&engine, moved), ([5.0, 0.0, 0.0], [0.0, 0.0, 180.0]));
+        assert_eq!(transform(&engine, pinned), ([0.0; 3], [0.0, 0.0, 90.0]));
+        assert_eq!(engine.world().find_in_layer(2).len(), 2);
+        assert!(engine.world().find_by_id(moved).unwrap().lock().unwrap().has_component::<RenderComponent>());
+        assert_eq!(engine.prefab_instances("enemy"), vec![moved, pinned]);
+    }
+
+    #[test]
+    fn test_template_changes_that_dont_load_leave_instances_alone() {
+        save::init();
+        let mut engine = Engine::new_headless();
+        engine.register_prefab("enemy", enemy());
+        let instance = engine.instantiate("enemy", Overrides::new().pos([1.0, 0.0, 0.0])).unwrap();
+
+        let mut broken = enemy();
+        for component in broken.objects[broken.root].components.iter_mut() {
+            if component.id == "Transform" {
+                component.data["rot"] = json!("sideways");
+            }
+        }
+        engine.register_prefab("enemy", broken);
+
+        assert_eq!(transform(&engine, instance), ([1.0, 0.0, 0.0], [0.0, 0.0, 45.0]));
+        assert!(engine.world().find_by_id(instance).unwrap().lock().unwrap().has_component::<RenderComponent>());
+    }
+}
@@ -0,0 +1,499 @@
+use crate::engine::component::ComponentTrait;
+use crate::engine::storage::{ComponentStorage, Ref, RefMut, SparseSet, Storage};
+use crate::engine::system::Access;
+use crate::engine::world::{ObjectId, World};
+use log::warn;
+use rayon::prelude::*;
+use std::any::{type_name, TypeId};
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+// A storage that is already borrowed, e.g. by a tick of the same type, counts as empty
+fn borrow_storage<T: ComponentTrait>(storage: &Option<Arc<Storage<T>>>) -> Option<RefMut<'_, SparseSet<T>>> {
+    let set = storage.as_ref()?.try_borrow_mut();
+    if set.is_none() {
+        warn!("Query can't borrow {}, it is already borrowed", type_name::<T>());
+    }
+    set
+}
+
+/// Matches like `T` but only borrows the storage for reading, yielding `&T`. Systems
+/// built from a query declare these as reads instead of writes.
+pub struct Read<T>(PhantomData<T>);
+
+/// One element of a query, either a required component `T` (yielding `&mut T`), a
+/// read-only one [`Read<T>`] (yielding `&T`) or an optional one `Option<T>` (yielding
+/// `Option<&mut T>`).
+pub trait QueryTerm {
+    type Storage;
+    type Guard<'a>;
+    type Item<'a>;
+
+    fn type_id() -> TypeId;
+    fn access(access: &mut Access);
+    fn storage(world: &World) -> Self::Storage;
+    fn borrow(storage: &Self::Storage) -> Self::Guard<'_>;
+    /// Objects that can match, `None` if this term doesn't narrow the search.
+    fn candidates(guard: &Self::Guard<'_>) -> Option<Vec<ObjectId>>;
+    fn item<'a>(guard: &'a mut Self::Guard<'_>, id: ObjectId) -> Option<Self::Item<'a>>;
+    /// [`QueryTerm::item`] for many objects at once, lined up with `ids`.
+    fn items<'a>(guard: &'a mut Self::Guard<'_>, ids: &[ObjectId]) -> Vec<Option<Self::Item<'a>>>;
+}
+
+fn owners<T>(set: Option<&SparseSet<T>>) -> Option<Vec<ObjectId>> {
+    let mut owners = set.map(|set| set.owners().to_vec()).unwrap_or_default();
+    owners.sort();
+    owners.dedup();
+    Some(owners)
+}
+
+impl<T: ComponentTrait> QueryTerm for T {
+    type Storage = Option<Arc<Storage<T>>>;
+    type Guard<'a> = Option<RefMut<'a, SparseSet<T>>>;
+    type Item<'a> = &'a mut T;
+
+    fn type_id() -> TypeId {
+        TypeId::of::<T>()
+    }
+
+    fn access(access: &mut Access) {
+        access.add_write(TypeId::of::<T>());
+    }
+
+    fn storage(world: &World) -> Self::Storage {
+        world.storage::<T>()
+    }
+
+    fn borrow(storage: &Self::Storage) -> Self::Guard<'_> {
+        borrow_storage(storage)
+    }
+
+    fn candidates(guard: &Self::Guard<'_>) -> Option<Vec<ObjectId>> {
+        owners(guard.as_deref())
+    }
+
+    fn item<'a>(guard: &'a mut Self::Guard<'_>, id: ObjectId) -> Option<Self::Item<'a>> {
+        guard.as_mut()?.get_mut(id)
+    }
+
+    fn items<'a>(guard: &'a mut Self::Guard<'_>, ids: &[ObjectId]) -> Vec<Option<Self::Item<'a>>> {
+        match guard.as_mut() {
+            Some(set) => set.get_many_mut(ids),
+            None => ids.iter().map(|_| None).collect(),
+        }
+    }
+}
+
+impl<T: ComponentTrait> QueryTerm for Read<T> {
+    type Storage = Option<Arc<Storage<T>>>;
+    type Guard<'a> = Option<Ref<'a, SparseSet<T>>>;
+    type Item<'a> = &'a T;
+
+    fn type_id() -> TypeId {
+        TypeId::of::<T>()
+    }
+
+    fn access(access: &mut Access) {
+        access.add_read(TypeId::of::<T>());
+    }
+
+    fn storage(world: &World) -> Self::Storage {
+        world.storage::<T>()
+    }
+
+    fn borrow(storage: &Self::Storage) -> Self::Guard<'_> {
+        let set = storage.as_ref()?.try_borrow();
+        if set.is_none() {
+            warn!("Query can't read {}, it is mutably borrowed", type_name::<T>());
+        }
+        set
+    }
+
+    fn candidates(guard: &Self::Guard<'_>) -> Option<Vec<ObjectId>> {
+        owners(guard.as_deref())
+    }
+
+    fn item<'a>(guard: &'a mut Self::Guard<'_>, id: ObjectId) -> Option<Self::Item<'a>> {
+        guard.as_ref()?.get(id)
+    }
+
+    fn items<'a>(guard: &'a mut Self::Guard<'_>, ids: &[ObjectId]) -> Vec<Option<Self::Item<'a>>> {
+        match guard.as_ref() {
+            Some(set) => set.get_many(ids),
+            None => ids.iter().map(|_| None).collect(),
+        }
+    }
+}
+
+impl<T: ComponentTrait> QueryTerm for Option<T> {
+    type Storage = Option<Arc<Storage<T>>>;
+    type Guard<'a> = Option<RefMut<'a, SparseSet<T>>>;
+    type Item<'a> = Option<&'a mut T>;
+
+    fn type_id() -> TypeId {
+        TypeId::of::<T>()
+    }
+
+    fn access(access: &mut Access) {
+        access.add_write(TypeId::of::<T>());
+    }
+
+    fn storage(world: &World) -> Self::Storage {
+        world.storage::<T>()
+    }
+
+    fn borrow(storage: &Self::Storage) -> Self::Guard<'_> {
+        borrow_storage(storage)
+    }
+
+    fn candidates(_guard: &Self::Guard<'_>) -> Option<Vec<ObjectId>> {
+        None
+    }
+
+    fn item<'a>(guard: &'a mut Self::Guard<'_>, id: ObjectId) -> Option<Self::Item<'a>> {
+        Some(guard.as_mut().and_then(|set| set.get_mut(id)))
+    }
+
+    fn items<'a>(guard: &'a mut Self::Guard<'_>, ids: &[ObjectId]) -> Vec<Option<Self::Item<'a>>> {
+        match guard.as_mut() {
+            Some(set) => set.get_many_mut(ids).into_iter().map(Some).collect(),
+            None => ids.iter().map(|_| Some(None)).collect(),
+        }
+    }
+}
+
+/// A tuple of [`QueryTerm`]s, e.g. `(Transform, Option<RenderComponent>)`.
+pub trait QueryParams {
+    type Storages;
+    type Guards<'a>;
+    type Items<'a>;
+
+    fn type_ids() -> Vec<TypeId>;
+    fn access() -> Access;
+    fn storages(world: &World) -> Self::Storages;
+    fn borrow(storages: &Self::Storages) -> Self::Guards<'_>;
+    fn candidates(guards: &Self::Guards<'_>) -> Option<Vec<ObjectId>>;
+    fn items<'a>(guards: &'a mut Self::Guards<'_>, id: ObjectId) -> Option<Self::Items<'a>>;
+    /// Items of every object in `ids` that matches, borrowed all at once.
+    fn items_many<'a>(guards: &'a mut Self::Guards<'_>, ids: &[ObjectId]) -> Vec<(ObjectId, Self::Items<'a>)>;
+}
+
+macro_rules! impl_query_params {
+    ($($term:ident $idx:tt),+) => {
+        impl<$($term: QueryTerm),+> QueryParams for ($($term,)+) {
+            type Storages = ($($term::Storage,)+);
+            type Guards<'a> = ($($term::Guard<'a>,)+);
+            type Items<'a> = ($($term::Item<'a>,)+);
+
+            fn type_ids() -> Vec<TypeId> {
+                vec![$($term::type_id()),+]
+            }
+
+            fn access() -> Access {
+                let mut access = Access::new();
+                $($term::access(&mut access);)+
+                access
+            }
+
+            fn storages(world: &World) -> Self::Storages {
+                ($($term::storage(world),)+)
+            }
+
+            fn borrow(storages: &Self::Storages) -> Self::Guards<'_> {
+                ($($term::borrow(&storages.$idx),)+)
+            }
+
+            fn candidates(guards: &Self::Guards<'_>) -> Option<Vec<ObjectId>> {
+                None::<Vec<ObjectId>>$(.or_else(|| $term::candidates(&guards.$idx)))+
+            }
+
+            fn items<'a>(guards: &'a mut Self::Guards<'_>, id: ObjectId) -> Option<Self::Items<'a>> {
+                Some(($($term::item(&mut guards.$idx, id)?,)+))
+            }
+
+            fn items_many<'a>(guards: &'a mut Self::Guards<'_>, ids: &[ObjectId]) -> Vec<(ObjectId, Self::Items<'a>)> {
+                let mut columns = ($($term::items(&mut guards.$idx, ids).into_iter(),)+);
+                ids.iter()
+                    .filter_map(|id| {
+                        let row = ($(columns.$idx.next()?,)+);
+                        Some((*id, ($(row.$idx?,)+)))
+                    })
+                    .collect()
+            }
+        }
+    };
+}
+
+impl_query_params!(A 0);
+impl_query_params!(A 0, B 1);
+impl_query_params!(A 0, B 1, C 2);
+impl_query_params!(A 0, B 1, C 2, D 3);
+impl_query_params!(A 0, B 1, C 2, D 3, E 4);
+impl_query_params!(A 0, B 1, C 2, D 3, E 4, F 5);
+
+/// Smallest number of objects [`Query::par_for_each`] hands to one thread.
+const BATCH_SIZE: usize = 256;
+
+/// Iterates every live object that has all the requested components. Built with
+/// [`Engine::query`](crate::engine::state::Engine::query) or [`World::query`].
+pub struct Query<Q: QueryParams> {
+    world: Arc<World>,
+    with: Vec<TypeId>,
+    without: Vec<TypeId>,
+    _params: PhantomData<Q>,
+}
+
+impl<Q: QueryParams> Query<Q> {
+    pub fn new(world: Arc<World>) -> Self {
+        let type_ids = Q::type_ids();
+        for (i, type_id) in type_ids.iter().enumerate() {
+            if type_ids[..i].contains(type_id) {
+                panic!("Query {} asks for the same component twice", type_name::<Q>());
+            }
+        }
+
+        Self {
+            world,
+            with: Vec::new(),
+            without: Vec::new(),
+            _params: PhantomData,
+        }
+    }
+
+    /// Only match objects that also have a `T`, without borrowing it.
+    pub fn with<T: ComponentTrait>(mut self) -> Self {
+        self.with.push(TypeId::of::<T>());
+        self
+    }
+
+    /// Skip objects that have a `T`.
+    pub fn without<T: ComponentTrait>(mut self) -> Self {
+        self.without.push(TypeId::of::<T>());
+        self
+    }
+
+    fn filter(&self) -> impl Fn(ObjectId) -> bool {
+        let world = self.world.clone();
+        let storages = |type_ids: &[TypeId]| -> Vec<Option<Arc<dyn ComponentStorage>>> {
+            type_ids.iter().map(|type_id| self.world.storage_by_type(*type_id)).collect()
+        };
+        let with = storages(&self.with);
+        let without = storages(&self.without);
+
+        move |id| {
+            let has = |storage: &Option<Arc<dyn ComponentStorage>>| {
+                storage.as_ref().is_some_and(|storage| storage.contains(id))
+            };
+            world.contains(id) && with.iter().all(has) && !without.iter().any(has)
+        }
+    }
+
+    /// Calls `f` with mutable access to the requested components of each match. Every
+    /// storage in the query stays borrowed until `f` has run for all matches.
+    pub fn for_each(&self, mut f: impl for<'a> FnMut(ObjectId, Q::Items<'a>)) {
+        let filter = self.filter();
+        let storages = Q::storages(&self.world);
+        let mut guards = Q::borrow(&storages);
+
+        let candidates = Q::candidates(&guards).unwrap_or_else(|| self.world.ids());
+        for id in candidates {
+            if !filter(id) {
+                continue;
+            }
+            if let Some(items) = Q::items(&mut guards, id) {
+                f(id, items);
+            }
+        }
+    }
+
+    /// Like [`Query::for_each`], but splits the matches into batches that run on the
+    /// rayon thread pool. `f` sees each object once and can't rely on the order.
+    pub fn par_for_each<F>(&self, f: F)
+    where
+        F: for<'a> Fn(ObjectId, Q::Items<'a>) + Send + Sync,
+        for<'a> Q::Items<'a>: Send,
+    {
+        let filter = self.filter();
+        let storages = Q::storages(&self.world);
+        let mut guards = Q::borrow(&storages);
+
+        let mut candidates = Q::candidates(&guards).unwrap_or_else(|| self.world.ids());
+        candidates.retain(|id| filter(*id));
+        Q::items_many(&mut guards, &candidates)
+            .into_par_iter()
+            .with_min_len(BATCH_SIZE)
+            .for_each(|(id, items)| f(id, items));
+    }
+
+    /// Same as [`Query::for_each`] for a single object, returns `None` if it doesn't match.
+    pub fn get(&self, id: ObjectId, f: impl for<'a> FnOnce(Q::Items<'a>)) -> Option<()> {
+        if !self.filter()(id) {
+            return None;
+        }
+
+        let storages = Q::storages(&self.world);
+        let mut guards = Q::borrow(&storages);
+        f(Q::items(&mut guards, id)?);
+        Some(())
+    }
+
+    pub fn ids(&self) -> Vec<ObjectId> {
+        let mut ids = Vec::new();
+        self.for_each(|id, _| ids.push(id));
+        ids
+    }
+
+    pub fn count(&self) -> usize {
+        self.ids().len()
+    }
+}
+
+impl World {
+    pub fn query<Q: QueryParams>(self: &Arc<Self>) -> Query<Q> {
+        Query::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::component::{ComponentState, ComponentWrapper, TickBehavior, TickVariant, Transform};
+    use crate::engine::components::RenderComponent;
+    use crate::engine::error::EngineResult;
+    use crate::engine::gameobject::{make_base_game_object, GameObject};
+    use crate::engine::graphics_backend::primitives::Primitives;
+    use crate::engine::save::ComponentSaveLoad;
+    use crate::engine::state::Engine;
+    use serde_json::Value;
+    use std::time::Duration;
+
+    // Queries its own type while it ticks
+    struct Counter {
+        state: ComponentState,
+        counters: Option<usize>,
+        transforms: Option<usize>,
+    }
+
+    impl Counter {
+        fn new() -> ComponentWrapper {
+            let component = Self { state: ComponentState::new(), counters: None, transforms: None };
+            ComponentWrapper::new(component, TickVariant::Default(Self::tick))
+        }
+    }
+
+    impl ComponentTrait for Counter {
+        fn name(&self) -> &str { "Counter" }
+        fn state(&mut self) -> &mut ComponentState { &mut self.state }
+    }
+
+    impl ComponentSaveLoad for Counter {
+        fn to_save_data(&mut self) -> Value { Value::Null }
+        fn from_save_data(_data: Value) -> EngineResult<ComponentWrapper> { Ok(Counter::new()) }
+    }
+
+    impl TickBehavior for Counter {
+        fn tick(&mut self, obj: &mut GameObject, _dt: Duration) {
+            let world = obj.world();
+            self.counters = Some(world.query::<(Counter,)>().ids().len());
+            self.transforms = Some(world.query::<(Read<Transform>, Option<Counter>)>().ids().len());
+        }
+    }
+
+    fn setup() -> (Arc<World>, ObjectId, ObjectId) {
+        let world = World::new();
+        let plain = make_base_game_object(&world, "plain".to_string());
+        let rendered = make_base_game_object(&world, "rendered".to_string());
+        rendered
+            .lock()
+            .unwrap()
+            .add_component(RenderComponent::new(Primitives::Cube(0.1, [1.0, 0.0, 0.0])))
+            .unwrap();
+
+        let plain_id = plain.lock().unwrap().id();
+        let rendered_id = rendered.lock().unwrap().id();
+        (world, plain_id, rendered_id)
+    }
+
+    #[test]
+    fn test_query_matches_all_required_components() {
+        let (world, plain, rendered) = setup();
+
+        assert_eq!(world.query::<(Transform,)>().ids(), vec![plain, rendered]);
+        assert_eq!(world.query::<(Transform, RenderComponent)>().ids(), vec![rendered]);
+    }
+
+    #[test]
+    fn test_query_gives_mutable_access() {
+        let (world, plain, _) = setup();
+
+        world.query::<(Transform,)>().for_each(|_, (transform,)| {
+            transform.pos[0] += 1.0;
+        });
+
+        let mut x = 0.0;
+        world.find_by_id(plain).unwrap().lock().unwrap().get_component_closure::<Transform>(|transform| {
+            x = transform.pos[0];
+        });
+        assert_eq!(x, 1.0);
+    }
+
+    #[test]
+    fn test_query_optional_and_filters() {
+        let (world, plain, rendered) = setup();
+
+        let mut seen = Vec::new();
+        world.query::<(Transform, Option<RenderComponent>)>().for_each(|id, (_, render)| {
+            seen.push((id, render.is_some()));
+        });
+        assert_eq!(seen, vec![(plain, false), (rendered, true)]);
+
+        assert_eq!(world.query::<(Transform,)>().without::<RenderComponent>().ids(), vec![plain]);
+        assert_eq!(world.query::<(Transform,)>().with::<RenderComponent>().ids(), vec![rendered]);
+        assert!(world.query::<(Transform,)>().get(plain, |_| {}).is_some());
+        assert!(world.query::<(RenderComponent,)>().get(plain, |_| {}).is_none());
+    }
+
+    #[test]
+    fn test_filters_work_while_the_filtered_storage_is_borrowed() {
+        let (world, plain, rendered) = setup();
+
+        // As it is while a RenderComponent ticks
+        let storage = world.storage::<RenderComponent>().unwrap();
+        let _ticking = storage.borrow_mut();
+        assert_eq!(world.query::<(Transform,)>().with::<RenderComponent>().ids(), vec![rendered]);
+        assert_eq!(world.query::<(Transform,)>().without::<RenderComponent>().ids(), vec![plain]);
+    }
+
+    #[test]
+    fn test_queries_from_a_tick_see_its_storage_as_empty() {
+        let mut engine = Engine::new_headless();
+        let world = engine.world();
+        let counter = engine.add_object(make_base_game_object(&world, "counter".to_string())).get();
+        engine.add_object(make_base_game_object(&world, "other".to_string()));
+        counter.lock().unwrap().add_component(Counter::new()).unwrap();
+
+        engine.tick();
+
+        let id = counter.lock().unwrap().id();
+        let counters = world.storage::<Counter>().unwrap();
+        let counters = counters.borrow();
+        let counter = counters.get(id).unwrap();
+        assert_eq!(counter.counters, Some(0));
+        assert_eq!(counter.transforms, Some(2));
+    }
+
+    #[test]
+    fn test_query_skips_destroyed_objects() {
+        let (world, plain, rendered) = setup();
+        world.destroy(rendered);
+
+        assert_eq!(world.query::<(Transform,)>().ids(), vec![plain]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_query_rejects_duplicate_components() {
+        let (world, _, _) = setup();
+        world.query::<(Transform, Option<Transform>)>();
+    }
+}
@@ -1,6 +1,6 @@
 use std::{sync::{Arc, Mutex}, f32::consts::PI};
 
-use super::{gameobject::{GameObject, self, GAME_OBJECT_REGISTRY}, collider::Point, collider, state::Engine, graphics_backend::primitives::{Primitives, Line, RaycastLine}};
+use super::{gameobject::{GameObject, self}, collider::Point, collider, state::Engine, world::{ObjectId, World}, graphics_backend::primitives::{Primitives, Line, RaycastLine}};
 
 #[derive(Debug)]
 pub struct CastError {
@@ -34,7 +34,7 @@ impl Raycast {
         // Line::new(initial_position, angle*(PI/180.0), length, thickness, [1.0, 0.0, 0.0])
     }
 
-    pub fn send(initial_position: Point, mut angle: f32, length: f32, ignore: Vec<i32>) -> Result<RaycastResult, CastError> {
+    pub fn send(world: &World, initial_position: Point, mut angle: f32, length: f32, ignore: Vec<ObjectId>) -> Result<RaycastResult, CastError> {
         let mut res = RaycastResult::new();
 
         angle *= (PI/180.0); // convert to radians
@@ -45,14 +45,17 @@ impl Raycast {
             z: 0.0, 
         };
         
-        for obj in gameobject::GAME_OBJECT_REGISTRY.try_lock().unwrap().iter() {
-            if gameobject::GAME_OBJECT_DESTROYED.lock().unwrap().contains(obj.0) {
-                continue;
-            }
-
-
-            if !ignore.contains(obj.0) {
-                let mut game_object = obj.1.try_lock().unwrap();
+        for obj in world.objects().iter() {
+            if !ignore.contains(&obj.0) {
+                // Objects locked further up the stack (e.g. the parent of the object
+                // casting, mid tick) can't be hit
+                let mut game_object = match obj.1.try_lock() {
+                    Ok(game_object) => game_object,
+                    Err(_) => continue,
+                };
+                if !game_object.state.active() {
+                    continue;
+                }
 
                 let mut line_segment = (initial_position.clone(), initial_position.clone() + (direction.clone() * length));
 
@@ -72,7 +75,6 @@ mod tests {
     use crate::engine::gameobject::{GameObject, GameObjectState};
     use crate::engine::collider::{Point, Collider};
     use std::sync::{Arc, Mutex};
-    use serial_test::serial;
 
     struct MockCollider {
         points: Vec<Point>,
@@ -90,25 +92,28 @@ mod tests {
         }
     }
 
-    #[serial]
+    #[test]
     fn test_raycast_no_collision() {
         let initial_position = Point { x: 0.0, y: 0.0, z: 0.0 };
         let angle = 0.0;
         let length = 10.0;
-        let ignore = vec![];
+        let ignore: Vec<ObjectId> = vec![];
+        let world = World::new();
 
-        let result = Raycast::send(initial_position, angle, length, ignore).unwrap();
+        let result = Raycast::send(&world, initial_position, angle, length, ignore).unwrap();
         assert_eq!(result.underlying.len(), 0);
     }
 
-    #[serial]
+    #[test]
     fn test_raycast_single_collision() {
         let initial_position = Point { x: 0.0, y: 0.0, z: 0.0 };
         let angle = 0.0;
         let length = 10.0;
-        let ignore = vec![];
+        let ignore: Vec<ObjectId> = vec![];
+        let world = World::new();
 
         let game_object = gameobject::make_base_game_object(
+            &world,
             "TestObject".to_string(),
         );
         game_object.lock().unwrap().add_collider(Arc::new(Mutex::new(MockCollider::new(
@@ -120,20 +125,22 @@ mod tests {
             ],
         ))));
 
-        let result = Raycast::send(initial_position, angle, length, ignore).unwrap();
+        let result = Raycast::send(&world, initial_position, angle, length, ignore).unwrap();
         assert_eq!(result.underlying.len(), 1);
 
         game_object.lock().unwrap().destroy();
     }
 
-    #[serial]
+    #[test]
     fn test_raycast_multiple_collisions() {
         let initial_position = Point { x: 0.0, y: 0.0, z: 0.0 };
         let angle = 0.0;
         let length = 10.0;
-        let ignore = vec![];
+        let ignore: Vec<ObjectId> = vec![];
+        let world = World::new();
 
         let game_object1 = gameobject::make_base_game_object(
+            &world,
             "TestObject1".to_string(),
         );
         game_object1.lock().unwrap().add_collider(Arc::new(Mutex::new(MockCollider::new(
@@ -146,6 +153,7 @@ mod tests {
         ))));
 
         let game_object2 = gameobject::make_base_game_object(
+            &world,
             "TestObject2".to_string(),
         );
         game_object2.lock().unwrap().add_collider(Arc::new(Mutex::new(MockCollider::new(
@@ -156,21 +164,21 @@ mod tests {
                 Point { x: 8.0, y: -1.0, z: 0.0 },
             ],
         ))));
-
-        let result = Raycast::send(initial_position, angle, length, ignore).unwrap();
+        let result = Raycast::send(&world, initial_position, angle, length, ignore).unwrap();
         assert_eq!(result.underlying.len(), 2);
         game_object1.lock().unwrap().destroy();
         game_object2.lock().unwrap().destroy();
     }
 
-    #[serial]
+    #[test]
     fn test_raycast_ignore_collision() {
         let initial_position = Point { x: 0.0, y: 0.0, z: 0.0 };
         let angle = 0.0;
         let length = 10.0;
-        let ignore = vec![1];
+        let world = World::new();
 
         let game_object1 = gameobject::make_base_game_object(
+            &world,
             "TestObject1".to_string(),
         );
 
@@ -184,6 +192,7 @@ mod tests {
         ))));
 
         let game_object2 = gameobject::make_base_game_object(
+            &world,
             "TestObject2".to_string(),
         );
         game_object2.lock().unwrap().add_collider(Arc::new(Mutex::new(MockCollider::new(
@@ -194,8 +203,9 @@ mod tests {
                 Point { x: 8.0, y: -1.0, z: 0.0 },
             ],
         ))));
+        let ignore = vec![game_object2.lock().unwrap().id()];
 
-        let result = Raycast::send(initial_position, angle, length, ignore).unwrap();
+        let result = Raycast::send(&world, initial_position, angle, length, ignore).unwrap();
         assert_eq!(result.underlying.len(), 1);
         game_object1.lock().unwrap().destroy();
         game_object2.lock().unwrap().destroy();
@@ -17,7 +17,14 @@ use lazy_static::lazy_static;
 use super::component::{TickVariant, InputTickBehavior, RenderTickBehavior, self, CharacterController2D};
 use super::components::{InputComponent, RenderComponent};
 use super::graphics_backend::primitives::Primitives;
+use super::lambda::{InputLambdaComponent, LambdaComponent, RenderLambdaComponent};
+use super::error::{EngineError, EngineResult};
 use super::state::Engine;
+use super::time::Time;
+use super::pool::PoolSaveData;
+use super::timer::TimerSaveData;
+use super::input::InputMap;
+use super::world::{ObjectId, World};
 pub use super::static_component::StaticComponent;
 use std::any::Any;
 use std::time::Duration;
@@ -197,14 +204,14 @@ impl<'de, T: Deserialize<'de> + Clone + Debug + Default + Send + Sync + ?Sized +
 // Define a trait for serialization and deserialization
 pub(crate) trait ComponentSaveLoad: Send + Sync + Downcast + std::any::Any {
     fn to_save_data(&mut self) -> Value;
-    fn from_save_data(data: Value) -> Arc<Mutex<ComponentWrapper>>
+    fn from_save_data(data: Value) -> EngineResult<ComponentWrapper>
     where
         Self: Sized;
 }
 
 pub(crate) trait StaticComponentSaveLoad: Send + Sync + Downcast + std::any::Any {
     fn to_save_data(&mut self) -> Value;
-    fn from_save_data(data: Value) -> Container
+    fn from_save_data(data: Value) -> EngineResult<Container>
     where
         Self: Sized;
 }
@@ -212,9 +219,34 @@ pub(crate) trait StaticComponentSaveLoad: Send + Sync + Downcast + std::any::Any
 impl_downcast!(ComponentSaveLoad);
 impl_downcast!(StaticComponentSaveLoad);
 
+/// A component type registered by `#[derive(Component)]`, loadable without calling [`init`].
+pub struct ComponentRegistration {
+    pub name: &'static str,
+    pub from_save_data: fn(Value) -> EngineResult<ComponentWrapper>,
+}
+
+inventory::collect!(ComponentRegistration);
+
+type ComponentConstructor = Box<dyn Fn(Value) -> EngineResult<ComponentWrapper> + Send + Sync>;
+type StaticComponentConstructor = Box<dyn Fn(Value) -> EngineResult<Arc<Mutex<dyn StaticComponent>>> + Send + Sync>;
+
+fn derived_components() -> HashMap<String, ComponentConstructor> {
+    inventory::iter::<ComponentRegistration>
+        .into_iter()
+        .map(|registration| {
+            info!("Component registered with name: {}", registration.name);
+            let constructor = registration.from_save_data;
+            (
+                registration.name.to_string(),
+                Box::new(constructor) as ComponentConstructor,
+            )
+        })
+        .collect()
+}
+
 lazy_static! {
-    static ref COMPONENT_REGISTRY: std::sync::RwLock<std::collections::HashMap<String, Box<dyn Fn(serde_json::Value) -> std::sync::Arc<std::sync::Mutex<ComponentWrapper>> + Send + Sync>>> = std::sync::RwLock::new(std::collections::HashMap::new());
-    static ref STATIC_COMPONENT_REGISTRY: RwLock<HashMap<String, Box<dyn Fn(Value) -> Arc<Mutex<dyn StaticComponent>> + Send + Sync>>> = RwLock::new(HashMap::new());
+    static ref COMPONENT_REGISTRY: RwLock<HashMap<String, ComponentConstructor>> = RwLock::new(derived_components());
+    static ref STATIC_COMPONENT_REGISTRY: RwLock<HashMap<String, StaticComponentConstructor>> = RwLock::new(HashMap::new());
     static ref LINK_REGISTRY: RwLock<HashMap<Uuid, Box<dyn std::any::Any + Send + Sync>>> = RwLock::new(HashMap::new());
     pub static ref UUID_REGISTRY: RwLock<HashMap<String, Uuid>> = RwLock::new(HashMap::new());
 }
@@ -260,6 +292,50 @@ pub fn get_link<T: 'static + Send + Sync + ?Sized>(id: Uuid) -> Option<Link<T>>
     }
 }
 
+/// Saves a link as the uuid of the static component it points to, for `#[save(link)]` fields.
+pub fn save_link<T: Clone + StaticComponent>(link: &mut Link<T>) -> Value {
+    let name = link.data.lock().unwrap().name();
+    let uuid = *UUID_REGISTRY.write().unwrap().entry(name).or_insert_with(Uuid::new_v4);
+    link.inc();
+    Value::String(uuid.to_string())
+}
+
+/// Loads a link saved by [`save_link`]. Static components are loaded after objects, so
+/// the link is synced to the static component by [`Link::get_data`].
+pub fn load_link<T: Default + Clone + StaticComponent>(data: &Value) -> Link<T> {
+    match data.as_str().and_then(|id| Uuid::parse_str(id).ok()) {
+        Some(id) => get_link(id).unwrap_or_else(|| {
+            let link = Link::new_with(Arc::new(Mutex::new(T::default())), id);
+            register_link(link.clone());
+            link
+        }),
+        None => {
+            warn!("Link missing from save data, linking to a new {}", std::any::type_name::<T>());
+            Link::new(Arc::new(Mutex::new(T::default())))
+        }
+    }
+}
+
+/// Saves a field of a derived component, fields that can't be saved are logged and saved as null.
+pub fn save_field<T: Serialize>(component: &str, field: &str, value: &T) -> Value {
+    serde_json::to_value(value).unwrap_or_else(|err| {
+        error!("Couldn't save {} of {}: {}", field, component, err);
+        Value::Null
+    })
+}
+
+/// Loads a field of a derived component saved by [`save_field`].
+pub fn load_field<T: serde::de::DeserializeOwned>(component: &str, field: &str, data: &Value) -> EngineResult<T> {
+    serde_json::from_value(data[field].clone())
+        .map_err(|err| EngineError::InvalidSave(format!("{} of {}: {}", field, component, err)))
+}
+
+/// Parses the saved data of the component called `name`, failing with
+/// [`EngineError::InvalidSave`] when it doesn't match the component's fields.
+pub fn parse_save_data<T: serde::de::DeserializeOwned>(name: &str, data: Value) -> EngineResult<T> {
+    serde_json::from_value(data).map_err(|err| EngineError::InvalidSave(format!("{}: {}", name, err)))
+}
+
 #[macro_export]
 macro_rules! impl_save_load_default {
     ($comp_type:ty, $save_struct:ident, { $( $field:ident : $field_type:ty ),* }, { $( $link_field:ident : $link_field_type:ty ),* }) => {
@@ -291,20 +367,20 @@ macro_rules! impl_save_load_default {
                 serde_json::to_value(save_data).unwrap()
             }
 
-            fn from_save_data(data: serde_json::Value) -> std::sync::Arc<std::sync::Mutex<ComponentWrapper>> {
-                let save_data: $save_struct = serde_json::from_value(data).unwrap();
-                let component = std::sync::Arc::new(std::sync::Mutex::new(Self {
+            fn from_save_data(data: serde_json::Value) -> $crate::engine::error::EngineResult<$crate::engine::component::ComponentWrapper> {
+                let save_data: $save_struct = $crate::engine::save::parse_save_data(stringify!($comp_type), data)?;
+                let component = Self {
                     $( $field: save_data.$field ),*,
                     $( $link_field: $crate::engine::save::get_link(save_data.$link_field.id.unwrap()).unwrap_or_else(|| {
                         let new_link = $crate::engine::save::Link::new(<$link_field_type>::default());
                         $crate::engine::save::register_link(new_link.clone());
                         new_link
                     })),*
-                }));
+                };
 
-                let ticker = std::sync::Arc::new(std::sync::Mutex::new(TickVariant::Default(component.clone() as std::sync::Arc<std::sync::Mutex<dyn $crate::engine::component::TickBehavior>>)));
+                let ticker = $crate::engine::component::TickVariant::Default(<Self as $crate::engine::component::TickBehavior>::tick);
 
-                std::sync::Arc::new(std::sync::Mutex::new($crate::engine::component::ComponentWrapper::new(component as std::sync::Arc<std::sync::Mutex<dyn $crate::engine::component::ComponentTrait>>, ticker)))
+                Ok($crate::engine::component::ComponentWrapper::new(component, ticker))
             }
         }
         $crate::engine::save::register_component::<$comp_type>(stringify!($comp_type));
@@ -346,10 +422,10 @@ macro_rules! impl_save_load_input {
                 serde_json::to_value(save_data).unwrap()
             }
 
-            fn from_save_data(data: serde_json::Value) -> std::sync::Arc<std::sync::Mutex<ComponentWrapper>> {
-                let save_data: $save_struct = serde_json::from_value(data).unwrap();
+            fn from_save_data(data: serde_json::Value) -> $crate::engine::error::EngineResult<$crate::engine::component::ComponentWrapper> {
+                let save_data: $save_struct = $crate::engine::save::parse_save_data(stringify!($comp_type), data)?;
 
-                let component = std::sync::Arc::new(std::sync::Mutex::new(Self {
+                let component = Self {
                     $( $field: save_data.$field ),*,
                     $( $link_field: $crate::engine::save::get_link(save_data.$link_field.id.unwrap()).unwrap_or_else(|| {
                         let new_link = $crate::engine::save::Link::new_with(Arc::new(Mutex::new(<$link_field_type>::default())), save_data.$link_field.id.unwrap());
@@ -358,11 +434,11 @@ macro_rules! impl_save_load_input {
 
                         new_link
                     })),*
-                }));
+                };
 
-                let ticker = std::sync::Arc::new(std::sync::Mutex::new(TickVariant::Input(component.clone() as std::sync::Arc<std::sync::Mutex<dyn $crate::engine::component::InputTickBehavior>>)));
+                let ticker = $crate::engine::component::TickVariant::Input(<Self as $crate::engine::component::InputTickBehavior>::tick_with_input);
 
-                std::sync::Arc::new(std::sync::Mutex::new($crate::engine::component::ComponentWrapper::new(component as std::sync::Arc<std::sync::Mutex<dyn $crate::engine::component::ComponentTrait>>, ticker)))
+                Ok($crate::engine::component::ComponentWrapper::new(component, ticker))
             }
         }
         $crate::engine::save::register_component::<$comp_type>(stringify!($comp_type));
@@ -401,20 +477,20 @@ macro_rules! impl_save_load_render {
                 serde_json::to_value(save_data).unwrap()
             }
 
-            fn from_save_data(data: serde_json::Value) -> std::sync::Arc<std::sync::Mutex<ComponentWrapper>> {
-                let save_data: $save_struct = serde_json::from_value(data).unwrap();
-                let component = std::sync::Arc::new(std::sync::Mutex::new(Self {
+            fn from_save_data(data: serde_json::Value) -> $crate::engine::error::EngineResult<$crate::engine::component::ComponentWrapper> {
+                let save_data: $save_struct = $crate::engine::save::parse_save_data(stringify!($comp_type), data)?;
+                let component = Self {
                     $( $field: save_data.$field ),*,
                     $( $link_field: $crate::engine::save::get_link(save_data.$link_field.id.unwrap()).unwrap_or_else(|| {
                         let new_link = $crate::engine::save::Link::new_with(<$link_field_type>::default(), save_data.$link_field.id.unwrap());
                         $crate::engine::save::register_link(new_link.clone());
                         new_link
                     })),*
-                }));
+                };
 
-                let ticker = std::sync::Arc::new(std::sync::Mutex::new(TickVariant::Render(component.clone() as std::sync::Arc<std::sync::Mutex<dyn $crate::engine::component::RenderTickBehavior>>)));
+                let ticker = $crate::engine::component::TickVariant::Render(<Self as $crate::engine::component::RenderTickBehavior>::render_tick);
 
-                std::sync::Arc::new(std::sync::Mutex::new($crate::engine::component::ComponentWrapper::new(component as std::sync::Arc<std::sync::Mutex<dyn $crate::engine::component::ComponentTrait>>, ticker)))
+                Ok($crate::engine::component::ComponentWrapper::new(component, ticker))
             }
         }
         $crate::engine::save::register_component::<$comp_type>(stringify!($comp_type));
@@ -462,8 +538,8 @@ macro_rules! impl_static_save_load {
                 serde_json::to_value(save_data).unwrap()
             }
 
-            fn from_save_data(data: serde_json::Value) -> $crate::engine::static_component::Container {
-                let save_data: $save_struct = serde_json::from_value(data).unwrap();
+            fn from_save_data(data: serde_json::Value) -> $crate::engine::error::EngineResult<$crate::engine::static_component::Container> {
+                let save_data: $save_struct = $crate::engine::save::parse_save_data(stringify!($comp_type), data)?;
                 let component = Arc::new(Mutex::new(Self {
                     $( $field: save_data.$field ),*,
                     $( $link_field: $crate::engine::save::get_link(save_data.$link_field.id.unwrap()).unwrap_or_else(|| {
@@ -486,9 +562,9 @@ macro_rules! impl_static_save_load {
                     warn!("Did not find link for {}", component.lock().unwrap().name());
                 }
 
-                $crate::engine::static_component::Container {
+                Ok($crate::engine::static_component::Container {
                     internal: component
-                }
+                })
             }
         }
 
@@ -510,13 +586,8 @@ macro_rules! impl_save_load {
 }
 
 pub fn init() {
-    impl_save_load!(
-        Transform, 
-        TransformSaveData, 
-        default, 
-        { pos: [f32; 3], rot: [f32; 3], state: ComponentState },
-        { }
-    );
+    // Saved by hand so older saves without a scale still load
+    register_component::<Transform>("Transform");
     
     impl_save_load!(
         CharacterController2D, 
@@ -541,6 +612,11 @@ pub fn init() {
         { name: String, obj: Primitives, state: ComponentState },
         { }
     );
+
+    // Only named lambdas are saved, they're rebound to their registered closure
+    register_component::<LambdaComponent>("LambdaComponent");
+    register_component::<InputLambdaComponent>("InputLambdaComponent");
+    register_component::<RenderLambdaComponent>("RenderLambdaComponent");
 }
 
 pub fn register_component<T: ComponentSaveLoad + 'static>(name: &str) {
@@ -548,9 +624,9 @@ pub fn register_component<T: ComponentSaveLoad + 'static>(name: &str) {
     info!("Registering component: {}", name);
     registry.insert(
         name.to_string(),
-        Box::new(|data: Value| -> Arc<Mutex<ComponentWrapper>> {
+        Box::new(|data: Value| -> EngineResult<ComponentWrapper> {
             T::from_save_data(data)
-        }) as Box<dyn Fn(Value) -> Arc<Mutex<ComponentWrapper>> + Send + Sync>,
+        }) as ComponentConstructor,
     );
     info!("Component registered with name: {}", name);
 }
@@ -559,9 +635,9 @@ pub fn register_static_component<T: StaticComponentSaveLoad + 'static>(name: &st
     let mut registry = STATIC_COMPONENT_REGISTRY.write().unwrap();
     registry.insert(
         name.to_string(),
-        Box::new(|data: Value| -> Arc<Mutex<dyn StaticComponent>> {
-            T::from_save_data(data).internal
-        }) as Box<dyn Fn(Value) -> Arc<Mutex<dyn StaticComponent>> + Send + Sync>,
+        Box::new(|data: Value| -> EngineResult<Arc<Mutex<dyn StaticComponent>>> {
+            Ok(T::from_save_data(data)?.internal)
+        }) as StaticComponentConstructor,
     );
     info!("Static component registered with name: {}", name);
 }
@@ -571,19 +647,35 @@ pub struct EngineSaveData {
     pub objects: Vec<GameObjectSaveData>,
     pub static_components: Vec<StaticComponentSaveData>,
     pub graphics: bool,
+    // Saves from before the engine clock existed start it at zero
+    #[serde(default)]
+    pub time: Time,
+    #[serde(default)]
+    pub timers: Vec<TimerSaveData>,
+    #[serde(default = "InputMap::defaults")]
+    pub input: InputMap,
+    #[serde(default)]
+    pub pools: Vec<PoolSaveData>,
 }
 
 impl EngineSaveData {
     pub fn from_engine(e: &mut Engine) -> EngineSaveData {
         info!("Converting engine state to save data.");
         Self {
-            objects: e.state.objects().iter_mut().map(|obj| {
-                GameObjectSaveData::from_game_object(&mut GameObject::find_by_id(*obj).expect("cannot find associated object").lock().unwrap())
+            // Objects destroyed earlier in the step are gone already
+            objects: e.state.objects().iter().filter_map(|obj| {
+                let obj = e.world.find_by_id(*obj)?;
+                let lock = obj.lock().unwrap();
+                Some(GameObjectSaveData::from_game_object(&lock))
             }).collect(),
-            static_components: e.state.static_components.iter().map(|static_comp| {
+            static_components: e.state.static_components().iter().map(|static_comp| {
                 StaticComponentSaveData::from_static_component(static_comp.clone())
             }).collect(),
             graphics: e.graphics,
+            time: e.time(),
+            timers: e.timers().save(),
+            input: e.input_map().clone(),
+            pools: e.world.pools().save(),
         }
     }
 
@@ -592,112 +684,157 @@ impl EngineSaveData {
         serde_json::to_string(&save).unwrap()
     }
 
-    pub async fn to_engine(&mut self) -> (Engine, EventLoop<()>) {
+    pub async fn to_engine(&mut self) -> EngineResult<(Engine, EventLoop<()>)> {
         info!("Restoring engine state from save data.");
         let event_loop = EventLoopBuilder::<()>::with_user_event().build();
         let mut engine = Engine::new(self.graphics, event_loop).await;
-    
+
+        self.restore_into(&mut engine.0)?;
+
+        Ok(engine)
+    }
+
+    pub fn to_headless_engine(&mut self) -> EngineResult<Engine> {
+        info!("Restoring headless engine state from save data.");
+        let mut engine = Engine::new_headless();
+
+        self.restore_into(&mut engine)?;
+
+        Ok(engine)
+    }
+
+    fn restore_into(&mut self, engine: &mut Engine) -> EngineResult<()> {
+        let world = engine.world();
+        world.set_time(self.time);
+        world.timers().restore(&self.timers);
+        world.pools().restore(&self.pools);
+        engine.set_input_map(self.input.clone());
+
         for obj in self.objects.iter_mut() {
-            engine.0.add_object(obj.to_game_object());
+            engine.add_object(obj.to_game_object(&world)?);
         }
 
         for static_comp in self.static_components.iter_mut() {
-            unsafe { engine.0.add_static(static_comp.to_static_component()) };
+            unsafe { engine.add_static(static_comp.to_static_component()?) };
         }
-
-        engine
+        Ok(())
     }
 
-    pub async fn to_engine_from_data(data: String) -> (Engine, EventLoop<()>) {
+    pub async fn to_engine_from_data(data: String) -> EngineResult<(Engine, EventLoop<()>)> {
         info!("Loading engine state from JSON data. (save.rs)");
-        let save = serde_json::from_str(&data).unwrap();
-        let mut e = serde_json::from_value::<Self>(save).unwrap();
+        let mut e = Self::from_json(&data)?;
         Self::to_engine(&mut e).await
     }
+
+    pub fn to_headless_engine_from_data(data: String) -> EngineResult<Engine> {
+        info!("Loading headless engine state from JSON data. (save.rs)");
+        let mut e = Self::from_json(&data)?;
+        Self::to_headless_engine(&mut e)
+    }
+
+    pub fn from_json(data: &str) -> EngineResult<Self> {
+        let save = serde_json::from_str::<Value>(data)?;
+        Ok(serde_json::from_value::<Self>(save)?)
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GameObjectSaveData {
-    components: Vec<ComponentSaveData>,
-    colliders: Vec<ColliderSaveData>,
-    parent: Option<i32>,
-    children: Vec<i32>,
-    id: i32,
-    name: String,
-    active: bool,
+    pub(crate) components: Vec<ComponentSaveData>,
+    pub(crate) colliders: Vec<ColliderSaveData>,
+    pub(crate) parent: Option<ObjectId>,
+    pub(crate) children: Vec<ObjectId>,
+    pub(crate) id: ObjectId,
+    pub(crate) name: String,
+    pub(crate) active: bool,
+    #[serde(default)]
+    pub(crate) tags: Vec<String>,
+    #[serde(default)]
+    pub(crate) layer: u32,
 }
 
 impl GameObjectSaveData {
     pub fn from_game_object(obj: &GameObject) -> Self {
         info!("Converting game object to save data. Object ID: {}", obj.id);
         GameObjectSaveData {
-            components: obj.components.iter().map(|c| ComponentSaveData::from_component(c.clone())).collect(),
-            colliders: obj.colliders.iter().map(|c| ColliderSaveData::from_collider(c.clone())).collect(),
+            components: ComponentSaveData::from_game_object(obj),
+            colliders: obj
+                .colliders
+                .iter()
+                .filter_map(|c| {
+                    ColliderSaveData::from_collider(c.clone())
+                        .map_err(|err| error!("{}, it won't be saved", err))
+                        .ok()
+                })
+                .collect(),
             parent: obj.state.parent_id,
             children: obj.state.child_ids.clone(),
             id: obj.id,
-            name: obj.name.clone(),
+            name: obj.name().to_string(),
             active: obj.state.active,
+            tags: obj.tags().iter().cloned().collect(),
+            layer: obj.layer(),
         }
     }
 
-    pub fn to_game_object(&self) -> Arc<Mutex<GameObject>> {
+    /// Fails without creating the object if any of its components isn't registered.
+    pub fn to_game_object(&self, world: &Arc<World>) -> EngineResult<Arc<Mutex<GameObject>>> {
         info!("Restoring game object from save data. Object ID: {}", self.id);
-        let obj = unsafe { GameObject::new(self.name.clone(), vec![], GameObjectState::new(self.active, self.parent, self.children.clone())) };
+        let components = self.components.iter().map(|comp| comp.to_component()).collect::<EngineResult<Vec<_>>>()?;
+        let obj = GameObject::new_with_id(world, self.id, self.name.clone(), vec![], GameObjectState::new(self.active, self.parent, self.children.clone()));
 
-        for comp in &self.components {
-            obj.lock().unwrap().add_component(comp.to_component());
+        // The rest of the object is still restored
+        if let Err(err) = obj.lock().unwrap().add_components(components) {
+            error!("GameObject {} was restored without some of its components: {}", self.id, err);
+        }
+
+        {
+            let mut lock = obj.lock().unwrap();
+            for tag in &self.tags {
+                lock.add_tag(tag);
+            }
+            lock.set_layer(self.layer);
         }
 
         for coll in &self.colliders {
             obj.lock().unwrap().add_collider(Arc::new(Mutex::new(coll.to_collider())));
         }
 
-        obj
+        Ok(obj)
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ComponentSaveData {
-    id: String,
-    data: Value,
+    pub(crate) id: String,
+    pub(crate) data: Value,
 }
 
 impl ComponentSaveData {
-    pub fn from_component(comp: Arc<Mutex<ComponentWrapper>>) -> Self {
-        let mut lock = match comp.try_lock() {
-            Ok(lock) => lock,
-            Err(_) => {
-                error!("Failed to acquire lock for component during save.");
-                panic!("couldn't get lock");
-            }
-        };
-
-        let comp_lock = (&mut *lock.component.lock().unwrap());
-
-        let (name, data) = {
-            let name = comp_lock.name().to_string();
-            let data = ComponentTrait::to_save_data(comp_lock);
-            (name, data)
-        };
-
-        info!("Component data saved. Component name: {}", name);
-        Self { id: name, data }
+    pub fn from_game_object(obj: &GameObject) -> Vec<Self> {
+        obj.storages()
+            .iter()
+            .flat_map(|storage| storage.save(obj.id))
+            .map(|(name, data)| {
+                info!("Component data saved. Component name: {}", name);
+                Self { id: name, data }
+            })
+            .collect()
     }
 
-    pub fn to_component(&self) -> Arc<Mutex<ComponentWrapper>> {
+    pub fn to_component(&self) -> EngineResult<ComponentWrapper> {
         let registry = COMPONENT_REGISTRY.read().unwrap();
         if let Some(constructor) = registry.get(&self.id) {
             info!("Restoring component from save data. Component name: {}", self.id);
             constructor(self.data.clone())
         } else {
             error!("Unknown component type: {}", self.id);
-            panic!("Unknown component type: {}", self.id);
+            Err(EngineError::UnknownComponent(self.id.clone()))
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct StaticComponentSaveData {
     id: String,
     data: Value,
@@ -711,19 +848,19 @@ impl StaticComponentSaveData {
         Self { id: lock.name(), data }
     }
 
-    pub fn to_static_component(&self) -> Arc<Mutex<dyn StaticComponent>> {
+    pub fn to_static_component(&self) -> EngineResult<Arc<Mutex<dyn StaticComponent>>> {
         let registry = STATIC_COMPONENT_REGISTRY.read().unwrap();
         if let Some(constructor) = registry.get(&self.id) {
             info!("Restoring static component from save data. Component name: {}", self.id);
             constructor(self.data.clone())
         } else {
             error!("Unknown static component type: {}", self.id);
-            panic!("Unknown static component type: {}", self.id);
+            Err(EngineError::UnknownStaticComponent(self.id.clone()))
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum ColliderType {
     CubeCollider { side_length: f32 },
     RectangularPrismCollider { width: f32, height: f32, depth: f32 },
@@ -731,13 +868,13 @@ pub enum ColliderType {
     OctagonCollider { size: f32 },
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ColliderSaveData {
     collider: ColliderType,
 }
 
 impl ColliderSaveData {
-    pub fn from_collider(collider: Arc<Mutex<Box<dyn Collider>>>) -> Self {
+    pub fn from_collider(collider: Arc<Mutex<Box<dyn Collider>>>) -> EngineResult<Self> {
         let lock = collider.lock().unwrap();
         let collider_type = if let Some(cube) = lock.downcast_ref::<CubeCollider>() {
             ColliderType::CubeCollider { side_length: cube.side_length }
@@ -748,12 +885,11 @@ impl ColliderSaveData {
         } else if let Some(octagon) = lock.downcast_ref::<OctagonCollider>() {
             ColliderType::OctagonCollider { size: octagon.size }
         } else {
-            error!("Unknown collider type encountered during save.");
-            panic!("Unknown collider type");
+            return Err(EngineError::UnknownCollider);
         };
         
         info!("Collider data saved. Collider type: {:?}", collider_type);
-        ColliderSaveData { collider: collider_type }
+        Ok(ColliderSaveData { collider: collider_type })
     }
 
     pub fn to_collider(&self) -> Box<dyn Collider> {
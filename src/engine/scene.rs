@@ -0,0 +1,311 @@
+use crate::engine::error::EngineResult;
+use crate::engine::save::{EngineSaveData, GameObjectSaveData, StaticComponentSaveData};
+use crate::engine::state::Engine;
+use crate::engine::static_component::StaticComponent;
+use crate::engine::world::{ObjectId, World};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+pub(crate) type SceneHook = Arc<dyn Fn(&mut Engine) + Send + Sync>;
+
+/// How [`Engine::load_scene`] treats the scenes that are already loaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadMode {
+    /// Unloads every loaded scene first, objects kept with
+    /// [`Engine::dont_destroy_on_load`] stay.
+    Single,
+    /// Loads next to the scenes that are already loaded.
+    Additive,
+}
+
+/// Objects and static components that are loaded and unloaded together. Scenes hold
+/// saved objects, which get fresh ids every time the scene loads, and hooks that run
+/// when it's loaded or unloaded. Anything a load hook adds belongs to the scene, so
+/// scenes can also be built in code.
+#[derive(Clone, Default)]
+pub struct Scene {
+    objects: Vec<GameObjectSaveData>,
+    static_components: Vec<StaticComponentSaveData>,
+    pub(crate) on_load: Vec<SceneHook>,
+    pub(crate) on_unload: Vec<SceneHook>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A scene holding the objects and static components of a save.
+    pub fn from_save(data: EngineSaveData) -> Self {
+        Self {
+            objects: data.objects,
+            static_components: data.static_components,
+            ..Self::default()
+        }
+    }
+
+    pub fn from_json(data: &str) -> EngineResult<Self> {
+        Ok(Self::from_save(EngineSaveData::from_json(data)?))
+    }
+
+    /// Runs `hook` after the scene's objects and static components are added.
+    pub fn on_load(mut self, hook: impl Fn(&mut Engine) + Send + Sync + 'static) -> Self {
+        self.on_load.push(Arc::new(hook));
+        self
+    }
+
+    /// Runs `hook` before the scene's objects and static components are removed.
+    pub fn on_unload(mut self, hook: impl Fn(&mut Engine) + Send + Sync + 'static) -> Self {
+        self.on_unload.push(Arc::new(hook));
+        self
+    }
+
+    pub(crate) fn objects(&self) -> &[GameObjectSaveData] {
+        &self.objects
+    }
+
+    pub(crate) fn static_components(&self) -> &[StaticComponentSaveData] {
+        &self.static_components
+    }
+}
+
+pub(crate) struct LoadedScene {
+    pub(crate) name: String,
+    pub(crate) objects: Vec<ObjectId>,
+    pub(crate) static_components: Vec<Arc<Mutex<dyn StaticComponent>>>,
+}
+
+/// Registered scenes, the ones that are loaded and what belongs to each. Objects and
+/// static components are added to the active scene, the ones added while no scene is
+/// active don't belong to any and are never unloaded.
+#[derive(Default)]
+pub struct Scenes {
+    templates: HashMap<String, Scene>,
+    // In the order they were loaded
+    pub(crate) loaded: Vec<LoadedScene>,
+    pub(crate) active: Option<String>,
+    persistent: HashSet<ObjectId>,
+    // Loads and unloads asked for while the engine was stepping
+    pub(crate) pending: Vec<(String, Option<LoadMode>)>,
+}
+
+impl Scenes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, name: &str, scene: Scene) {
+        self.templates.insert(name.to_string(), scene);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Scene> {
+        self.templates.get(name)
+    }
+
+    /// Names of the loaded scenes, in the order they were loaded.
+    pub fn loaded(&self) -> Vec<String> {
+        self.loaded.iter().map(|scene| scene.name.clone()).collect()
+    }
+
+    pub fn is_loaded(&self, name: &str) -> bool {
+        self.loaded.iter().any(|scene| scene.name == name)
+    }
+
+    pub fn active(&self) -> Option<&str> {
+        self.active.as_deref()
+    }
+
+    /// Ids of the objects that belong to `name`, including destroyed ones until the
+    /// end of the tick.
+    pub fn objects(&self, name: &str) -> Vec<ObjectId> {
+        self.find(name).map(|scene| scene.objects.clone()).unwrap_or_default()
+    }
+
+    /// The scene `id` belongs to, if any.
+    pub fn scene_of(&self, id: ObjectId) -> Option<&str> {
+        self.loaded
+            .iter()
+            .find(|scene| scene.objects.contains(&id))
+            .map(|scene| scene.name.as_str())
+    }
+
+    pub(crate) fn find(&self, name: &str) -> Option<&LoadedScene> {
+        self.loaded.iter().find(|scene| scene.name == name)
+    }
+
+    fn active_scene(&mut self) -> Option<&mut LoadedScene> {
+        let active = self.active.as_ref()?;
+        self.loaded.iter_mut().find(|scene| &scene.name == active)
+    }
+
+    pub(crate) fn track_object(&mut self, id: ObjectId) {
+        if let Some(scene) = self.active_scene() {
+            scene.objects.push(id);
+        }
+    }
+
+    pub(crate) fn track_static(&mut self, comp: Arc<Mutex<dyn StaticComponent>>) {
+        if let Some(scene) = self.active_scene() {
+            scene.static_components.push(comp);
+        }
+    }
+
+    pub(crate) fn forget_object(&mut self, id: ObjectId) {
+        self.persistent.remove(&id);
+        for scene in self.loaded.iter_mut() {
+            scene.objects.retain(|object| *object != id);
+        }
+    }
+
+    /// Moves `id` out of its scene so it survives unloads.
+    pub(crate) fn keep(&mut self, id: ObjectId) {
+        for scene in self.loaded.iter_mut() {
+            scene.objects.retain(|object| *object != id);
+        }
+        self.persistent.insert(id);
+    }
+
+    /// Whether `id` or one of its parents was kept with [`Scenes::keep`].
+    pub(crate) fn is_persistent(&self, world: &World, id: ObjectId) -> bool {
+        let mut current = Some(id);
+        while let Some(id) = current {
+            if self.persistent.contains(&id) {
+                return true;
+            }
+            current = world.find_by_id(id).and_then(|obj| obj.lock().unwrap().state.parent_id);
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::error::EngineError;
+    use crate::engine::gameobject::make_base_game_object;
+    use crate::engine::save::StaticComponentSaveLoad;
+    use crate::engine::static_component::Container;
+    use serde_json::Value;
+
+    // Switches to the game over scene the first time it ticks
+    #[derive(Debug, Default)]
+    struct GameOver {
+        ticks: u32,
+    }
+
+    impl StaticComponentSaveLoad for GameOver {
+        fn to_save_data(&mut self) -> Value {
+            Value::Null
+        }
+
+        fn from_save_data(_data: Value) -> EngineResult<Container> {
+            Ok(Container { internal: Arc::new(Mutex::new(GameOver::default())) })
+        }
+    }
+
+    impl StaticComponent for GameOver {
+        fn tick(&mut self, engine: &mut Engine) {
+            self.ticks += 1;
+            if self.ticks == 1 {
+                engine.load_scene("game_over", LoadMode::Single).unwrap();
+                assert_eq!(engine.scenes().loaded(), vec!["level", "hud"]);
+            }
+        }
+
+        fn name(&mut self) -> String {
+            "GameOver".to_string()
+        }
+    }
+
+    fn names(engine: &Engine) -> Vec<String> {
+        let world = engine.world();
+        engine.state().objects().iter().map(|id| world.find_by_id(*id).unwrap().lock().unwrap().name().to_string()).collect()
+    }
+
+    fn logged(log: &Arc<Mutex<Vec<String>>>, line: &str) -> impl Fn(&mut Engine) + Send + Sync + 'static {
+        let (log, line) = (log.clone(), line.to_string());
+        move |_| log.lock().unwrap().push(line.clone())
+    }
+
+    #[test]
+    fn test_single_loads_replace_scenes_but_not_persistent_objects() {
+        let mut engine = Engine::new_headless();
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let mut saved = Engine::new_headless();
+        saved.add_object(make_base_game_object(&saved.world(), "player".to_string()));
+        let level = Scene::from_json(&saved.export_raw()).unwrap();
+
+        let menu_log = log.clone();
+        engine.register_scene(
+            "menu",
+            Scene::new()
+                .on_load(move |engine| {
+                    menu_log.lock().unwrap().push("menu loaded".to_string());
+                    let world = engine.world();
+                    engine.add_object(make_base_game_object(&world, "button".to_string()));
+                    let music = engine.add_object(make_base_game_object(&world, "music".to_string())).get_id();
+                    engine.dont_destroy_on_load(music);
+                })
+                .on_unload(logged(&log, "menu unloaded")),
+        );
+        engine.register_scene("level", level.on_load(logged(&log, "level loaded")));
+
+        engine.add_object(make_base_game_object(&engine.world(), "camera".to_string()));
+        engine.load_scene("menu", LoadMode::Single).unwrap();
+        assert_eq!(names(&engine), vec!["camera", "button", "music"]);
+        assert_eq!(engine.scenes().active(), Some("menu"));
+
+        engine.load_scene("level", LoadMode::Single).unwrap();
+        engine.load_scene("level", LoadMode::Single).unwrap();
+        assert_eq!(names(&engine), vec!["camera", "music", "player"]);
+        assert_eq!(engine.scenes().loaded(), vec!["level"]);
+        assert_eq!(*log.lock().unwrap(), vec!["menu loaded", "menu unloaded", "level loaded", "level loaded"]);
+
+        let player = engine.state().objects()[2];
+        assert_eq!(engine.scenes().scene_of(player), Some("level"));
+        assert_ne!(player, saved.state().objects()[0]);
+
+        assert_eq!(engine.load_scene("credits", LoadMode::Single), Err(EngineError::UnknownScene("credits".to_string())));
+        assert_eq!(engine.unload_scene("menu"), Err(EngineError::SceneNotLoaded("menu".to_string())));
+    }
+
+    #[test]
+    fn test_additive_scenes_and_changes_while_stepping() {
+        let mut engine = Engine::new_headless();
+        engine.register_scene(
+            "level",
+            Scene::new().on_load(|engine| {
+                engine.add_object(make_base_game_object(&engine.world(), "enemy".to_string()));
+            }),
+        );
+        engine.register_scene(
+            "hud",
+            Scene::new().on_load(|engine| {
+                engine.add_object(make_base_game_object(&engine.world(), "health".to_string()));
+                unsafe { engine.add_static(Arc::new(Mutex::new(GameOver::default()))) };
+            }),
+        );
+        engine.register_scene("game_over", Scene::new());
+
+        engine.load_scene("level", LoadMode::Single).unwrap();
+        engine.load_scene("hud", LoadMode::Additive).unwrap();
+        assert_eq!(engine.scenes().active(), Some("level"));
+        assert_eq!(names(&engine), vec!["enemy", "health"]);
+
+        // Objects added later go to the active scene
+        let spawned = engine.add_object(make_base_game_object(&engine.world(), "bullet".to_string())).get_id();
+        assert_eq!(engine.scenes().scene_of(spawned), Some("level"));
+
+        engine.unload_scene("hud").unwrap();
+        assert_eq!(names(&engine), vec!["enemy", "bullet"]);
+        assert!(engine.state().static_components().is_empty());
+
+        engine.load_scene("hud", LoadMode::Additive).unwrap();
+        engine.tick();
+        assert_eq!(engine.scenes().loaded(), vec!["game_over"]);
+        assert!(engine.state().objects().is_empty());
+        assert!(engine.state().static_components().is_empty());
+        engine.tick();
+    }
+}
@@ -4,9 +4,9 @@ use crate::engine::gameobject;
 use crate::engine::renderer;
 use crate::engine::static_component;
 use std::fmt::Display;
+use std::future::Future;
 use std::fmt::Formatter;
 use std::ops::Index;
-use std::process::exit;
 use std::ptr::NonNull;
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Mutex};
@@ -20,6 +20,7 @@ use winit::window;
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop, EventLoopProxy},
+    platform::run_return::EventLoopExtRunReturn,
     window::{Window, WindowBuilder, WindowId},
 };
 
@@ -37,6 +38,21 @@ use super::save::Link;
 use super::save::{EngineSaveData, StaticComponent};
 // use super::static_component::StaticComponent;
 use super::ui::UIElement;
+use super::query::{Query, QueryParams};
+use super::prefab::{self, Overrides, Prefab, Prefabs};
+use super::input::InputMap;
+use super::pool::PoolStats;
+use super::scene::{LoadMode, LoadedScene, Scene, Scenes};
+use super::system::{Scheduler, Stage, Step, System};
+use super::event::EventBus;
+use super::error::{EngineError, EngineResult};
+use super::commands::Commands;
+use super::time::Time;
+use super::task::{TaskContext, TaskHandle};
+use super::timer::{TimerHandle, Timers};
+use super::timestep::FixedTimestep;
+use super::transform;
+use super::world::{ObjectId, World};
 
 #[derive(Debug, Clone)]
 pub enum AppEvent {
@@ -95,8 +111,10 @@ impl AppEvent {
 }
 
 pub struct EngineState {
-    objects: Vec<i32>,
-    pub static_components: Vec<Arc<Mutex<dyn static_component::StaticComponent>>>,
+    objects: Vec<ObjectId>,
+    // Only ever appended to outside of `Engine::remove_static`, the scheduler lines its
+    // statics up with this list by position
+    static_components: Vec<Arc<Mutex<dyn static_component::StaticComponent>>>,
 }
 
 impl EngineState {
@@ -107,14 +125,22 @@ impl EngineState {
         }
     }
 
-    pub fn objects(&self) -> Vec<i32> {
+    pub fn objects(&self) -> Vec<ObjectId> {
         self.objects.clone()
     }
 
-    pub fn add_object(&mut self, obj: i32) {
+    pub fn add_object(&mut self, obj: ObjectId) {
         self.objects.push(obj);
     }
 
+    pub fn remove_object(&mut self, obj: ObjectId) {
+        self.objects.retain(|id| *id != obj);
+    }
+
+    pub fn static_components(&self) -> &[Arc<Mutex<dyn static_component::StaticComponent>>] {
+        &self.static_components
+    }
+
     pub fn add_static(&mut self, obj: Arc<Mutex<dyn static_component::StaticComponent>>) {
         self.static_components.push(obj);
     }
@@ -129,41 +155,59 @@ impl EngineState {
     }
 }
 
+// How often `Engine::run` updates the render queue when no simulation step is due
+const RENDER_INTERVAL: Duration = Duration::from_micros(1_000_000 / 144);
+
 pub struct FrameData {
     pub dt: Option<Duration>,
 }
 
 pub struct Engine {
     pub state: EngineState,
-    pub renderer: Arc<Mutex<renderer::Renderer>>,
+    pub world: Arc<World>,
+    pub renderer: Option<Arc<Mutex<renderer::Renderer>>>,
     pub graphics: bool,
-    pub event_loop_proxy: EventLoopProxy<()>,
+    pub event_loop_proxy: Option<EventLoopProxy<()>>,
     render_handle: Option<JoinHandle<()>>,
+    render_queue: Arc<Mutex<Vec<component::RenderOutput>>>,
+    camera: camera::Camera,
     event_tx: Option<Sender<AppEvent>>,
     control_rx: Option<Receiver<ControlFlow>>,
     frame_data_rx: Option<Receiver<FrameData>>,
-    win_id: WindowId,
+    win_id: Option<WindowId>,
+    /// Overrides the simulation step handed to components and systems when set.
     pub dt: Option<Duration>,
+    timestep: FixedTimestep,
+    frame_time: Duration,
     keys_pressed: Vec<winit::event::VirtualKeyCode>,
     mouse_buttons_pressed: Vec<winit::event::MouseButton>,
     mouse_position: (f64, f64),
+    input_map: Arc<InputMap>,
     pub physics_engine: PhysicsEngine,
     pub paused: bool,
+    scheduler: Scheduler,
+    prefabs: Prefabs,
+    scenes: Scenes,
+    // Scene changes asked for while set wait for the end of the step
+    stepping: bool,
+    quitting: bool,
+    shut_down: bool,
 }
 
 unsafe impl Send for Engine {}
 unsafe impl Sync for Engine {}
 
 pub struct ToLockGameObj {
-    id: i32
+    id: ObjectId,
+    world: Arc<World>,
 }
 
 impl ToLockGameObj {
     pub fn get(&self) -> Arc<Mutex<GameObject>> {
-        GameObject::find_by_id(self.id).unwrap().clone()
+        self.world.find_by_id(self.id).unwrap().clone()
     }
 
-    pub fn get_id(&self) -> i32 {
+    pub fn get_id(&self) -> ObjectId {
         self.id
     }
 }
@@ -174,38 +218,80 @@ impl Engine {
 
         let (mut renderer_instance, window_id) =
             renderer::Renderer::new(String::from("Engine"), 800, 600, &event_loop).await;
+        let render_queue = renderer_instance.render_queue.clone();
+        let camera = renderer_instance.backend.camera.clone();
         let renderer = Arc::new(Mutex::new(renderer_instance));
 
         let (event_tx, event_rx) = mpsc::channel::<AppEvent>();
         let (control_tx, control_rx) = mpsc::channel::<ControlFlow>();
         let (frame_data_tx, frame_data_rx) = mpsc::channel::<FrameData>();
 
-        let engine = Self {
+        let mut engine = Self::build(graphics, render_queue, camera);
+        engine.renderer = Some(renderer.clone());
+        engine.event_loop_proxy = Some(event_loop_proxy);
+        engine.event_tx = Some(event_tx);
+        engine.control_rx = Some(control_rx);
+        engine.frame_data_rx = Some(frame_data_rx);
+        engine.win_id = Some(window_id);
+
+        if graphics {
+            let renderer_clone = renderer.clone();
+            engine.render_handle = Some(thread::spawn(move || {
+                renderer::Renderer::run(renderer_clone, event_rx, control_tx, frame_data_tx);
+            }));
+        }
+
+        (engine, event_loop)
+    }
+
+    /// Creates an engine without a window or wgpu device. Render output is still
+    /// gathered into [`Engine::render_queue`], UI elements are dropped.
+    pub fn new_headless() -> Self {
+        Self::build(
+            false,
+            Arc::new(Mutex::new(Vec::new())),
+            camera::Camera::new(cgmath::Point3::new(0.0, 0.0, 5.0), 800.0 / 600.0),
+        )
+    }
+
+    fn build(
+        graphics: bool,
+        render_queue: Arc<Mutex<Vec<component::RenderOutput>>>,
+        camera: camera::Camera,
+    ) -> Self {
+        Self {
             state: EngineState::new(),
-            renderer: renderer.clone(),
+            world: World::new(),
+            renderer: None,
             graphics,
-            event_loop_proxy,
+            event_loop_proxy: None,
             render_handle: None,
-            event_tx: Some(event_tx),
-            control_rx: Some(control_rx),
-            frame_data_rx: Some(frame_data_rx),
-            win_id: window_id,
+            render_queue,
+            camera,
+            event_tx: None,
+            control_rx: None,
+            frame_data_rx: None,
+            win_id: None,
             dt: None,
+            timestep: FixedTimestep::default(),
+            frame_time: Duration::ZERO,
             keys_pressed: Vec::new(),
             mouse_buttons_pressed: Vec::new(),
             mouse_position: (0.0, 0.0),
+            input_map: Arc::new(InputMap::defaults()),
             physics_engine: PhysicsEngine::new(0.1),
-            paused: false
-        };
-
-        if graphics {
-            let renderer_clone = renderer.clone();
-            thread::spawn(move || {
-                renderer::Renderer::run(renderer_clone, event_rx, control_tx, frame_data_tx);
-            });
+            paused: false,
+            scheduler: Scheduler::new(),
+            prefabs: Prefabs::new(),
+            scenes: Scenes::new(),
+            stepping: false,
+            quitting: false,
+            shut_down: false,
         }
+    }
 
-        (engine, event_loop)
+    pub fn is_headless(&self) -> bool {
+        self.renderer.is_none()
     }
 
     pub fn pause(&mut self) {
@@ -220,13 +306,32 @@ impl Engine {
         &self.state
     }
 
-    pub fn renderer(&self) -> Arc<Mutex<renderer::Renderer>> {
+    pub fn world(&self) -> Arc<World> {
+        self.world.clone()
+    }
+
+    /// Iterates every live object that has all the components in `Q`, see [`Query`].
+    pub fn query<Q: QueryParams>(&self) -> Query<Q> {
+        self.world.query::<Q>()
+    }
+
+    pub fn renderer(&self) -> Option<Arc<Mutex<renderer::Renderer>>> {
         self.renderer.clone()
     }
 
+    pub fn render_queue(&self) -> Arc<Mutex<Vec<component::RenderOutput>>> {
+        self.render_queue.clone()
+    }
+
+    pub fn camera(&self) -> camera::Camera {
+        match &self.renderer {
+            Some(renderer) => renderer.lock().unwrap().backend.camera.clone(),
+            None => self.camera.clone(),
+        }
+    }
+
     pub fn render(&mut self, data: component::RenderOutput) -> usize {
-        let mut renderer = self.renderer.lock().unwrap();
-        let mut render_queue = renderer.render_queue.lock().unwrap();
+        let mut render_queue = self.render_queue.lock().unwrap();
         render_queue.push(data);
         // if render_queue.len() == 2 {
         //     return 0;
@@ -237,8 +342,7 @@ impl Engine {
     }
 
     pub fn remove_from_render_queue(&mut self, reference: usize) {
-        let mut renderer = self.renderer.lock().unwrap();
-        let mut render_queue = renderer.render_queue.lock().unwrap();
+        let mut render_queue = self.render_queue.lock().unwrap();
 
         // println!("removed: {}", reference);
         render_queue.remove(reference);
@@ -249,11 +353,66 @@ impl Engine {
             keys_pressed: self.keys_pressed.clone(),
             mouse_buttons_pressed: self.mouse_buttons_pressed.clone(),
             mouse_position: self.mouse_position,
+            bindings: self.input_map.clone(),
         }
     }
 
+    /// What the actions and axes components read from [`component::InputData`] are
+    /// bound to.
+    pub fn input_map(&self) -> &InputMap {
+        &self.input_map
+    }
+
+    /// Rebinds actions and axes, components see the change from their next tick.
+    pub fn input_map_mut(&mut self) -> &mut InputMap {
+        Arc::make_mut(&mut self.input_map)
+    }
+
+    pub fn set_input_map(&mut self, map: InputMap) {
+        self.input_map = Arc::new(map);
+    }
+
+    /// Asks [`Engine::run`] to shut down once the current step is done, after which it
+    /// returns. Headless engines only raise [`Engine::is_quitting`], whatever ticks them
+    /// calls [`Engine::shutdown`].
     pub fn quit(&mut self) {
-        exit(0);
+        self.quitting = true;
+        if let Some(proxy) = &self.event_loop_proxy {
+            // Wakes the event loop up so it sees the request
+            let _ = proxy.send_event(());
+        }
+    }
+
+    pub fn is_quitting(&self) -> bool {
+        self.quitting
+    }
+
+    /// Runs `on_shutdown` on every component, then on every static component, once.
+    /// [`Engine::run`] calls it after the tick thread has stopped. Anything still locked,
+    /// like the static component calling this, is skipped.
+    pub fn shutdown(&mut self) {
+        if self.shut_down {
+            return;
+        }
+        self.shut_down = true;
+        info!("Shutting down");
+
+        let world = self.world();
+        for id in self.state.objects() {
+            if let Some(obj) = world.find_by_id(id) {
+                match obj.try_lock() {
+                    Ok(mut obj) => obj.shutdown(),
+                    Err(_) => warn!("GameObject {} is locked, skipping its shutdown hooks", id),
+                }
+            }
+        }
+
+        for comp in self.state.static_components.clone() {
+            match comp.try_lock() {
+                Ok(mut comp) => comp.on_shutdown(self),
+                Err(_) => warn!("A static component is locked, skipping its shutdown hook"),
+            }
+        }
     }
 
     pub fn export(&mut self) -> EngineSaveData {
@@ -264,60 +423,432 @@ impl Engine {
         EngineSaveData::from_engine_to_json(self)
     }
 
-    pub async fn import(mut data: EngineSaveData) -> (Self, EventLoop<()>) {
-        EngineSaveData::to_engine(&mut data).await
+    /// Panics if the save can't be loaded, see [`Engine::try_import`].
+    pub async fn import(data: EngineSaveData) -> (Self, EventLoop<()>) {
+        Self::try_import(data).await.unwrap_or_else(|err| panic!("{}", err))
     }
 
     pub async fn import_from_json(data: String) -> (Self, EventLoop<()>) {
+        Self::try_import_from_json(data).await.unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn import_headless(data: EngineSaveData) -> Self {
+        Self::try_import_headless(data).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn import_from_json_headless(data: String) -> Self {
+        Self::try_import_from_json_headless(data).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Fails on malformed data or components, static components and colliders that
+    /// aren't registered.
+    pub async fn try_import(mut data: EngineSaveData) -> EngineResult<(Self, EventLoop<()>)> {
+        EngineSaveData::to_engine(&mut data).await
+    }
+
+    pub async fn try_import_from_json(data: String) -> EngineResult<(Self, EventLoop<()>)> {
         info!("Loading json... (state.rs)");
         EngineSaveData::to_engine_from_data(data).await
     }
 
+    pub fn try_import_headless(mut data: EngineSaveData) -> EngineResult<Self> {
+        EngineSaveData::to_headless_engine(&mut data)
+    }
+
+    pub fn try_import_from_json_headless(data: String) -> EngineResult<Self> {
+        info!("Loading json headless... (state.rs)");
+        EngineSaveData::to_headless_engine_from_data(data)
+    }
+
+    /// Runs one simulation step followed by a render update of where it ended up.
+    /// Headless engines are driven by calling this, or [`Engine::frame`] to use the
+    /// fixed timestep.
     pub fn tick(&mut self) {
         if (self.paused) { return; }
 
-        self.renderer.lock().unwrap().backend.ui_handler.clear();
+        self.frame_time = self.delta();
+        self.step();
+        self.world.set_interpolation(1.0);
+        self.render_update();
+    }
+
+    /// Runs as many fixed simulation steps as fit in `elapsed` (plus what was left over
+    /// from earlier frames), then a render update interpolated between the last two
+    /// steps. Returns the number of steps run.
+    pub fn frame(&mut self, elapsed: Duration) -> u32 {
+        if (self.paused) { return 0; }
 
-        for comp in self.state.static_components.clone().iter() {
-            comp.lock().unwrap().tick(self);
+        self.frame_time = elapsed;
+        let steps = self.timestep.advance(elapsed);
+        for _ in 0..steps {
+            self.step();
         }
 
-        let mut i = 0;
-        let mut obj_clone = self.state.objects.clone();
-        obj_clone.reverse();
+        self.world.set_interpolation(self.timestep.alpha());
+        self.render_update();
+        steps
+    }
 
-        for obj in obj_clone.iter() {
-            if !gameobject::GAME_OBJECT_DESTROYED.lock().unwrap().contains(obj) {
-                gameobject::to_object(*obj, |game_object| {
-                    // println!("obj: {}: references: {}. engine references: {}", i, game_object.render_references.len(), self.renderer.lock().unwrap().render_queue.lock().unwrap().len());
-                    game_object.render_references.reverse();
-                    for ref_id in game_object.render_references.drain(..) {
-                        self.remove_from_render_queue(ref_id);
-                    }
-                });
-                i += 1;
+    fn step(&mut self) {
+        self.stepping = true;
+        let world = self.world.clone();
+        world.advance_time(self.dt.unwrap_or(self.timestep.step()));
+        transform::snapshot(&world);
+
+        if let Some(renderer) = &self.renderer {
+            renderer.lock().unwrap().backend.ui_handler.clear();
+        }
+
+        let dt = self.delta();
+        self.run_stage(Stage::PreUpdate, dt);
+
+        transform::propagate(&world);
+
+        for obj in self.state.objects.clone().iter() {
+            if let Some(game_object) = world.find_by_id(*obj) {
+                let mut game_object = game_object.lock().unwrap();
+                if game_object.state.parent_id.is_none() {
+                    game_object.tick_all(self);
+                }
             }
         }
+        self.run_stage(Stage::Update, dt);
+
+        self.run_timers();
+        world.tasks().poll();
+        self.run_stage(Stage::PostUpdate, dt);
+
+        self.run_stage(Stage::PhysicsStep, dt);
+
+        world.events().flush();
+        self.apply_commands();
+        self.flush_destroyed();
+
+        self.stepping = false;
+        self.apply_scene_changes();
+    }
+
+    /// Rebuilds the render queue from the render components, which see the
+    /// interpolation factor through [`World::interpolation`].
+    pub fn render_update(&mut self) {
+        self.stepping = true;
+        let world = self.world.clone();
+        transform::propagate(&world);
+
+        // Everything is re-submitted below, so the queue is rebuilt from scratch. This
+        // also drops the output of objects destroyed since the last update.
+        self.render_queue.lock().unwrap().clear();
+
+        self.run_stage(Stage::Render, self.frame_time);
 
         for obj in self.state.objects.clone().iter() {
-            if !gameobject::GAME_OBJECT_DESTROYED.lock().unwrap().contains(obj) {
-                gameobject::to_object(*obj, |game_object| {
-                    if game_object.state.parent_id.is_none() {
-                        game_object.tick_all(self);
-                    }
-                });
+            if let Some(game_object) = world.find_by_id(*obj) {
+                let mut game_object = game_object.lock().unwrap();
+                if game_object.state.parent_id.is_none() {
+                    game_object.render_all(self);
+                }
             }
         }
+
+        self.stepping = false;
+        self.apply_scene_changes();
+    }
+
+    /// Runs `callback` once the engine clock has moved on by `delay`.
+    pub fn after(&mut self, delay: Duration, callback: impl FnMut(&mut Engine) + Send + 'static) -> TimerHandle {
+        self.world.timers().after(delay, callback)
+    }
+
+    /// Runs `callback` every `interval` until the timer is cancelled.
+    pub fn every(&mut self, interval: Duration, callback: impl FnMut(&mut Engine) + Send + 'static) -> TimerHandle {
+        self.world.timers().every(interval, callback)
+    }
+
+    pub fn cancel_timer(&mut self, handle: TimerHandle) -> bool {
+        self.world.timers().cancel(handle)
+    }
+
+    pub fn timers(&self) -> &Timers {
+        self.world.timers()
+    }
+
+    /// Starts an async task that isn't owned by any object, see [`Tasks`].
+    pub fn start_task<F, Fut>(&mut self, f: F) -> TaskHandle
+    where
+        F: FnOnce(TaskContext) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.world.tasks().start(None, f)
+    }
+
+    fn run_timers(&mut self) {
+        let now = self.world.time().total();
+        for mut timer in self.world.timers().take_due(now) {
+            timer.fire(self);
+            self.world.timers().finish(timer);
+        }
     }
 
+    /// The simulation step components and systems are ticked with, scaled by the
+    /// time scale.
+    pub fn delta(&self) -> Duration {
+        self.dt.unwrap_or(self.timestep.step()).mul_f64(self.world.time().scale() as f64)
+    }
+
+    /// The engine clock. It only moves while steps run, so it stops while the engine is
+    /// paused.
+    pub fn time(&self) -> Time {
+        self.world.time()
+    }
+
+    /// Scales [`Engine::delta`] and the scaled clock, e.g. `0.2` for slow motion or `0.0`
+    /// for a hit-stop. Unscaled time keeps going.
+    pub fn set_time_scale(&mut self, scale: f32) {
+        self.world.set_time_scale(scale);
+    }
+
+    /// Time covered by the current render update.
+    pub fn frame_time(&self) -> Duration {
+        self.frame_time
+    }
+
+    pub fn timestep(&self) -> &FixedTimestep {
+        &self.timestep
+    }
+
+    /// Simulation steps per second, 60 by default.
+    pub fn set_tick_rate(&mut self, rate: f64) {
+        self.timestep.set_rate(rate);
+    }
+
+    /// The most simulation steps one frame can run, 5 by default. Time past that is
+    /// dropped, so the game slows down instead of falling further behind.
+    pub fn set_max_steps(&mut self, max_steps: u32) {
+        self.timestep.set_max_steps(max_steps);
+    }
+
+    pub fn interpolation(&self) -> f32 {
+        self.world.interpolation()
+    }
+
+    /// Changes recorded during the tick, applied at the end of it. See [`Commands`].
+    pub fn commands(&self) -> &Commands {
+        self.world.commands()
+    }
+
+    /// Applies every recorded command in order, including ones recorded while applying
+    /// (e.g. from an `on_add` hook). Spawned objects are added to the engine.
+    pub fn apply_commands(&mut self) {
+        loop {
+            let commands = self.world.commands().take();
+            if commands.is_empty() {
+                break;
+            }
+
+            for command in commands {
+                if let Some(obj) = command.apply(&self.world) {
+                    self.add_object(obj);
+                }
+            }
+        }
+    }
+
+    /// The event bus components and static components use to talk to each other.
+    /// Buffered events are delivered at the end of every tick.
+    pub fn events(&self) -> &EventBus {
+        self.world.events()
+    }
+
+    /// Adds a system that runs every tick after the objects have ticked. Systems that
+    /// don't conflict run at the same time.
+    pub fn add_system(&mut self, system: impl System + 'static) {
+        self.scheduler.add(system);
+    }
+
+    pub fn add_system_to(&mut self, stage: Stage, system: impl System + 'static) {
+        self.scheduler.add_to(stage, system);
+    }
+
+    /// Moves the static components called `name` out of `PreUpdate`.
+    pub fn set_static_stage(&mut self, name: &str, stage: Stage) {
+        self.scheduler.set_static_stage(name, stage);
+    }
+
+    /// Runs the systems or static components called `first` before the ones called
+    /// `then`, within a stage.
+    pub fn order(&mut self, first: &str, then: &str) {
+        self.scheduler.order(first, then);
+    }
+
+    /// Everything a simulation step and a render update run, stage by stage.
+    pub fn schedule_dump(&mut self) -> String {
+        self.sync_statics();
+
+        let mut out = String::new();
+        for stage in Stage::ALL {
+            out.push_str(&format!("{:?}\n", stage));
+
+            // What the engine runs itself around the scheduled steps
+            let (before, after): (&[&str], &[&str]) = match stage {
+                Stage::PreUpdate => (&[], &["transform propagation"]),
+                Stage::Update => (&["objects (input, then default components)"], &[]),
+                Stage::PostUpdate => (&["timers", "tasks"], &[]),
+                Stage::PhysicsStep => (&["physics"], &["events", "commands", "destroyed objects"]),
+                Stage::Render => (&[], &["render components"]),
+            };
+
+            let mut lines: Vec<String> = before.iter().map(|line| line.to_string()).collect();
+            lines.extend(self.scheduler.describe(stage));
+            lines.extend(after.iter().map(|line| line.to_string()));
+
+            for line in lines {
+                out.push_str(&format!("  {}\n", line));
+            }
+        }
+        out
+    }
+
+    // Static components pushed straight into the state haven't been scheduled yet
+    fn sync_statics(&mut self) {
+        let scheduled = self.scheduler.static_count();
+        for comp in &self.state.static_components[scheduled..] {
+            let name = comp.lock().unwrap().name();
+            self.scheduler.add_static(name);
+        }
+    }
+
+    fn run_stage(&mut self, stage: Stage, dt: Duration) {
+        self.sync_statics();
+
+        let world = self.world.clone();
+        for step in self.scheduler.plan(stage).to_vec() {
+            match step {
+                Step::Static(i) => {
+                    let comp = self.state.static_components[i].clone();
+                    comp.lock().unwrap().tick(self);
+                }
+                Step::Systems(batches) => self.scheduler.run_batches(&batches, &world, dt),
+            }
+        }
+    }
+
+    /// Sets how many threads systems run on, `1` runs them in the order they were added.
+    pub fn set_system_threads(&mut self, threads: usize) {
+        self.scheduler.set_threads(threads);
+    }
+
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    /// Registers `prefab` under `name`, replacing a template updates its live instances.
+    pub fn register_prefab(&mut self, name: &str, prefab: Prefab) {
+        self.prefabs.register(&self.world, name, prefab);
+    }
+
+    pub fn prefab(&self, name: &str) -> Option<&Prefab> {
+        self.prefabs.get(name)
+    }
+
+    /// Root ids of the live instances of the prefab `name`.
+    pub fn prefab_instances(&self, name: &str) -> Vec<ObjectId> {
+        self.prefabs.instances(&self.world, name)
+    }
+
+    /// Creates a new copy of the prefab `name` with fresh ids and returns the root's id.
+    pub fn instantiate(&mut self, name: &str, overrides: Overrides) -> EngineResult<ObjectId> {
+        let (root, objects) = self.prefabs.instantiate(&self.world, name, overrides)?;
+        for obj in objects {
+            self.add_object(obj);
+        }
+        Ok(root)
+    }
+
+    /// Creates a pool for the prefab `name` holding `size` deactivated copies of it, or
+    /// adds `size` more copies if it already has one.
+    pub fn create_pool(&mut self, name: &str, size: usize) -> EngineResult<()> {
+        if self.prefab(name).is_none() {
+            return Err(EngineError::UnknownPrefab(name.to_string()));
+        }
+
+        let world = self.world();
+        world.pools().create(name);
+        for _ in 0..size {
+            let id = self.instantiate(name, Overrides::new().active(false))?;
+            world.pools().put_free(name, id);
+        }
+        Ok(())
+    }
+
+    /// Activates a free object from the pool of the prefab `name` and returns its id,
+    /// creating a new copy when every object in the pool is in use.
+    pub fn acquire(&mut self, name: &str) -> EngineResult<ObjectId> {
+        let world = self.world();
+        let (id, hit) = match world.pools().take_free(name)? {
+            Some(id) => (id, true),
+            None => (self.instantiate(name, Overrides::new().active(false))?, false),
+        };
+
+        if let Err(err) = gameobject::to_object(&world, id, |obj| obj.set_active(true)) {
+            world.pools().put_free(name, id);
+            return Err(err);
+        }
+
+        // The last snapshot is from wherever the object was released, moving it after
+        // acquiring would render as a slide from there
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let _ = gameobject::to_object(&world, id, |obj| {
+                obj.get_component_closure::<component::Transform>(|transform| transform.reset_interpolation());
+                stack.extend(obj.state.child_ids.iter().copied());
+            });
+        }
+
+        world.pools().hand_out(name, id, hit);
+        Ok(id)
+    }
+
+    /// Deactivates `id` and puts it back into its pool, see [`GameObject::release`] for
+    /// releasing from the object's own components.
+    pub fn release(&mut self, id: ObjectId) -> EngineResult<()> {
+        if !self.world.pools().in_use(id) {
+            return Err(EngineError::NotPooled(id));
+        }
+        gameobject::to_object(&self.world, id, |obj| obj.set_active(false))?;
+        self.world.pools().release(id)
+    }
+
+    pub fn pool_stats(&self, name: &str) -> Option<PoolStats> {
+        self.world.pools().stats(name)
+    }
+
+    /// Destroys an object and its children right away instead of at the end of the tick.
+    pub fn destroy(&mut self, id: ObjectId) {
+        self.world.destroy(id);
+        self.flush_destroyed();
+    }
+
+    /// Removes objects marked with [`World::destroy`] from the world, the engine state
+    /// and the physics engine.
+    pub fn flush_destroyed(&mut self) {
+        for id in self.world.flush_destroyed() {
+            self.state.remove_object(id);
+            self.physics_engine.remove_object(id);
+            self.scenes.forget_object(id);
+        }
+    }
+
+    /// Adds an object to the engine and the active scene.
     pub fn add_object(&mut self, obj: gameobject::MutexdGameObject) -> ToLockGameObj {
         let id = obj.clone().lock().unwrap().id();
         self.state.add_object(id);
         self.physics_engine.add_object(id);
-        ToLockGameObj { id }
+        self.scenes.track_object(id);
+        ToLockGameObj { id, world: self.world.clone() }
     }
 
     pub unsafe fn add_static(&mut self, comp: Arc<Mutex<dyn static_component::StaticComponent>>) {
+        self.scenes.track_static(comp.clone());
         self.state.add_static(comp);
     }
 
@@ -325,11 +856,173 @@ impl Engine {
     where
         T: StaticComponent + Clone + 'static,
     {
+        self.scenes.track_static(data.clone());
         self.state.add_static_linked(data)
     }
 
+    fn remove_static(&mut self, comp: &Arc<Mutex<dyn static_component::StaticComponent>>) {
+        if let Some(index) = self.state.static_components.iter().position(|other| Arc::ptr_eq(other, comp)) {
+            if index < self.scheduler.static_count() {
+                self.scheduler.remove_static(index);
+            }
+            self.state.static_components.remove(index);
+        }
+    }
+
+    /// Registers `scene` under `name` for [`Engine::load_scene`].
+    pub fn register_scene(&mut self, name: &str, scene: Scene) {
+        self.scenes.register(name, scene);
+    }
+
+    pub fn scenes(&self) -> &Scenes {
+        &self.scenes
+    }
+
+    /// Loads the scene `name`, reloading it if it's already loaded. With
+    /// [`LoadMode::Single`] it becomes the active scene, additive loads only make it
+    /// active when no other scene is. Called while the engine is stepping, e.g. from a
+    /// static component or a timer, the scene changes at the end of the step.
+    pub fn load_scene(&mut self, name: &str, mode: LoadMode) -> EngineResult<()> {
+        if self.scenes.get(name).is_none() {
+            return Err(EngineError::UnknownScene(name.to_string()));
+        }
+        if self.stepping {
+            self.scenes.pending.push((name.to_string(), Some(mode)));
+            return Ok(());
+        }
+        self.switch_scene(name, mode)
+    }
+
+    /// Runs the unload hooks of `name`, then destroys its objects and removes its
+    /// static components. Deferred like [`Engine::load_scene`].
+    pub fn unload_scene(&mut self, name: &str) -> EngineResult<()> {
+        if self.stepping {
+            let loading = self.scenes.pending.iter().any(|(pending, mode)| pending == name && mode.is_some());
+            if !self.scenes.is_loaded(name) && !loading {
+                return Err(EngineError::SceneNotLoaded(name.to_string()));
+            }
+            self.scenes.pending.push((name.to_string(), None));
+            return Ok(());
+        }
+        self.unload_now(name)
+    }
+
+    /// Makes `name` the scene new objects and static components are added to.
+    pub fn set_active_scene(&mut self, name: &str) -> EngineResult<()> {
+        if !self.scenes.is_loaded(name) {
+            return Err(EngineError::SceneNotLoaded(name.to_string()));
+        }
+        self.scenes.active = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Takes the root object `id` and its children out of their scene, so they survive
+    /// scene changes until destroyed.
+    pub fn dont_destroy_on_load(&mut self, id: ObjectId) {
+        self.scenes.keep(id);
+    }
+
+    fn switch_scene(&mut self, name: &str, mode: LoadMode) -> EngineResult<()> {
+        let scene = self.scenes.get(name).cloned().ok_or_else(|| EngineError::UnknownScene(name.to_string()))?;
+        match mode {
+            LoadMode::Single => {
+                for loaded in self.scenes.loaded() {
+                    self.unload_now(&loaded)?;
+                }
+            }
+            LoadMode::Additive if self.scenes.is_loaded(name) => self.unload_now(name)?,
+            LoadMode::Additive => {}
+        }
+
+        info!("Loading scene {}", name);
+        let previous = self.scenes.active.replace(name.to_string());
+        self.scenes.loaded.push(LoadedScene {
+            name: name.to_string(),
+            objects: Vec::new(),
+            static_components: Vec::new(),
+        });
+
+        let world = self.world();
+        let added = prefab::spawn_copies(&world, scene.objects(), |_, _| {}).and_then(|(_, objects)| {
+            for obj in objects {
+                self.add_object(obj);
+            }
+            for data in scene.static_components() {
+                unsafe { self.add_static(data.to_static_component()?) };
+            }
+            Ok(())
+        });
+        if let Err(err) = added {
+            self.drop_scene(name);
+            self.scenes.active = previous.filter(|previous| self.scenes.is_loaded(previous));
+            return Err(err);
+        }
+
+        for hook in scene.on_load.iter() {
+            hook(self);
+        }
+
+        if mode == LoadMode::Additive && self.scenes.active() == Some(name) {
+            if let Some(previous) = previous.filter(|previous| self.scenes.is_loaded(previous)) {
+                self.scenes.active = Some(previous);
+            }
+        }
+        Ok(())
+    }
+
+    fn unload_now(&mut self, name: &str) -> EngineResult<()> {
+        if !self.scenes.is_loaded(name) {
+            return Err(EngineError::SceneNotLoaded(name.to_string()));
+        }
+
+        info!("Unloading scene {}", name);
+        let hooks = self.scenes.get(name).map(|scene| scene.on_unload.clone()).unwrap_or_default();
+        for hook in hooks {
+            hook(self);
+        }
+        self.drop_scene(name);
+        Ok(())
+    }
+
+    // Removes what belongs to the scene without running its hooks
+    fn drop_scene(&mut self, name: &str) {
+        let index = match self.scenes.loaded.iter().position(|scene| scene.name == name) {
+            Some(index) => index,
+            None => return,
+        };
+        let scene = self.scenes.loaded.remove(index);
+
+        for id in scene.objects {
+            if !self.scenes.is_persistent(&self.world, id) {
+                self.world.destroy(id);
+            }
+        }
+        for comp in scene.static_components.iter() {
+            self.remove_static(comp);
+        }
+        if self.scenes.active() == Some(name) {
+            self.scenes.active = self.scenes.loaded.last().map(|scene| scene.name.clone());
+        }
+        self.flush_destroyed();
+    }
+
+    // Loads and unloads asked for while the engine was stepping
+    fn apply_scene_changes(&mut self) {
+        for (name, mode) in std::mem::take(&mut self.scenes.pending) {
+            let changed = match mode {
+                Some(mode) => self.switch_scene(&name, mode),
+                None => self.unload_now(&name),
+            };
+            if let Err(err) = changed {
+                error!("Couldn't change scene {}: {}", name, err);
+            }
+        }
+    }
+
     pub fn add_ui_element(&mut self, element: UIElement) {
-        self.renderer.lock().unwrap().backend.ui_handler.queue(element);
+        if let Some(renderer) = &self.renderer {
+            renderer.lock().unwrap().backend.ui_handler.queue(element);
+        }
     }
 
     pub fn get_static_closure<T>(&mut self, mut f: impl FnMut(&mut T)) -> Option<()>
@@ -369,30 +1062,28 @@ impl Engine {
         None
     }
 
-    pub fn run(engine: Arc<Mutex<Self>>, event_loop: EventLoop<()>) {
-        // println!("running");
+    /// Runs the engine until the window is closed, Escape is pressed or
+    /// [`Engine::quit`] is called. The tick thread is stopped, the shutdown hooks run
+    /// and the render thread is joined before it returns, so the caller can still
+    /// export the engine.
+    pub fn run(engine: Arc<Mutex<Self>>, mut event_loop: EventLoop<()>) {
         let self_clone = engine.clone();
-        thread::spawn(move || {
+        let tick_thread = thread::spawn(move || {
+            let mut last = Instant::now();
             loop {
-                thread::sleep(Duration::from_millis(16)); // Adjust timing as needed, e.g., ~60 Hz
+                let now = Instant::now();
                 let mut engine = self_clone.lock().unwrap();
-                engine.tick(); // Perform periodic update
+                if engine.quitting {
+                    break;
+                }
+                engine.frame(now - last);
+                let next_step = engine.timestep.until_next_step();
                 drop(engine);
-            }
-        });
+                last = now;
 
-        let self_clone_ = engine.clone();
-        thread::spawn(move || {
-            let mut last = SystemTime::now();
-            loop {
-                // let mut engine = self_clone_.try_lock().unwrap();
-                // let mut dt = last.elapsed();
-                // if dt.is_ok() {
-                //     engine.physics_engine.tick(dt.unwrap().as_secs_f32());
-                // }
-                // last = SystemTime::now();
-                //
-                // drop(engine);
+                // Render updates run at least at the render rate so motion between steps
+                // stays smooth
+                thread::sleep(next_step.min(RENDER_INTERVAL));
             }
         });
 
@@ -400,16 +1091,28 @@ impl Engine {
         let control_rx = engine.lock().unwrap().control_rx.take().unwrap();
         let frame_data_rx = engine.lock().unwrap().frame_data_rx.take().unwrap();
 
-        let win_id = engine.lock().unwrap().win_id;
+        let win_id = engine
+            .lock()
+            .unwrap()
+            .win_id
+            .expect("Engine::run needs a windowed engine, headless engines are ticked manually");
+
+        let event_engine = engine.clone();
+        event_loop.run_return(move |event, _, control_flow| {
+            if let Event::UserEvent(()) = event {
+                if event_engine.lock().unwrap().quitting {
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+            }
 
-        event_loop.run(move |event, _, control_flow| {
             if let Some(app_event) = AppEvent::from_event(&event, &win_id) {
                 if event_tx.send(app_event.clone()).is_err() {
                     *control_flow = ControlFlow::Exit;
                     return;
                 }
 
-                let mut engine_lock = engine.lock().unwrap();
+                let mut engine_lock = event_engine.lock().unwrap();
 
                 match app_event {
                     AppEvent::KeyPressed(key) => {
@@ -442,10 +1145,352 @@ impl Engine {
                 Err(_) => *control_flow = ControlFlow::Wait,
             }
 
-            match frame_data_rx.try_recv() {
-                Ok(frame_data) => engine.lock().unwrap().dt = frame_data.dt,
-                Err(_) => {}
+            // Frame times are measured by the tick thread, this just drains the channel
+            let _ = frame_data_rx.try_recv();
+        });
+
+        // Nothing ticks once the shutdown hooks have run
+        engine.lock().unwrap().quitting = true;
+        if tick_thread.join().is_err() {
+            error!("The tick thread panicked");
+        }
+        engine.lock().unwrap().shutdown();
+
+        // The event loop's sender is gone, so the render thread stops waiting for events
+        let render_handle = engine.lock().unwrap().render_handle.take();
+        if let Some(render_handle) = render_handle {
+            if render_handle.join().is_err() {
+                error!("The render thread panicked");
             }
+        }
+        info!("Engine stopped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::component::CharacterController2D;
+    use crate::engine::components::RenderComponent;
+    use crate::engine::gameobject::make_base_game_object;
+    use crate::engine::error::EngineError;
+    use crate::engine::graphics_backend::primitives::Primitives;
+    use crate::engine::save::{self, StaticComponentSaveLoad};
+    use crate::engine::static_component::Container;
+    use crate::engine::system::{Access, FnSystem, QuerySystem};
+    use crate::engine::event::Delivery;
+
+    #[derive(Debug, Default)]
+    struct TickCounter {
+        ticks: u32,
+        shutdowns: u32,
+    }
+
+    impl StaticComponentSaveLoad for TickCounter {
+        fn to_save_data(&mut self) -> Value {
+            Value::Null
+        }
+
+        fn from_save_data(_data: Value) -> EngineResult<Container> {
+            Ok(Container { internal: Arc::new(Mutex::new(TickCounter::default())) })
+        }
+    }
+
+    impl static_component::StaticComponent for TickCounter {
+        fn tick(&mut self, _engine: &mut Engine) {
+            self.ticks += 1;
+        }
+
+        fn name(&mut self) -> String {
+            "TickCounter".to_string()
+        }
+
+        fn on_shutdown(&mut self, _engine: &mut Engine) {
+            self.shutdowns += 1;
+        }
+    }
+
+    #[test]
+    fn test_headless_engine_ticks_static_components() {
+        let mut engine = Engine::new_headless();
+        assert!(engine.is_headless());
+
+        let counter = Arc::new(Mutex::new(TickCounter::default()));
+        unsafe { engine.add_static(counter.clone()) };
+
+        engine.tick();
+        engine.tick();
+
+        assert_eq!(counter.lock().unwrap().ticks, 2);
+    }
+
+    #[test]
+    fn test_headless_engine_gathers_render_output() {
+        let mut engine = Engine::new_headless();
+
+        let obj = engine.add_object(make_base_game_object(&engine.world(), "renderable".to_string()));
+        obj.get()
+            .lock()
+            .unwrap()
+            .add_component(RenderComponent::new(Primitives::Cube(0.1, [1.0, 0.0, 0.0])))
+            .unwrap();
+
+        engine.tick();
+        assert_eq!(engine.render_queue().lock().unwrap().len(), 1);
+
+        engine.tick();
+        let queue = engine.render_queue();
+        let mut queue = queue.lock().unwrap();
+        assert_eq!(queue.len(), 1);
+        assert!(!queue[0].raw_desc().0.is_empty());
+    }
+
+    #[test]
+    fn test_headless_export_and_import() {
+        save::init();
+
+        let mut engine = Engine::new_headless();
+        engine.add_object(make_base_game_object(&engine.world(), "saved".to_string()));
+
+        let json = engine.export_raw();
+        let mut restored = Engine::import_from_json_headless(json);
+
+        assert!(restored.is_headless());
+        assert_eq!(restored.state().objects().len(), 1);
+        restored.tick();
+    }
+
+    #[test]
+    fn test_bad_saves_fail_to_import() {
+        save::init();
+
+        let mut engine = Engine::new_headless();
+        let obj = engine.add_object(make_base_game_object(&engine.world(), "saved".to_string())).get();
+        obj.lock().unwrap().add_component(CharacterController2D::new(None)).unwrap();
+        let mut data = engine.export();
+        data.objects[0].components[0].id = "NotRegistered".to_string();
+
+        assert_eq!(
+            Engine::try_import_headless(data).err(),
+            Some(EngineError::UnknownComponent("NotRegistered".to_string()))
+        );
+
+        // Registered components with data of the wrong shape
+        let mut invalid = |id: &str, field: &str| {
+            let mut data = serde_json::to_value(engine.export()).unwrap();
+            let components = data["objects"][0]["components"].as_array_mut().unwrap();
+            let comp = components.iter_mut().find(|comp| comp["id"] == id).unwrap();
+            comp["data"][field] = Value::String("oops".to_string());
+            match Engine::try_import_from_json_headless(data.to_string()) {
+                Err(EngineError::InvalidSave(reason)) => reason,
+                _ => panic!("{} with a bad {} was loaded", id, field),
+            }
+        };
+        assert!(invalid("Transform", "pos").starts_with("Transform: "));
+        assert!(invalid("CharacterController2D", "moveamt").starts_with("CharacterController2D: "));
+        assert!(matches!(
+            Engine::try_import_from_json_headless("{\"objects\": 3}".to_string()),
+            Err(EngineError::InvalidSave(_))
+        ));
+    }
+
+    #[test]
+    fn test_exports_skip_objects_destroyed_this_step() {
+        let mut engine = Engine::new_headless();
+        let doomed = engine.add_object(make_base_game_object(&engine.world(), "doomed".to_string())).get_id();
+        engine.add_object(make_base_game_object(&engine.world(), "kept".to_string()));
+
+        engine.world().destroy(doomed);
+        let data = engine.export();
+        assert_eq!(data.objects.len(), 1);
+        assert_eq!(data.objects[0].name, "kept");
+    }
+
+    #[test]
+    fn test_tags_and_layers_are_saved() {
+        save::init();
+
+        let mut engine = Engine::new_headless();
+        let obj = engine.add_object(make_base_game_object(&engine.world(), "tagged".to_string())).get();
+        obj.lock().unwrap().add_tag("enemy");
+        obj.lock().unwrap().set_layer(3);
+
+        let restored = Engine::import_from_json_headless(engine.export_raw());
+        let world = restored.world();
+        let found = world.find_by_tag("enemy").expect("tag wasn't restored");
+        assert_eq!(found.lock().unwrap().name(), "tagged");
+        assert_eq!(world.find_in_layer(3).len(), 1);
+    }
+
+    #[test]
+    fn test_engines_have_separate_worlds() {
+        let mut first = Engine::new_headless();
+        let mut second = Engine::new_headless();
+
+        let obj = first.add_object(make_base_game_object(&first.world(), "only in first".to_string()));
+
+        assert!(first.world().find_by_id(obj.get_id()).is_some());
+        assert!(!second.world().contains(obj.get_id()));
+
+        first.tick();
+        second.tick();
+    }
+
+    #[test]
+    fn test_destroyed_objects_leave_engine_and_render_queue() {
+        let mut engine = Engine::new_headless();
+        let world = engine.world();
+
+        let parent = engine.add_object(make_base_game_object(&world, "parent".to_string()));
+        let child = engine.add_object(make_base_game_object(&world, "child".to_string()));
+        gameobject::reparent(&world, parent.get_id(), child.get_id()).unwrap();
+        child
+            .get()
+            .lock()
+            .unwrap()
+            .add_component(RenderComponent::new(Primitives::Cube(0.1, [1.0, 0.0, 0.0])))
+            .unwrap();
+
+        engine.tick();
+        assert_eq!(engine.render_queue().lock().unwrap().len(), 1);
+
+        parent.get().lock().unwrap().destroy();
+        engine.tick();
+
+        assert!(engine.state().objects().is_empty());
+        assert!(engine.physics_engine.game_objects.is_empty());
+        assert!(!world.contains(child.get_id()));
+        assert!(engine.render_queue().lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_engine_runs_systems_each_tick() {
+        let mut engine = Engine::new_headless();
+        engine.dt = Some(Duration::from_secs(1));
+        let obj = engine.add_object(make_base_game_object(&engine.world(), "moved".to_string()));
+
+        engine.add_system(QuerySystem::<(component::Transform,), _>::new("move", |_, (transform,), dt| {
+            transform.pos[0] += dt.as_secs_f32();
+        }));
+        engine.tick();
+        engine.tick();
+
+        let mut x = 0.0;
+        obj.get().lock().unwrap().get_component_closure::<component::Transform>(|transform| x = transform.pos[0]);
+        assert_eq!(x, 2.0);
+    }
+
+    #[test]
+    fn test_frames_run_fixed_steps_and_interpolate() {
+        let mut engine = Engine::new_headless();
+        engine.set_tick_rate(10.0);
+        let obj = engine.add_object(make_base_game_object(&engine.world(), "moved".to_string()));
+        obj.get()
+            .lock()
+            .unwrap()
+            .add_component(RenderComponent::new(Primitives::Cube(0.1, [1.0, 0.0, 0.0])))
+            .unwrap();
+
+        engine.add_system(QuerySystem::<(component::Transform,), _>::new("move", |_, (transform,), dt| {
+            transform.pos[0] += dt.as_secs_f32() * 10.0;
+        }));
+
+        assert_eq!(engine.frame(Duration::from_millis(250)), 2);
+        assert!((engine.interpolation() - 0.5).abs() < 1e-4);
+        assert_eq!(engine.render_queue().lock().unwrap().len(), 1);
+
+        let (mut x, mut rendered_x) = (0.0, 0.0);
+        let alpha = engine.interpolation();
+        obj.get().lock().unwrap().get_component_closure::<component::Transform>(|transform| {
+            x = transform.pos[0];
+            rendered_x = transform.interpolated(alpha).pos[0];
         });
+        assert!((x - 2.0).abs() < 1e-4);
+        assert!((rendered_x - 1.5).abs() < 1e-4);
+
+        engine.set_max_steps(3);
+        assert_eq!(engine.frame(Duration::from_secs(10)), 3);
+        assert_eq!(engine.frame(Duration::ZERO), 0);
+    }
+
+    #[test]
+    fn test_engine_clock_pauses_scales_and_is_saved() {
+        let mut engine = Engine::new_headless();
+        engine.set_tick_rate(10.0);
+        engine.tick();
+        engine.pause();
+        engine.tick();
+        engine.frame(Duration::from_secs(1));
+        engine.unpause();
+        assert_eq!(engine.time().ticks(), 1);
+        assert_eq!(engine.time().total(), Duration::from_millis(100));
+
+        engine.set_time_scale(0.5);
+        assert_eq!(engine.delta(), Duration::from_millis(50));
+        engine.tick();
+        assert_eq!(engine.time().delta(), Duration::from_millis(50));
+        assert_eq!(engine.time().total(), Duration::from_millis(150));
+        assert_eq!(engine.time().unscaled(), Duration::from_millis(200));
+
+        let loaded = Engine::import_from_json_headless(engine.export_raw());
+        assert_eq!(loaded.time().now(), engine.time().now());
+        assert_eq!(loaded.time().scale(), 0.5);
+    }
+
+    #[test]
+    fn test_static_components_and_systems_follow_stage_order() {
+        let mut engine = Engine::new_headless();
+        let counter = Arc::new(Mutex::new(TickCounter::default()));
+        unsafe { engine.add_static(counter.clone()) };
+        let seen = Arc::new(Mutex::new(Vec::new()));
+
+        let (system_counter, system_seen) = (counter.clone(), seen.clone());
+        engine.add_system_to(Stage::PostUpdate, FnSystem::new("check", Access::new(), move |_, _| {
+            system_seen.lock().unwrap().push(system_counter.lock().unwrap().ticks);
+        }));
+        engine.set_static_stage("TickCounter", Stage::PostUpdate);
+        engine.tick();
+        engine.order("check", "TickCounter");
+        engine.tick();
+
+        assert_eq!(*seen.lock().unwrap(), vec![1, 1]);
+        let dump = engine.schedule_dump();
+        let post_update = dump.split("PostUpdate\n").nth(1).unwrap();
+        assert!(post_update.starts_with("  timers\n  tasks\n  systems check\n  static TickCounter\n"));
+        assert!(dump.starts_with("PreUpdate\n  transform propagation\n"));
+    }
+
+    #[test]
+    fn test_static_components_receive_buffered_events() {
+        let mut engine = Engine::new_headless();
+        let counter = Arc::new(Mutex::new(TickCounter::default()));
+        unsafe { engine.add_static(counter.clone()) };
+
+        engine
+            .events()
+            .subscribe_static::<u32, TickCounter>(counter.clone(), Delivery::EndOfTick, |counter, ticks| {
+                counter.ticks += ticks;
+            });
+        engine.events().send(10u32);
+        assert_eq!(counter.lock().unwrap().ticks, 0);
+
+        engine.tick();
+        assert_eq!(counter.lock().unwrap().ticks, 11);
+    }
+
+    #[test]
+    fn test_static_components_get_shutdown_hooks() {
+        let mut engine = Engine::new_headless();
+        let counter = Arc::new(Mutex::new(TickCounter::default()));
+        unsafe { engine.add_static(counter.clone()) };
+
+        engine.tick();
+        assert!(!engine.is_quitting());
+        engine.quit();
+        engine.shutdown();
+        engine.shutdown();
+
+        let counter = counter.lock().unwrap();
+        assert_eq!((counter.ticks, counter.shutdowns), (1, 1));
     }
 }
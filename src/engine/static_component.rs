@@ -13,6 +13,8 @@ pub trait StaticComponent: Send + Sync + Downcast + StaticComponentSaveLoad + De
 where Self: 'static {
     fn tick(&mut self, engine: &mut Engine);
     fn name(&mut self) -> String;
+    /// Called once when the engine shuts down, e.g. to save progress.
+    fn on_shutdown(&mut self, engine: &mut Engine) {}
 }
 
 pub struct Container {
@@ -0,0 +1,568 @@
+use crate::engine::camera;
+use crate::engine::component::{ComponentTrait, ComponentType, InputData, Lifecycle, RenderOutput, TickVariant};
+use crate::engine::gameobject::GameObject;
+use crate::engine::world::ObjectId;
+use downcast_rs::{impl_downcast, DowncastSync};
+use log::warn;
+use serde_json::Value;
+use smallvec::SmallVec;
+use std::any::{type_name, TypeId};
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Thread safe `RefCell`: any number of shared borrows or a single exclusive one.
+/// Conflicting borrows fail straight away instead of blocking like a mutex would.
+pub struct BorrowCell<T> {
+    borrows: AtomicIsize,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for BorrowCell<T> {}
+unsafe impl<T: Send + Sync> Sync for BorrowCell<T> {}
+
+impl<T> BorrowCell<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            borrows: AtomicIsize::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn try_borrow(&self) -> Option<Ref<'_, T>> {
+        let mut current = self.borrows.load(Ordering::Relaxed);
+        loop {
+            if current < 0 {
+                return None;
+            }
+
+            match self.borrows.compare_exchange_weak(current, current + 1, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => return Some(Ref { cell: self }),
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    pub fn try_borrow_mut(&self) -> Option<RefMut<'_, T>> {
+        self.borrows
+            .compare_exchange(0, -1, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| RefMut { cell: self })
+    }
+
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.try_borrow()
+            .unwrap_or_else(|| panic!("{} is already mutably borrowed", type_name::<T>()))
+    }
+
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        self.try_borrow_mut()
+            .unwrap_or_else(|| panic!("{} is already borrowed", type_name::<T>()))
+    }
+}
+
+pub struct Ref<'a, T> {
+    cell: &'a BorrowCell<T>,
+}
+
+impl<T> Deref for Ref<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.cell.value.get() }
+    }
+}
+
+impl<T> Drop for Ref<'_, T> {
+    fn drop(&mut self) {
+        self.cell.borrows.fetch_sub(1, Ordering::Release);
+    }
+}
+
+pub struct RefMut<'a, T> {
+    cell: &'a BorrowCell<T>,
+}
+
+impl<T> Deref for RefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.cell.value.get() }
+    }
+}
+
+impl<T> DerefMut for RefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.cell.value.get() }
+    }
+}
+
+impl<T> Drop for RefMut<'_, T> {
+    fn drop(&mut self) {
+        self.cell.borrows.store(0, Ordering::Release);
+    }
+}
+
+/// Packs every component of one type into a single `Vec`, indexed by object through a
+/// sparse array. An object can hold more than one instance.
+pub struct SparseSet<T> {
+    sparse: Vec<SmallVec<[u32; 1]>>,
+    dense: Vec<T>,
+    owners: Vec<ObjectId>,
+}
+
+impl<T> SparseSet<T> {
+    pub fn new() -> Self {
+        Self {
+            sparse: Vec::new(),
+            dense: Vec::new(),
+            owners: Vec::new(),
+        }
+    }
+
+    fn indices(&self, id: ObjectId) -> &[u32] {
+        match self.sparse.get(id.index as usize) {
+            // The slot may still point at components of a destroyed object with the same index
+            Some(indices) if indices.first().is_some_and(|i| self.owners[*i as usize] == id) => indices,
+            _ => &[],
+        }
+    }
+
+    pub fn insert(&mut self, id: ObjectId, value: T) {
+        let index = id.index as usize;
+        if self.sparse.len() <= index {
+            self.sparse.resize_with(index + 1, SmallVec::new);
+        }
+
+        self.sparse[index].push(self.dense.len() as u32);
+        self.dense.push(value);
+        self.owners.push(id);
+    }
+
+    pub fn contains(&self, id: ObjectId) -> bool {
+        !self.indices(id).is_empty()
+    }
+
+    pub fn count(&self, id: ObjectId) -> usize {
+        self.indices(id).len()
+    }
+
+    pub fn get(&self, id: ObjectId) -> Option<&T> {
+        let index = *self.indices(id).first()?;
+        Some(&self.dense[index as usize])
+    }
+
+    pub fn get_mut(&mut self, id: ObjectId) -> Option<&mut T> {
+        let index = *self.indices(id).first()?;
+        Some(&mut self.dense[index as usize])
+    }
+
+    /// First instance of each of `ids`, all borrowed at once. `ids` must not repeat.
+    pub fn get_many_mut(&mut self, ids: &[ObjectId]) -> Vec<Option<&mut T>> {
+        let indices: Vec<Option<u32>> = ids.iter().map(|id| self.indices(*id).first().copied()).collect();
+        let mut slots: Vec<Option<&mut T>> = self.dense.iter_mut().map(Some).collect();
+        indices
+            .into_iter()
+            .map(|index| slots[index? as usize].take())
+            .collect()
+    }
+
+    pub fn get_many(&self, ids: &[ObjectId]) -> Vec<Option<&T>> {
+        ids.iter().map(|id| self.get(*id)).collect()
+    }
+
+    /// Calls `f` on every instance `id` holds, in insertion order.
+    pub fn for_each_of(&mut self, id: ObjectId, mut f: impl FnMut(&mut T)) {
+        let indices: SmallVec<[u32; 4]> = self.indices(id).iter().copied().collect();
+        for index in indices {
+            f(&mut self.dense[index as usize]);
+        }
+    }
+
+    pub fn remove(&mut self, id: ObjectId) -> Vec<T> {
+        let mut indices: SmallVec<[u32; 4]> = self.indices(id).iter().copied().collect();
+        if indices.is_empty() {
+            return Vec::new();
+        }
+        self.sparse[id.index as usize].clear();
+
+        // Highest first so the swapped in elements never belong to `id`
+        indices.sort_unstable_by(|a, b| b.cmp(a));
+        let mut removed = Vec::with_capacity(indices.len());
+        for index in indices {
+            let index = index as usize;
+            let last = self.dense.len() - 1;
+
+            removed.push(self.dense.swap_remove(index));
+            self.owners.swap_remove(index);
+
+            if index != last {
+                let moved = self.owners[index];
+                for slot in self.sparse[moved.index as usize].iter_mut() {
+                    if *slot as usize == last {
+                        *slot = index as u32;
+                    }
+                }
+            }
+        }
+
+        removed.reverse();
+        removed
+    }
+
+    pub fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+
+    /// Owner of every instance, lined up with [`SparseSet::iter`].
+    pub fn owners(&self) -> &[ObjectId] {
+        &self.owners
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ObjectId, &T)> {
+        self.owners.iter().copied().zip(self.dense.iter())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (ObjectId, &mut T)> {
+        self.owners.iter().copied().zip(self.dense.iter_mut())
+    }
+}
+
+impl<T> Default for SparseSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Type erased view of a [`Storage`], what [`GameObject`]s and the [`World`](crate::engine::world::World)
+/// hold on to.
+pub trait ComponentStorage: DowncastSync {
+    fn component_type(&self) -> TypeId;
+    /// How the components tick, render components run in the render update instead of
+    /// the simulation step.
+    fn tick_type(&self) -> ComponentType;
+    fn contains(&self, id: ObjectId) -> bool;
+    fn remove(&self, id: ObjectId);
+    fn tick(
+        &self,
+        id: ObjectId,
+        input: Option<&InputData>,
+        obj: &mut GameObject,
+        dt: Duration,
+        cam: camera::Camera,
+    ) -> Vec<RenderOutput>;
+    /// Runs the hook for `event` on every instance `id` holds.
+    fn lifecycle(&self, id: ObjectId, obj: &mut GameObject, event: Lifecycle);
+    /// Name and save data of every instance `id` holds, nothing if the storage is
+    /// already borrowed.
+    fn save(&self, id: ObjectId) -> Vec<(String, Value)>;
+}
+
+impl_downcast!(sync ComponentStorage);
+
+// A change made while the set was borrowed, applied in order once it's free again
+enum Pending<T> {
+    Insert(ObjectId, T),
+    Remove(ObjectId),
+}
+
+/// All components of type `T` along with how they tick.
+pub struct Storage<T: ComponentTrait> {
+    set: BorrowCell<SparseSet<T>>,
+    ticker: TickVariant<T>,
+    // How many instances each object holds, pending ones included, so membership can be
+    // answered while the set is borrowed
+    counts: Mutex<HashMap<ObjectId, usize>>,
+    // Inserts and removals that happened while the set was borrowed, e.g. from a tick of
+    // the same type
+    pending: Mutex<Vec<Pending<T>>>,
+    has_pending: AtomicBool,
+    // How many of each object's newest instances haven't had `on_start` yet
+    unstarted: Mutex<HashMap<ObjectId, usize>>,
+    has_unstarted: AtomicBool,
+}
+
+impl<T: ComponentTrait> Storage<T> {
+    pub fn new(ticker: TickVariant<T>) -> Self {
+        Self {
+            set: BorrowCell::new(SparseSet::new()),
+            ticker,
+            counts: Mutex::new(HashMap::new()),
+            pending: Mutex::new(Vec::new()),
+            has_pending: AtomicBool::new(false),
+            unstarted: Mutex::new(HashMap::new()),
+            has_unstarted: AtomicBool::new(false),
+        }
+    }
+
+    pub fn insert(&self, id: ObjectId, value: T) {
+        *self.unstarted.lock().unwrap().entry(id).or_insert(0) += 1;
+        self.has_unstarted.store(true, Ordering::Release);
+        *self.counts.lock().unwrap().entry(id).or_insert(0) += 1;
+
+        match self.set.try_borrow_mut() {
+            Some(mut set) => {
+                self.apply_pending(&mut set);
+                set.insert(id, value);
+            }
+            None => self.defer(Pending::Insert(id, value)),
+        }
+    }
+
+    fn defer(&self, change: Pending<T>) {
+        self.pending.lock().unwrap().push(change);
+        self.has_pending.store(true, Ordering::Release);
+    }
+
+    fn take_unstarted(&self, id: ObjectId) -> usize {
+        let mut unstarted = self.unstarted.lock().unwrap();
+        let count = unstarted.remove(&id).unwrap_or(0);
+        if unstarted.is_empty() {
+            self.has_unstarted.store(false, Ordering::Release);
+        }
+        count
+    }
+
+    fn apply_pending(&self, set: &mut SparseSet<T>) {
+        if !self.has_pending.swap(false, Ordering::Acquire) {
+            return;
+        }
+
+        for change in self.pending.lock().unwrap().drain(..) {
+            match change {
+                Pending::Insert(id, value) => set.insert(id, value),
+                Pending::Remove(id) => {
+                    set.remove(id);
+                }
+            }
+        }
+    }
+
+    pub fn try_borrow(&self) -> Option<Ref<'_, SparseSet<T>>> {
+        self.set.try_borrow()
+    }
+
+    pub fn try_borrow_mut(&self) -> Option<RefMut<'_, SparseSet<T>>> {
+        let mut set = self.set.try_borrow_mut()?;
+        self.apply_pending(&mut set);
+        Some(set)
+    }
+
+    pub fn borrow(&self) -> Ref<'_, SparseSet<T>> {
+        self.set.borrow()
+    }
+
+    pub fn borrow_mut(&self) -> RefMut<'_, SparseSet<T>> {
+        let mut set = self.set.borrow_mut();
+        self.apply_pending(&mut set);
+        set
+    }
+}
+
+impl<T: ComponentTrait> ComponentStorage for Storage<T> {
+    fn component_type(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+
+    fn tick_type(&self) -> ComponentType {
+        self.ticker.component_type()
+    }
+
+    fn contains(&self, id: ObjectId) -> bool {
+        self.counts.lock().unwrap().contains_key(&id)
+    }
+
+    fn remove(&self, id: ObjectId) {
+        self.counts.lock().unwrap().remove(&id);
+        self.unstarted.lock().unwrap().remove(&id);
+        match self.try_borrow_mut() {
+            Some(mut set) => {
+                set.remove(id);
+            }
+            None => self.defer(Pending::Remove(id)),
+        }
+    }
+
+    fn tick(
+        &self,
+        id: ObjectId,
+        input: Option<&InputData>,
+        obj: &mut GameObject,
+        dt: Duration,
+        cam: camera::Camera,
+    ) -> Vec<RenderOutput> {
+        let mut set = match self.try_borrow_mut() {
+            Some(set) => set,
+            None => {
+                warn!("Skipping tick of {}, its storage is already borrowed", type_name::<T>());
+                return Vec::new();
+            }
+        };
+
+        let unstarted = match self.has_unstarted.load(Ordering::Acquire) {
+            true => self.take_unstarted(id),
+            false => 0,
+        };
+        let started = set.count(id) - unstarted.min(set.count(id));
+
+        let mut out = Vec::new();
+        let mut index = 0;
+        set.for_each_of(id, |component| {
+            if index >= started {
+                component.on_start(obj);
+            }
+            index += 1;
+
+            if let Some(render) = self.ticker.tick(component, input, obj, dt, cam.clone()) {
+                out.push(render);
+            }
+        });
+        out
+    }
+
+    fn lifecycle(&self, id: ObjectId, obj: &mut GameObject, event: Lifecycle) {
+        let mut set = match self.try_borrow_mut() {
+            Some(set) => set,
+            None => {
+                warn!("Skipping {:?} of {}, its storage is already borrowed", event, type_name::<T>());
+                return;
+            }
+        };
+
+        set.for_each_of(id, |component| match event {
+            Lifecycle::Enable => component.on_enable(obj),
+            Lifecycle::Disable => component.on_disable(obj),
+            Lifecycle::Destroy => component.on_destroy(obj),
+            Lifecycle::Shutdown => component.on_shutdown(obj),
+        });
+    }
+
+    fn save(&self, id: ObjectId) -> Vec<(String, Value)> {
+        let mut set = match self.try_borrow_mut() {
+            Some(set) => set,
+            None => {
+                warn!("Skipping the save data of {}, its storage is already borrowed", type_name::<T>());
+                return Vec::new();
+            }
+        };
+
+        let mut out = Vec::new();
+        set.for_each_of(id, |component| {
+            if component.persistent() {
+                out.push((component.name().to_string(), ComponentTrait::to_save_data(component)));
+            }
+        });
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::component::{TickBehavior, Transform};
+
+    #[test]
+    fn test_sparse_set_insert_get_remove() {
+        let mut set = SparseSet::new();
+        let a = ObjectId::new(0, 0);
+        let b = ObjectId::new(5, 0);
+        let c = ObjectId::new(2, 0);
+
+        set.insert(a, "a");
+        set.insert(b, "b1");
+        set.insert(c, "c");
+        set.insert(b, "b2");
+
+        assert_eq!(set.get(b), Some(&"b1"));
+        assert_eq!(set.count(b), 2);
+
+        assert_eq!(set.remove(b), vec!["b1", "b2"]);
+        assert!(!set.contains(b));
+        assert_eq!(set.get(a), Some(&"a"));
+        assert_eq!(set.get(c), Some(&"c"));
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn test_sparse_set_ignores_stale_ids() {
+        let mut set = SparseSet::new();
+        set.insert(ObjectId::new(1, 0), 1);
+
+        assert!(set.get(ObjectId::new(1, 1)).is_none());
+        assert!(set.remove(ObjectId::new(1, 1)).is_empty());
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn test_storage_defers_inserts_while_borrowed() {
+        let storage = Storage::new(TickVariant::Default(<Transform as TickBehavior>::tick));
+        let id = ObjectId::new(0, 0);
+        let mut transform = Transform::default();
+        transform.pos = [1.0, 2.0, 3.0];
+
+        let set = storage.borrow();
+        storage.insert(id, transform);
+        assert!(!set.contains(id));
+        drop(set);
+
+        assert_eq!(storage.borrow_mut().get(id).unwrap().pos, [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn test_storage_answers_and_defers_removals_while_borrowed() {
+        let storage = Storage::new(TickVariant::Default(<Transform as TickBehavior>::tick));
+        let id = ObjectId::new(0, 0);
+        storage.insert(id, Transform::default());
+
+        // Like a query run from a tick of the same type
+        let set = storage.borrow_mut();
+        assert!(storage.contains(id));
+        storage.remove(id);
+        assert!(!storage.contains(id));
+        storage.insert(id, Transform::default());
+        assert!(storage.contains(id));
+        assert_eq!(set.count(id), 1);
+        drop(set);
+
+        assert_eq!(storage.borrow_mut().count(id), 1);
+        storage.remove(id);
+        assert!(!storage.contains(id) && storage.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_storage_skips_saving_while_borrowed() {
+        let storage = Storage::new(TickVariant::Default(<Transform as TickBehavior>::tick));
+        let id = ObjectId::new(0, 0);
+        storage.insert(id, Transform::default());
+
+        let set = storage.borrow();
+        assert!(storage.save(id).is_empty());
+        drop(set);
+
+        assert_eq!(storage.save(id).len(), 1);
+    }
+
+    #[test]
+    fn test_borrow_cell_rejects_conflicting_borrows() {
+        let cell = BorrowCell::new(0);
+
+        let shared = cell.borrow();
+        assert!(cell.try_borrow().is_some());
+        assert!(cell.try_borrow_mut().is_none());
+        drop(shared);
+
+        let mut exclusive = cell.borrow_mut();
+        *exclusive += 1;
+        assert!(cell.try_borrow().is_none());
+        drop(exclusive);
+
+        assert_eq!(*cell.borrow(), 1);
+    }
+}
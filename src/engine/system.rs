@@ -0,0 +1,603 @@
+use crate::engine::component::ComponentTrait;
+use crate::engine::query::QueryParams;
+use crate::engine::world::{ObjectId, World};
+use log::warn;
+use std::any::TypeId;
+use std::collections::{BTreeMap, HashMap};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The component types a system reads and writes. Two systems can run side by side as
+/// long as neither writes something the other touches.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Access {
+    reads: Vec<TypeId>,
+    writes: Vec<TypeId>,
+}
+
+impl Access {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn read<T: ComponentTrait>(mut self) -> Self {
+        self.add_read(TypeId::of::<T>());
+        self
+    }
+
+    pub fn write<T: ComponentTrait>(mut self) -> Self {
+        self.add_write(TypeId::of::<T>());
+        self
+    }
+
+    pub fn add_read(&mut self, type_id: TypeId) {
+        if !self.reads.contains(&type_id) {
+            self.reads.push(type_id);
+        }
+    }
+
+    pub fn add_write(&mut self, type_id: TypeId) {
+        if !self.writes.contains(&type_id) {
+            self.writes.push(type_id);
+        }
+    }
+
+    pub fn reads(&self) -> &[TypeId] {
+        &self.reads
+    }
+
+    pub fn writes(&self) -> &[TypeId] {
+        &self.writes
+    }
+
+    pub fn conflicts_with(&self, other: &Access) -> bool {
+        self.writes
+            .iter()
+            .any(|type_id| other.reads.contains(type_id) || other.writes.contains(type_id))
+            || other.writes.iter().any(|type_id| self.reads.contains(type_id))
+    }
+}
+
+/// Logic that runs once per tick over the world rather than per object. The scheduler
+/// trusts [`System::access`]; touching undeclared components from `run` can panic on a
+/// borrow conflict when systems run in parallel.
+pub trait System: Send {
+    fn name(&self) -> String;
+    fn access(&self) -> Access;
+    fn run(&mut self, world: &Arc<World>, dt: Duration);
+}
+
+/// A [`System`] that runs a closure over a [`Query`](crate::engine::query::Query) of `Q`,
+/// split into object batches across the thread pool. Access is taken from `Q`: plain
+/// components are writes, [`Read`](crate::engine::query::Read) terms are reads.
+pub struct QuerySystem<Q, F> {
+    name: String,
+    f: F,
+    _params: PhantomData<fn() -> Q>,
+}
+
+impl<Q, F> QuerySystem<Q, F>
+where
+    Q: QueryParams + 'static,
+    F: for<'a> Fn(ObjectId, Q::Items<'a>, Duration) + Send + Sync + 'static,
+    for<'a> Q::Items<'a>: Send,
+{
+    pub fn new(name: &str, f: F) -> Self {
+        Self {
+            name: name.to_string(),
+            f,
+            _params: PhantomData,
+        }
+    }
+}
+
+impl<Q, F> System for QuerySystem<Q, F>
+where
+    Q: QueryParams + 'static,
+    F: for<'a> Fn(ObjectId, Q::Items<'a>, Duration) + Send + Sync + 'static,
+    for<'a> Q::Items<'a>: Send,
+{
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn access(&self) -> Access {
+        Q::access()
+    }
+
+    fn run(&mut self, world: &Arc<World>, dt: Duration) {
+        let f = &self.f;
+        world.query::<Q>().par_for_each(|id, items| f(id, items, dt));
+    }
+}
+
+/// A [`System`] from a closure with an explicitly declared [`Access`].
+pub struct FnSystem<F> {
+    name: String,
+    access: Access,
+    f: F,
+}
+
+impl<F> FnSystem<F>
+where
+    F: FnMut(&Arc<World>, Duration) + Send + 'static,
+{
+    pub fn new(name: &str, access: Access, f: F) -> Self {
+        Self {
+            name: name.to_string(),
+            access,
+            f,
+        }
+    }
+}
+
+impl<F> System for FnSystem<F>
+where
+    F: FnMut(&Arc<World>, Duration) + Send + 'static,
+{
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn access(&self) -> Access {
+        self.access.clone()
+    }
+
+    fn run(&mut self, world: &Arc<World>, dt: Duration) {
+        (self.f)(world, dt)
+    }
+}
+
+/// Where in the engine's loop something runs. Every simulation step goes through the
+/// stages from `PreUpdate` to `PhysicsStep`, `Render` runs in each render update.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Stage {
+    PreUpdate,
+    Update,
+    PostUpdate,
+    PhysicsStep,
+    Render,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 5] = [Stage::PreUpdate, Stage::Update, Stage::PostUpdate, Stage::PhysicsStep, Stage::Render];
+}
+
+/// One entry of a stage's plan.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Step {
+    /// Index of a static component in the engine state
+    Static(usize),
+    /// Batches of systems, the systems in a batch run side by side
+    Systems(Vec<Vec<usize>>),
+}
+
+/// Runs systems on a thread pool and decides where static components run. Systems are
+/// grouped into batches: each system lands in the first batch after every earlier
+/// system it conflicts with, so conflicting systems keep the order they were added in
+/// and the result matches a sequential run.
+///
+/// Static components run in `PreUpdate` and systems in `Update` unless told otherwise.
+/// Within a stage static components run before systems, both in the order they were
+/// added, except where [`Scheduler::order`] says otherwise.
+pub struct Scheduler {
+    systems: Vec<Box<dyn System>>,
+    system_stages: Vec<Stage>,
+    statics: Vec<String>,
+    static_stages: HashMap<String, Stage>,
+    // (first, then) pairs of system or static component names
+    order: Vec<(String, String)>,
+    plans: BTreeMap<Stage, Vec<Step>>,
+    pool: Option<rayon::ThreadPool>,
+}
+
+impl Scheduler {
+    /// Uses rayon's global pool, one thread per core.
+    pub fn new() -> Self {
+        Self {
+            systems: Vec::new(),
+            system_stages: Vec::new(),
+            statics: Vec::new(),
+            static_stages: HashMap::new(),
+            order: Vec::new(),
+            plans: BTreeMap::new(),
+            pool: None,
+        }
+    }
+
+    /// Uses a dedicated pool of `threads` threads. With one thread everything runs in
+    /// the order it was added.
+    pub fn with_threads(threads: usize) -> Self {
+        let mut scheduler = Self::new();
+        scheduler.set_threads(threads);
+        scheduler
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.pool = match rayon::ThreadPoolBuilder::new().num_threads(threads.max(1)).build() {
+            Ok(pool) => Some(pool),
+            Err(err) => {
+                warn!("Couldn't build a {} thread pool, using the global one: {}", threads, err);
+                None
+            }
+        };
+    }
+
+    pub fn threads(&self) -> usize {
+        match &self.pool {
+            Some(pool) => pool.current_num_threads(),
+            None => rayon::current_num_threads(),
+        }
+    }
+
+    /// Adds a system to the `Update` stage.
+    pub fn add(&mut self, system: impl System + 'static) {
+        self.add_to(Stage::Update, system);
+    }
+
+    pub fn add_to(&mut self, stage: Stage, system: impl System + 'static) {
+        self.systems.push(Box::new(system));
+        self.system_stages.push(stage);
+        self.build_plans();
+    }
+
+    pub(crate) fn add_static(&mut self, name: String) {
+        self.statics.push(name);
+        self.build_plans();
+    }
+
+    pub(crate) fn remove_static(&mut self, index: usize) {
+        self.statics.remove(index);
+        self.build_plans();
+    }
+
+    pub(crate) fn static_count(&self) -> usize {
+        self.statics.len()
+    }
+
+    /// Moves every static component called `name`, including ones added later.
+    pub fn set_static_stage(&mut self, name: &str, stage: Stage) {
+        self.static_stages.insert(name.to_string(), stage);
+        self.build_plans();
+    }
+
+    /// Makes the systems or static components called `first` run before the ones called
+    /// `then`. Only applies within a stage, the stages themselves always run in order.
+    pub fn order(&mut self, first: &str, then: &str) {
+        self.order.push((first.to_string(), then.to_string()));
+        self.build_plans();
+    }
+
+    pub fn len(&self) -> usize {
+        self.systems.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.systems.is_empty()
+    }
+
+    /// System names grouped by the batch they run in, stage by stage.
+    pub fn stages(&self) -> Vec<Vec<String>> {
+        self.plans
+            .values()
+            .flatten()
+            .filter_map(|step| match step {
+                Step::Systems(batches) => Some(batches),
+                Step::Static(_) => None,
+            })
+            .flatten()
+            .map(|batch| batch.iter().map(|i| self.systems[*i].name()).collect())
+            .collect()
+    }
+
+    pub(crate) fn plan(&self, stage: Stage) -> &[Step] {
+        self.plans.get(&stage).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// One line per step of `stage`, static components by name and systems that run
+    /// side by side joined with `|`.
+    pub fn describe(&self, stage: Stage) -> Vec<String> {
+        let mut lines = Vec::new();
+        for step in self.plan(stage) {
+            match step {
+                Step::Static(i) => lines.push(format!("static {}", self.statics[*i])),
+                Step::Systems(batches) => {
+                    for batch in batches {
+                        let names: Vec<String> = batch.iter().map(|i| self.systems[*i].name()).collect();
+                        lines.push(format!("systems {}", names.join(" | ")));
+                    }
+                }
+            }
+        }
+        lines
+    }
+
+    fn static_stage(&self, name: &str) -> Stage {
+        self.static_stages.get(name).copied().unwrap_or(Stage::PreUpdate)
+    }
+
+    fn build_plans(&mut self) {
+        // (is static, index, name)
+        let mut items: BTreeMap<Stage, Vec<(bool, usize, String)>> = BTreeMap::new();
+        for (i, name) in self.statics.iter().enumerate() {
+            items.entry(self.static_stage(name)).or_default().push((true, i, name.clone()));
+        }
+        for (i, system) in self.systems.iter().enumerate() {
+            items.entry(self.system_stages[i]).or_default().push((false, i, system.name()));
+        }
+
+        for (first, then) in self.order.iter() {
+            let stages_of = |name: &String| -> Vec<Stage> {
+                items.iter().filter(|(_, items)| items.iter().any(|item| &item.2 == name)).map(|(stage, _)| *stage).collect()
+            };
+            let (first_stages, then_stages) = (stages_of(first), stages_of(then));
+            if first_stages.iter().any(|a| then_stages.iter().any(|b| a > b)) {
+                warn!("{} can't run before {}, it's in a later stage", first, then);
+            }
+        }
+
+        self.plans = items.into_iter().map(|(stage, items)| (stage, self.build_plan(items))).collect();
+    }
+
+    fn build_plan(&self, items: Vec<(bool, usize, String)>) -> Vec<Step> {
+        let after: Vec<Vec<usize>> = items
+            .iter()
+            .map(|(_, _, name)| {
+                (0..items.len())
+                    .filter(|j| self.order.iter().any(|(first, then)| first == &items[*j].2 && then == name))
+                    .collect()
+            })
+            .collect();
+
+        // Topological sort, picking the earliest ready item so unconstrained items keep
+        // their order
+        let mut sorted: Vec<usize> = Vec::with_capacity(items.len());
+        let mut placed = vec![false; items.len()];
+        while sorted.len() < items.len() {
+            let ready = (0..items.len()).find(|i| !placed[*i] && after[*i].iter().all(|j| placed[*j] || j == i));
+            let next = match ready {
+                Some(next) => next,
+                None => {
+                    let next = (0..items.len()).find(|i| !placed[*i]).unwrap();
+                    warn!("Ordering constraints around {} form a cycle, ignoring them", items[next].2);
+                    next
+                }
+            };
+            placed[next] = true;
+            sorted.push(next);
+        }
+
+        let mut plan = Vec::new();
+        let mut group: Vec<usize> = Vec::new();
+        for i in sorted {
+            let (is_static, index, _) = &items[i];
+            if *is_static {
+                if !group.is_empty() {
+                    plan.push(Step::Systems(self.batches(&std::mem::take(&mut group), &items, &after)));
+                }
+                plan.push(Step::Static(*index));
+            } else {
+                group.push(i);
+            }
+        }
+        if !group.is_empty() {
+            plan.push(Step::Systems(self.batches(&group, &items, &after)));
+        }
+        plan
+    }
+
+    fn batches(&self, group: &[usize], items: &[(bool, usize, String)], after: &[Vec<usize>]) -> Vec<Vec<usize>> {
+        let access: Vec<Access> = group.iter().map(|i| self.systems[items[*i].1].access()).collect();
+        let mut batch_of: Vec<usize> = Vec::with_capacity(group.len());
+        let mut batches: Vec<Vec<usize>> = Vec::new();
+
+        for (k, i) in group.iter().enumerate() {
+            let batch = (0..k)
+                .filter(|j| access[*j].conflicts_with(&access[k]) || after[*i].contains(&group[*j]))
+                .map(|j| batch_of[j] + 1)
+                .max()
+                .unwrap_or(0);
+
+            if batches.len() <= batch {
+                batches.resize_with(batch + 1, Vec::new);
+            }
+            batches[batch].push(items[*i].1);
+            batch_of.push(batch);
+        }
+
+        batches
+    }
+
+    /// Runs the systems of every stage, stage by stage. Static components are left to
+    /// the engine.
+    pub fn run(&mut self, world: &Arc<World>, dt: Duration) {
+        for stage in Stage::ALL {
+            for step in self.plan(stage).to_vec() {
+                if let Step::Systems(batches) = step {
+                    self.run_batches(&batches, world, dt);
+                }
+            }
+        }
+    }
+
+    /// Runs every system one after another on the calling thread, in the order
+    /// [`Scheduler::run`] runs their batches: stage by stage, following
+    /// [`Scheduler::order`], systems that don't conflict grouped together.
+    pub fn run_sequential(&mut self, world: &Arc<World>, dt: Duration) {
+        let order: Vec<usize> = self
+            .plans
+            .values()
+            .flatten()
+            .filter_map(|step| match step {
+                Step::Systems(batches) => Some(batches.concat()),
+                Step::Static(_) => None,
+            })
+            .flatten()
+            .collect();
+        for i in order {
+            self.systems[i].run(world, dt);
+        }
+    }
+
+    pub(crate) fn run_batches(&mut self, batches: &[Vec<usize>], world: &Arc<World>, dt: Duration) {
+        match self.pool.take() {
+            Some(pool) => {
+                pool.install(|| self.run_batches_in_pool(batches, world, dt));
+                self.pool = Some(pool);
+            }
+            None => self.run_batches_in_pool(batches, world, dt),
+        }
+    }
+
+    fn run_batches_in_pool(&mut self, batches: &[Vec<usize>], world: &Arc<World>, dt: Duration) {
+        let sequential = rayon::current_num_threads() == 1;
+
+        for batch in batches {
+            let mut systems: Vec<&mut Box<dyn System>> = self
+                .systems
+                .iter_mut()
+                .enumerate()
+                .filter(|(i, _)| batch.contains(i))
+                .map(|(_, system)| system)
+                .collect();
+
+            if sequential || systems.len() == 1 {
+                for system in systems {
+                    system.run(world, dt);
+                }
+                continue;
+            }
+
+            rayon::scope(|scope| {
+                for system in systems {
+                    scope.spawn(move |_| system.run(world, dt));
+                }
+            });
+        }
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::component::{CharacterController2D, Transform};
+    use crate::engine::components::RenderComponent;
+    use crate::engine::gameobject::make_base_game_object;
+    use crate::engine::graphics_backend::primitives::Primitives;
+    use crate::engine::query::Read;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn populate(objects: usize) -> Arc<World> {
+        let world = World::new();
+        for i in 0..objects {
+            let obj = make_base_game_object(&world, format!("object {}", i));
+            let mut obj = obj.lock().unwrap();
+            obj.get_component_closure::<Transform>(|transform| transform.pos = [i as f32, 0.0, 0.0]);
+            if i % 3 == 0 {
+                obj.add_component(RenderComponent::new(Primitives::Cube(0.1, [1.0, 0.0, 0.0]))).unwrap();
+            }
+        }
+        world
+    }
+
+    fn positions(world: &Arc<World>) -> Vec<(ObjectId, [f32; 3])> {
+        let mut positions = Vec::new();
+        world.query::<(Transform,)>().for_each(|id, (transform,)| positions.push((id, transform.pos)));
+        positions
+    }
+
+    fn add_systems(scheduler: &mut Scheduler, rendered: Arc<AtomicUsize>) {
+        scheduler.add(QuerySystem::<(Transform,), _>::new("move", |_, (transform,), dt| {
+            transform.pos[1] += dt.as_secs_f32();
+        }));
+        scheduler.add(FnSystem::new("count", Access::new().read::<RenderComponent>(), move |world, _| {
+            rendered.fetch_add(world.query::<(Read<RenderComponent>,)>().count(), Ordering::Relaxed);
+        }));
+        // Conflicts with "move", so it has to see its output
+        scheduler.add(QuerySystem::<(Transform, Option<RenderComponent>), _>::new(
+            "double",
+            |_, (transform, render), _| {
+                let factor = if render.is_some() { 3.0 } else { 2.0 };
+                transform.pos[1] *= factor;
+            },
+        ));
+    }
+
+    #[test]
+    fn test_access_conflicts() {
+        let read = Access::new().read::<Transform>();
+        let write = Access::new().write::<Transform>();
+        let other = Access::new().write::<RenderComponent>();
+
+        assert!(!read.conflicts_with(&read));
+        assert!(read.conflicts_with(&write));
+        assert!(write.conflicts_with(&read));
+        assert!(write.conflicts_with(&write));
+        assert!(!write.conflicts_with(&other));
+        assert_eq!(<(Read<Transform>, RenderComponent)>::access(), Access::new().read::<Transform>().write::<RenderComponent>());
+    }
+
+    #[test]
+    fn test_conflicting_systems_run_in_later_stages() {
+        let mut scheduler = Scheduler::new();
+        add_systems(&mut scheduler, Arc::new(AtomicUsize::new(0)));
+        scheduler.add(FnSystem::new("controller", Access::new().write::<CharacterController2D>(), |_, _| {}));
+
+        assert_eq!(
+            scheduler.stages(),
+            vec![
+                vec!["move".to_string(), "count".to_string(), "controller".to_string()],
+                vec!["double".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ordering_constraints_and_stages() {
+        let mut scheduler = Scheduler::new();
+        add_systems(&mut scheduler, Arc::new(AtomicUsize::new(0)));
+        scheduler.add_to(Stage::PreUpdate, FnSystem::new("early", Access::new(), |_, _| {}));
+        scheduler.order("count", "move");
+        scheduler.order("double", "early");
+
+        assert_eq!(
+            scheduler.stages(),
+            vec![
+                vec!["early".to_string()],
+                vec!["count".to_string()],
+                vec!["move".to_string()],
+                vec!["double".to_string()],
+            ]
+        );
+        assert_eq!(scheduler.describe(Stage::Update), vec!["systems count", "systems move", "systems double"]);
+    }
+
+    #[test]
+    fn test_parallel_run_matches_sequential() {
+        let dt = Duration::from_millis(16);
+        let parallel_world = populate(2000);
+        let sequential_world = populate(2000);
+        let parallel_rendered = Arc::new(AtomicUsize::new(0));
+        let sequential_rendered = Arc::new(AtomicUsize::new(0));
+
+        let mut parallel = Scheduler::with_threads(4);
+        let mut sequential = Scheduler::new();
+        add_systems(&mut parallel, parallel_rendered.clone());
+        add_systems(&mut sequential, sequential_rendered.clone());
+
+        for _ in 0..5 {
+            parallel.run(&parallel_world, dt);
+            sequential.run_sequential(&sequential_world, dt);
+        }
+
+        assert_eq!(positions(&parallel_world), positions(&sequential_world));
+        assert_eq!(parallel_rendered.load(Ordering::Relaxed), sequential_rendered.load(Ordering::Relaxed));
+    }
+}
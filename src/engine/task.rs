@@ -0,0 +1,265 @@
+use crate::engine::gameobject::MutexdGameObject;
+use crate::engine::time::Time;
+use crate::engine::world::{ObjectId, World};
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+type BoxedTask = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Refers to a running task.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TaskHandle(u64);
+
+struct Task {
+    owner: Option<ObjectId>,
+    future: BoxedTask,
+}
+
+#[derive(Default)]
+struct Running {
+    tasks: BTreeMap<u64, Task>,
+    next_id: u64,
+    // Tasks taken out to be polled, removing an id cancels the task once its poll returns
+    polling: BTreeSet<u64>,
+}
+
+/// Async tasks the engine polls once per simulation step, after the timers. Tasks owned
+/// by an object are dropped when it's destroyed. Nothing wakes them up in between, so
+/// they have to wait with [`TaskContext`], not with an async runtime's timers.
+pub struct Tasks {
+    running: Mutex<Running>,
+    world: Weak<World>,
+}
+
+impl Tasks {
+    pub(crate) fn new(world: Weak<World>) -> Self {
+        Self {
+            running: Mutex::new(Running::default()),
+            world,
+        }
+    }
+
+    /// Starts the future `f` returns, first polled at the end of the current step.
+    pub fn start<F, Fut>(&self, owner: Option<ObjectId>, f: F) -> TaskHandle
+    where
+        F: FnOnce(TaskContext) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let context = TaskContext { world: self.world.clone(), owner };
+        let task = Task { owner, future: Box::pin(f(context)) };
+
+        let mut running = self.running.lock().unwrap();
+        let id = running.next_id;
+        running.next_id += 1;
+        running.tasks.insert(id, task);
+        TaskHandle(id)
+    }
+
+    /// Returns whether the task was still running.
+    pub fn cancel(&self, handle: TaskHandle) -> bool {
+        let mut running = self.running.lock().unwrap();
+        running.tasks.remove(&handle.0).is_some() || running.polling.remove(&handle.0)
+    }
+
+    pub fn is_running(&self, handle: TaskHandle) -> bool {
+        let running = self.running.lock().unwrap();
+        running.tasks.contains_key(&handle.0) || running.polling.contains(&handle.0)
+    }
+
+    pub(crate) fn cancel_owned_by(&self, owner: ObjectId) {
+        self.running.lock().unwrap().tasks.retain(|_, task| task.owner != Some(owner));
+    }
+
+    pub fn len(&self) -> usize {
+        self.running.lock().unwrap().tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Polls every task once, in the order they were started.
+    pub(crate) fn poll(&self) {
+        let tasks = {
+            let mut running = self.running.lock().unwrap();
+            let tasks = std::mem::take(&mut running.tasks);
+            running.polling.extend(tasks.keys());
+            tasks
+        };
+
+        let world = self.world.upgrade();
+        let mut context = Context::from_waker(Waker::noop());
+        for (id, mut task) in tasks {
+            let owner_gone = match (task.owner, &world) {
+                (Some(owner), Some(world)) => !world.contains(owner),
+                _ => false,
+            };
+            let done = owner_gone || task.future.as_mut().poll(&mut context).is_ready();
+
+            let mut running = self.running.lock().unwrap();
+            if running.polling.remove(&id) && !done {
+                running.tasks.insert(id, task);
+            }
+        }
+    }
+}
+
+/// Handed to every task, what it waits on and reaches the world through.
+#[derive(Clone)]
+pub struct TaskContext {
+    world: Weak<World>,
+    owner: Option<ObjectId>,
+}
+
+impl TaskContext {
+    pub fn world(&self) -> Arc<World> {
+        self.world.upgrade().expect("Tasks outlived their world")
+    }
+
+    pub fn owner_id(&self) -> Option<ObjectId> {
+        self.owner
+    }
+
+    /// The object that started the task, unlocked since tasks run after the objects tick.
+    pub fn owner(&self) -> Option<MutexdGameObject> {
+        self.world().find_by_id(self.owner?)
+    }
+
+    pub fn time(&self) -> Time {
+        self.world().time()
+    }
+
+    /// Resolves once `duration` has passed on the engine clock.
+    pub fn wait(&self, duration: Duration) -> Wait {
+        Wait { context: self.clone(), until: self.time().total() + duration }
+    }
+
+    /// Resolves on the first step `condition` returns true, checking it straight away.
+    pub fn wait_until<F: FnMut() -> bool + Unpin>(&self, condition: F) -> WaitUntil<F> {
+        WaitUntil { condition }
+    }
+
+    /// Resolves on the next simulation step.
+    pub fn next_tick(&self) -> NextTick {
+        NextTick { context: self.clone(), tick: self.time().ticks() }
+    }
+}
+
+pub struct Wait {
+    context: TaskContext,
+    until: Duration,
+}
+
+impl Future for Wait {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+        match self.context.time().total() >= self.until {
+            true => Poll::Ready(()),
+            false => Poll::Pending,
+        }
+    }
+}
+
+pub struct WaitUntil<F> {
+    condition: F,
+}
+
+impl<F: FnMut() -> bool + Unpin> Future for WaitUntil<F> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+        match (self.condition)() {
+            true => Poll::Ready(()),
+            false => Poll::Pending,
+        }
+    }
+}
+
+pub struct NextTick {
+    context: TaskContext,
+    tick: u64,
+}
+
+impl Future for NextTick {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+        match self.context.time().ticks() > self.tick {
+            true => Poll::Ready(()),
+            false => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::component::Transform;
+    use crate::engine::gameobject::make_base_game_object;
+    use crate::engine::state::Engine;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn test_tasks_wait_on_the_engine_clock() {
+        let mut engine = Engine::new_headless();
+        engine.set_tick_rate(10.0);
+        let obj = engine.add_object(make_base_game_object(&engine.world(), "flashing".to_string())).get();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let go = Arc::new(AtomicBool::new(false));
+
+        let (task_log, task_go) = (log.clone(), go.clone());
+        obj.lock().unwrap().start_task(|ctx| async move {
+            task_log.lock().unwrap().push(ctx.time().ticks());
+            ctx.next_tick().await;
+            task_log.lock().unwrap().push(ctx.time().ticks());
+            ctx.wait(Duration::from_millis(300)).await;
+            task_log.lock().unwrap().push(ctx.time().ticks());
+            ctx.wait_until(|| task_go.load(Ordering::SeqCst)).await;
+
+            ctx.owner().unwrap().lock().unwrap().get_component_closure::<Transform>(|transform| {
+                transform.pos[0] = 1.0;
+            });
+        });
+
+        for _ in 0..6 {
+            engine.tick();
+        }
+        assert_eq!(*log.lock().unwrap(), vec![1, 2, 5]);
+        assert_eq!(engine.world().tasks().len(), 1);
+
+        go.store(true, Ordering::SeqCst);
+        engine.tick();
+        assert!(engine.world().tasks().is_empty());
+        let mut x = 0.0;
+        obj.lock().unwrap().get_component_closure::<Transform>(|transform| x = transform.pos[0]);
+        assert_eq!(x, 1.0);
+    }
+
+    #[test]
+    fn test_tasks_stop_with_their_owner() {
+        let mut engine = Engine::new_headless();
+        let world = engine.world();
+        let obj = engine.add_object(make_base_game_object(&world, "owner".to_string())).get();
+        let owned = obj.lock().unwrap().start_task(|ctx| async move {
+            loop {
+                ctx.next_tick().await;
+            }
+        });
+        let free = engine.start_task(|ctx| async move { ctx.wait(Duration::from_secs(60)).await });
+
+        engine.tick();
+        assert!(world.tasks().is_running(owned));
+        obj.lock().unwrap().destroy();
+        engine.tick();
+
+        assert!(!world.tasks().is_running(owned));
+        assert!(world.tasks().is_running(free));
+        assert!(world.tasks().cancel(free));
+        assert!(world.tasks().is_empty());
+    }
+}
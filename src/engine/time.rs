@@ -18,6 +18,21 @@ impl OxidizedInstant {
         }
     }
 
+    /// The engine clock's current time, scaled by its time scale. Unlike [`OxidizedInstant::now`]
+    /// it stops while the engine is paused and is restored with a save.
+    pub fn at(time: &Time) -> Self {
+        OxidizedInstant {
+            current_time_ms: time.total.as_secs_f64() * 1000.0,
+        }
+    }
+
+    /// The engine clock's current time, ignoring its time scale.
+    pub fn at_unscaled(time: &Time) -> Self {
+        OxidizedInstant {
+            current_time_ms: time.unscaled.as_secs_f64() * 1000.0,
+        }
+    }
+
     pub fn duration_since(&self, earlier: OxidizedInstant) -> Duration {
         let diff_ms = self.current_time_ms - earlier.current_time_ms;
         Duration::from_secs_f64(diff_ms / 1000.0)
@@ -84,3 +99,104 @@ impl Ord for OxidizedInstant {
         self.current_time_ms.partial_cmp(&other.current_time_ms).unwrap()
     }
 }
+
+/// The engine's clock, advanced by every simulation step. Scaled time follows the time
+/// scale (slow motion, hit-stop), unscaled time always moves at the simulation rate.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Time {
+    total: Duration,
+    unscaled: Duration,
+    delta: Duration,
+    unscaled_delta: Duration,
+    scale: f32,
+    ticks: u64,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self {
+            total: Duration::ZERO,
+            unscaled: Duration::ZERO,
+            delta: Duration::ZERO,
+            unscaled_delta: Duration::ZERO,
+            scale: 1.0,
+            ticks: 0,
+        }
+    }
+}
+
+impl Time {
+    pub(crate) fn advance(&mut self, step: Duration) {
+        self.unscaled_delta = step;
+        self.delta = step.mul_f64(self.scale as f64);
+        self.unscaled += self.unscaled_delta;
+        self.total += self.delta;
+        self.ticks += 1;
+    }
+
+    /// Scaled time since the engine started.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    pub fn unscaled(&self) -> Duration {
+        self.unscaled
+    }
+
+    /// Scaled length of the current step.
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn unscaled_delta(&self) -> Duration {
+        self.unscaled_delta
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// `0.5` runs at half speed, `0.0` freezes scaled time. Takes effect from the next step.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale.max(0.0);
+    }
+
+    /// Number of simulation steps run so far.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn now(&self) -> OxidizedInstant {
+        OxidizedInstant::at(self)
+    }
+
+    pub fn unscaled_now(&self) -> OxidizedInstant {
+        OxidizedInstant::at_unscaled(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_scale_only_affects_scaled_time() {
+        let mut time = Time::default();
+        let step = Duration::from_millis(100);
+        time.advance(step);
+        let start = time.now();
+
+        time.set_scale(0.5);
+        time.advance(step);
+        assert_eq!(time.delta(), Duration::from_millis(50));
+        assert_eq!(time.unscaled_delta(), step);
+        assert_eq!(time.total(), Duration::from_millis(150));
+        assert_eq!(time.unscaled(), Duration::from_millis(200));
+        assert_eq!(time.ticks(), 2);
+
+        time.set_scale(0.0);
+        time.advance(step);
+        assert!(((time.now() - start).as_secs_f64() - 0.05).abs() < 1e-9);
+        assert!(time.unscaled_now() > start);
+    }
+}
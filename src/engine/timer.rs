@@ -0,0 +1,293 @@
+use crate::engine::state::Engine;
+use crate::engine::world::World;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::Duration;
+
+type NamedCallback = Arc<dyn Fn(&mut Engine) + Send + Sync>;
+
+lazy_static! {
+    static ref TIMER_CALLBACKS: RwLock<HashMap<String, NamedCallback>> = RwLock::new(HashMap::new());
+}
+
+/// Registers a callback that timers can refer to by name. Only named timers are saved.
+pub fn register_timer_callback(name: &str, callback: impl Fn(&mut Engine) + Send + Sync + 'static) {
+    TIMER_CALLBACKS.write().unwrap().insert(name.to_string(), Arc::new(callback));
+    info!("Timer callback registered with name: {}", name);
+}
+
+/// Refers to a pending timer, stays valid across a save and load.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TimerHandle(u64);
+
+enum Callback {
+    Closure(Box<dyn FnMut(&mut Engine) + Send>),
+    Named(String),
+}
+
+pub(crate) struct Timer {
+    id: u64,
+    // On the scaled engine clock
+    due: Duration,
+    interval: Option<Duration>,
+    callback: Callback,
+}
+
+impl Timer {
+    pub(crate) fn fire(&mut self, engine: &mut Engine) {
+        match &mut self.callback {
+            Callback::Closure(callback) => callback(engine),
+            Callback::Named(name) => {
+                let callback = TIMER_CALLBACKS.read().unwrap().get(name).cloned();
+                match callback {
+                    Some(callback) => callback(engine),
+                    None => warn!("No timer callback registered with name: {}", name),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TimerSaveData {
+    id: u64,
+    due: Duration,
+    interval: Option<Duration>,
+    callback: String,
+}
+
+#[derive(Default)]
+struct Pending {
+    timers: HashMap<u64, Timer>,
+    next_id: u64,
+    // Timers taken out to fire, removing an id cancels a repeating timer from its own callback
+    firing: HashSet<u64>,
+}
+
+/// Callbacks that run once the engine clock reaches a point in time, checked every
+/// simulation step after the systems. They run on the scaled clock, so they pause with
+/// the engine and follow the time scale.
+pub struct Timers {
+    pending: Mutex<Pending>,
+    world: Weak<World>,
+}
+
+impl Timers {
+    pub(crate) fn new(world: Weak<World>) -> Self {
+        Self {
+            pending: Mutex::new(Pending::default()),
+            world,
+        }
+    }
+
+    fn now(&self) -> Duration {
+        self.world.upgrade().expect("Timers outlived their world").time().total()
+    }
+
+    fn add(&self, delay: Duration, interval: Option<Duration>, callback: Callback) -> TimerHandle {
+        let due = self.now() + delay;
+        let mut pending = self.pending.lock().unwrap();
+        let id = pending.next_id;
+        pending.next_id += 1;
+        pending.timers.insert(id, Timer { id, due, interval, callback });
+        TimerHandle(id)
+    }
+
+    /// Runs `callback` once, `delay` from now.
+    pub fn after(&self, delay: Duration, callback: impl FnMut(&mut Engine) + Send + 'static) -> TimerHandle {
+        self.add(delay, None, Callback::Closure(Box::new(callback)))
+    }
+
+    /// Runs `callback` every `interval` until cancelled, at most once per step.
+    pub fn every(&self, interval: Duration, callback: impl FnMut(&mut Engine) + Send + 'static) -> TimerHandle {
+        self.add(interval, Some(interval), Callback::Closure(Box::new(callback)))
+    }
+
+    /// Like [`Timers::after`] with a callback registered through [`register_timer_callback`],
+    /// so the timer is saved.
+    pub fn after_named(&self, delay: Duration, name: &str) -> TimerHandle {
+        self.add(delay, None, Callback::Named(name.to_string()))
+    }
+
+    pub fn every_named(&self, interval: Duration, name: &str) -> TimerHandle {
+        self.add(interval, Some(interval), Callback::Named(name.to_string()))
+    }
+
+    /// Returns whether the timer was still pending.
+    pub fn cancel(&self, handle: TimerHandle) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        pending.timers.remove(&handle.0).is_some() || pending.firing.remove(&handle.0)
+    }
+
+    pub fn is_pending(&self, handle: TimerHandle) -> bool {
+        let pending = self.pending.lock().unwrap();
+        pending.timers.contains_key(&handle.0) || pending.firing.contains(&handle.0)
+    }
+
+    /// Time left until the timer next fires.
+    pub fn remaining(&self, handle: TimerHandle) -> Option<Duration> {
+        let due = self.pending.lock().unwrap().timers.get(&handle.0)?.due;
+        Some(due.saturating_sub(self.now()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.lock().unwrap().timers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Takes the timers due at `now`, earliest first.
+    pub(crate) fn take_due(&self, now: Duration) -> Vec<Timer> {
+        let mut pending = self.pending.lock().unwrap();
+        let mut ids: Vec<(Duration, u64)> =
+            pending.timers.values().filter(|timer| timer.due <= now).map(|timer| (timer.due, timer.id)).collect();
+        ids.sort();
+
+        let mut due = Vec::new();
+        for (_, id) in ids {
+            pending.firing.insert(id);
+            due.extend(pending.timers.remove(&id));
+        }
+        due
+    }
+
+    /// Puts a fired timer back if it repeats and wasn't cancelled while firing.
+    pub(crate) fn finish(&self, mut timer: Timer) {
+        let mut pending = self.pending.lock().unwrap();
+        if !pending.firing.remove(&timer.id) {
+            return;
+        }
+
+        if let Some(interval) = timer.interval {
+            timer.due += interval;
+            pending.timers.insert(timer.id, timer);
+        }
+    }
+
+    /// Named timers, closure timers can't be saved and are left out.
+    pub(crate) fn save(&self) -> Vec<TimerSaveData> {
+        let pending = self.pending.lock().unwrap();
+        let mut out: Vec<TimerSaveData> = pending
+            .timers
+            .values()
+            .filter_map(|timer| match &timer.callback {
+                Callback::Named(name) => Some(TimerSaveData {
+                    id: timer.id,
+                    due: timer.due,
+                    interval: timer.interval,
+                    callback: name.clone(),
+                }),
+                Callback::Closure(_) => {
+                    warn!("Timer {} runs a closure and won't be saved", timer.id);
+                    None
+                }
+            })
+            .collect();
+        out.sort_by_key(|timer| timer.id);
+        out
+    }
+
+    pub(crate) fn restore(&self, timers: &[TimerSaveData]) {
+        let mut pending = self.pending.lock().unwrap();
+        for timer in timers {
+            pending.next_id = pending.next_id.max(timer.id + 1);
+            pending.timers.insert(
+                timer.id,
+                Timer {
+                    id: timer.id,
+                    due: timer.due,
+                    interval: timer.interval,
+                    callback: Callback::Named(timer.callback.clone()),
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn counter() -> (Arc<AtomicUsize>, impl FnMut(&mut Engine) + Send + 'static) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let inner = count.clone();
+        (count, move |_: &mut Engine| {
+            inner.fetch_add(1, Ordering::SeqCst);
+        })
+    }
+
+    #[test]
+    fn test_timers_run_on_the_engine_clock() {
+        let mut engine = Engine::new_headless();
+        engine.set_tick_rate(10.0);
+        let (once, once_callback) = counter();
+        let (repeated, repeated_callback) = counter();
+        let once_handle = engine.after(Duration::from_millis(250), once_callback);
+        let repeated_handle = engine.every(Duration::from_millis(100), repeated_callback);
+
+        engine.tick();
+        engine.tick();
+        assert_eq!(once.load(Ordering::SeqCst), 0);
+        assert_eq!(engine.timers().remaining(once_handle), Some(Duration::from_millis(50)));
+
+        engine.pause();
+        engine.tick();
+        engine.unpause();
+        engine.tick();
+        assert_eq!(once.load(Ordering::SeqCst), 1);
+        assert!(!engine.timers().is_pending(once_handle));
+        assert_eq!(repeated.load(Ordering::SeqCst), 3);
+
+        assert!(engine.cancel_timer(repeated_handle));
+        engine.tick();
+        assert_eq!(repeated.load(Ordering::SeqCst), 3);
+        assert!(engine.timers().is_empty());
+    }
+
+    #[test]
+    fn test_repeating_timers_can_cancel_themselves() {
+        let mut engine = Engine::new_headless();
+        let handle = Arc::new(Mutex::new(None));
+        let (count, mut callback) = counter();
+        let own_handle = handle.clone();
+        *handle.lock().unwrap() = Some(engine.every(Duration::ZERO, move |engine| {
+            callback(engine);
+            engine.cancel_timer(own_handle.lock().unwrap().unwrap());
+        }));
+
+        engine.tick();
+        engine.tick();
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_named_timers_are_saved() {
+        static FIRED: AtomicUsize = AtomicUsize::new(0);
+        register_timer_callback("test_named_timers_are_saved", |_| {
+            FIRED.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let mut engine = Engine::new_headless();
+        engine.set_tick_rate(10.0);
+        let handle = engine.timers().every_named(Duration::from_millis(200), "test_named_timers_are_saved");
+        // Still pending at export, closures can't be saved so it's left out
+        engine.after(Duration::from_millis(500), |_| {});
+        engine.tick();
+        assert_eq!(engine.timers().len(), 2);
+
+        let mut loaded = Engine::import_from_json_headless(engine.export_raw());
+        loaded.set_tick_rate(10.0);
+        assert_eq!(loaded.timers().len(), 1);
+        assert_eq!(loaded.timers().remaining(handle), Some(Duration::from_millis(100)));
+
+        loaded.tick();
+        assert_eq!(FIRED.load(Ordering::SeqCst), 1);
+        assert_eq!(loaded.timers().remaining(handle), Some(Duration::from_millis(200)));
+    }
+}
@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+/// Turns variable frame times into a whole number of fixed simulation steps. Time that
+/// isn't a whole step yet is carried over to the next frame, and what's left after
+/// the cap is reached is dropped so a slow frame can't snowball.
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+    step: Duration,
+    max_steps: u32,
+    accumulator: Duration,
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self::new(60.0)
+    }
+}
+
+impl FixedTimestep {
+    /// `rate` simulation steps per second, at most 5 per frame.
+    pub fn new(rate: f64) -> Self {
+        Self {
+            step: Self::step_for(rate),
+            max_steps: 5,
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    fn step_for(rate: f64) -> Duration {
+        assert!(rate > 0.0, "the simulation rate has to be positive, got {}", rate);
+        Duration::from_secs_f64(1.0 / rate)
+    }
+
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    pub fn rate(&self) -> f64 {
+        1.0 / self.step.as_secs_f64()
+    }
+
+    pub fn set_rate(&mut self, rate: f64) {
+        self.step = Self::step_for(rate);
+    }
+
+    pub fn max_steps(&self) -> u32 {
+        self.max_steps
+    }
+
+    pub fn set_max_steps(&mut self, max_steps: u32) {
+        self.max_steps = max_steps.max(1);
+    }
+
+    /// Adds `elapsed` to the accumulator and returns how many steps to run.
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.accumulator += elapsed;
+
+        let mut steps = 0;
+        while self.accumulator >= self.step && steps < self.max_steps {
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+
+        if steps == self.max_steps && self.accumulator >= self.step {
+            self.accumulator = Duration::ZERO;
+        }
+        steps
+    }
+
+    /// How far (0 to 1) the time left in the accumulator is into the next step.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator.as_secs_f64() / self.step.as_secs_f64()) as f32
+    }
+
+    /// Time until the next step is due.
+    pub fn until_next_step(&self) -> Duration {
+        self.step.saturating_sub(self.accumulator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steps_carry_over_remainders() {
+        let mut timestep = FixedTimestep::new(100.0);
+
+        assert_eq!(timestep.advance(Duration::from_millis(25)), 2);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-4);
+        assert_eq!(timestep.advance(Duration::from_millis(5)), 1);
+        assert!(timestep.alpha() < 1e-4);
+        assert_eq!(timestep.advance(Duration::from_millis(3)), 0);
+        assert_eq!(timestep.until_next_step(), Duration::from_millis(7));
+    }
+
+    #[test]
+    fn test_slow_frames_are_capped() {
+        let mut timestep = FixedTimestep::new(100.0);
+        timestep.set_max_steps(3);
+
+        assert_eq!(timestep.advance(Duration::from_secs(1)), 3);
+        assert_eq!(timestep.alpha(), 0.0);
+        assert_eq!(timestep.advance(Duration::from_millis(10)), 1);
+    }
+}
@@ -0,0 +1,280 @@
+use crate::engine::component::Transform;
+use crate::engine::world::{ObjectId, World};
+use log::warn;
+use nalgebra::{Rotation3, Vector3};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Position, rotation (euler angles in degrees, applied x then y then z) and scale, all
+/// relative to the same space.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Pose {
+    pub pos: [f32; 3],
+    pub rot: [f32; 3],
+    pub scale: [f32; 3],
+}
+
+impl Default for Pose {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Pose {
+    pub const IDENTITY: Pose = Pose {
+        pos: [0.0; 3],
+        rot: [0.0; 3],
+        scale: [1.0; 3],
+    };
+
+    fn rotation(&self) -> Rotation3<f32> {
+        Rotation3::from_euler_angles(self.rot[0].to_radians(), self.rot[1].to_radians(), self.rot[2].to_radians())
+    }
+
+    /// `child`, given relative to this pose, in this pose's parent space.
+    pub fn compose(&self, child: &Pose) -> Pose {
+        if *self == Pose::IDENTITY {
+            return *child;
+        }
+
+        let rot = if self.rot == [0.0; 3] {
+            child.rot
+        } else if self.rot[..2] == [0.0; 2] && child.rot[..2] == [0.0; 2] {
+            // Rotations around z only just add up, keeps 2D angles unwrapped
+            [0.0, 0.0, self.rot[2] + child.rot[2]]
+        } else {
+            let (x, y, z) = (self.rotation() * child.rotation()).euler_angles();
+            [x.to_degrees(), y.to_degrees(), z.to_degrees()]
+        };
+
+        Pose {
+            pos: self.transform_point(child.pos),
+            rot,
+            scale: mul(self.scale, child.scale),
+        }
+    }
+
+    /// Blends towards `other` by `t`, component by component. Each angle turns the short
+    /// way round, e.g. from 350 to 10 degrees through 0.
+    pub fn lerp(&self, other: &Pose, t: f32) -> Pose {
+        let lerp = |a: [f32; 3], b: [f32; 3]| [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * t);
+        let turn = |a: [f32; 3], b: [f32; 3]| [0, 1, 2].map(|i| a[i] + ((b[i] - a[i] + 180.0).rem_euclid(360.0) - 180.0) * t);
+        Pose {
+            pos: lerp(self.pos, other.pos),
+            rot: turn(self.rot, other.rot),
+            scale: lerp(self.scale, other.scale),
+        }
+    }
+
+    /// Maps a point in this pose's space to its parent space.
+    pub fn transform_point(&self, point: [f32; 3]) -> [f32; 3] {
+        let scaled = Vector3::from(mul(self.scale, point));
+        let moved = self.rotation() * scaled + Vector3::from(self.pos);
+        moved.into()
+    }
+
+    /// Maps a point in the parent space back to this pose's space.
+    pub fn inverse_transform_point(&self, point: [f32; 3]) -> [f32; 3] {
+        let local = self.rotation().inverse() * (Vector3::from(point) - Vector3::from(self.pos));
+        let local: [f32; 3] = local.into();
+        [0, 1, 2].map(|i| if self.scale[i] == 0.0 { 0.0 } else { local[i] / self.scale[i] })
+    }
+}
+
+fn mul(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] * b[0], a[1] * b[1], a[2] * b[2]]
+}
+
+/// Hands every [`Transform`] its parent's world pose, walking down from the root
+/// objects. Objects without a `Transform` pass their parent's pose on unchanged.
+/// Objects whose parent is gone count as roots. The hierarchy comes from the world's
+/// index, so no object is locked.
+pub fn propagate(world: &World) {
+    let storage = match world.storage::<Transform>() {
+        Some(storage) => storage,
+        None => return,
+    };
+
+    let parents = world.parents();
+    let mut children: HashMap<ObjectId, Vec<ObjectId>> = HashMap::new();
+    for (id, parent) in parents.iter() {
+        children.entry(*parent).or_default().push(*id);
+    }
+
+    let mut transforms = match storage.try_borrow_mut() {
+        Some(transforms) => transforms,
+        None => {
+            warn!("Couldn't propagate transforms, they are already borrowed");
+            return;
+        }
+    };
+
+    for (id, transform) in transforms.iter_mut() {
+        if !parents.contains_key(&id) {
+            transform.set_parent_world(Pose::IDENTITY);
+        }
+    }
+
+    // Only the hierarchies are left, their tops don't need a Transform
+    let mut stack: Vec<(ObjectId, Pose)> = children
+        .keys()
+        .filter(|id| !parents.contains_key(*id))
+        .map(|id| (*id, Pose::IDENTITY))
+        .collect();
+    while let Some((id, parent)) = stack.pop() {
+        let pose = match transforms.get_mut(id) {
+            Some(transform) => {
+                transform.set_parent_world(parent);
+                transform.world()
+            }
+            None => parent,
+        };
+
+        for child in children.remove(&id).unwrap_or_default() {
+            stack.push((child, pose));
+        }
+    }
+}
+
+/// Records every [`Transform`]'s world pose as the start of the next simulation step,
+/// what [`Transform::interpolated`] blends from.
+pub fn snapshot(world: &World) {
+    propagate(world);
+
+    let storage = match world.storage::<Transform>() {
+        Some(storage) => storage,
+        None => return,
+    };
+    let mut transforms = match storage.try_borrow_mut() {
+        Some(transforms) => transforms,
+        None => {
+            warn!("Couldn't snapshot transforms, they are already borrowed");
+            return;
+        }
+    };
+    for (_, transform) in transforms.iter_mut() {
+        transform.store_previous();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::collider::{CubeCollider, Point};
+    use crate::engine::gameobject::{self, make_base_game_object, GameObject, GameObjectState};
+    use crate::engine::save::ComponentSaveLoad;
+    use crate::engine::state::Engine;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    fn set_pos(obj: &Arc<Mutex<GameObject>>, pos: [f32; 3]) {
+        obj.lock().unwrap().get_component_closure::<Transform>(|transform| transform.pos = pos);
+    }
+
+    fn world_pos(obj: &Arc<Mutex<GameObject>>) -> [f32; 3] {
+        obj.lock().unwrap().world_pos()
+    }
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        for i in 0..3 {
+            assert!((a[i] - b[i]).abs() < 1e-4, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_compose_and_convert_points() {
+        let parent = Pose { pos: [1.0, 0.0, 0.0], rot: [0.0, 0.0, 90.0], scale: [2.0; 3] };
+        let child = Pose { pos: [1.0, 0.0, 0.0], rot: [0.0, 0.0, 45.0], scale: [0.5; 3] };
+
+        let world = parent.compose(&child);
+        assert_close(world.pos, [1.0, 2.0, 0.0]);
+        assert_close(world.rot, [0.0, 0.0, 135.0]);
+        assert_close(world.scale, [1.0; 3]);
+
+        assert_close(parent.inverse_transform_point(world.pos), child.pos);
+        assert_eq!(Pose::IDENTITY.compose(&child), child);
+    }
+
+    #[test]
+    fn test_compose_matches_applying_both_rotations() {
+        let parent = Pose { pos: [0.0; 3], rot: [90.0, 0.0, 0.0], scale: [1.0; 3] };
+        let child = Pose { pos: [0.0; 3], rot: [0.0, 0.0, 90.0], scale: [1.0; 3] };
+
+        let world = parent.compose(&child);
+        let point = [1.0, 2.0, 3.0];
+        assert_close(world.transform_point(point), parent.transform_point(child.transform_point(point)));
+    }
+
+    #[test]
+    fn test_lerp_turns_the_short_way_round() {
+        let from = Pose { rot: [0.0, 90.0, 350.0], ..Pose::IDENTITY };
+        let to = Pose { pos: [2.0, 0.0, 0.0], rot: [0.0, -90.0, 10.0], scale: [1.0; 3] };
+
+        let halfway = from.lerp(&to, 0.5);
+        assert_close(halfway.pos, [1.0, 0.0, 0.0]);
+        assert_close(halfway.rot, [0.0, 0.0, 360.0]);
+        assert_close(from.lerp(&to, 0.25).rot, [0.0, 45.0, 355.0]);
+    }
+
+    #[test]
+    fn test_children_follow_their_parents() {
+        let mut engine = Engine::new_headless();
+        let world = engine.world();
+        let parent = engine.add_object(make_base_game_object(&world, "parent".to_string())).get();
+        let child = engine.add_object(make_base_game_object(&world, "child".to_string())).get();
+        let grandchild = engine.add_object(make_base_game_object(&world, "grandchild".to_string())).get();
+
+        set_pos(&parent, [1.0, 0.0, 0.0]);
+        set_pos(&child, [1.0, 0.0, 0.0]);
+        set_pos(&grandchild, [0.0, 1.0, 0.0]);
+        let (parent_id, child_id, grandchild_id) = (
+            parent.lock().unwrap().id(),
+            child.lock().unwrap().id(),
+            grandchild.lock().unwrap().id(),
+        );
+        gameobject::reparent(&world, parent_id, child_id).unwrap();
+        gameobject::reparent(&world, child_id, grandchild_id).unwrap();
+        assert_close(world_pos(&child), [2.0, 0.0, 0.0]);
+
+        parent.lock().unwrap().get_component_closure::<Transform>(|transform| {
+            transform.pos = [0.0, 5.0, 0.0];
+            transform.rot = [0.0, 0.0, 90.0];
+        });
+        engine.tick();
+
+        assert_close(world_pos(&child), [0.0, 6.0, 0.0]);
+        assert_close(world_pos(&grandchild), [-1.0, 6.0, 0.0]);
+    }
+
+    #[test]
+    fn test_colliders_use_the_world_position() {
+        let mut engine = Engine::new_headless();
+        let world = engine.world();
+        let parent = engine.add_object(make_base_game_object(&world, "parent".to_string())).get();
+        let child = engine.add_object(make_base_game_object(&world, "child".to_string())).get();
+        set_pos(&parent, [3.0, 0.0, 0.0]);
+        let (parent_id, child_id) = (parent.lock().unwrap().id(), child.lock().unwrap().id());
+        gameobject::reparent(&world, parent_id, child_id).unwrap();
+        gameobject::add_collider(&world, child_id, Arc::new(Mutex::new(CubeCollider::new(0.5)))).unwrap();
+        engine.tick();
+
+        assert!(gameobject::colliding_point(&world, child_id, Point { x: 3.0, y: 0.0, z: 0.0 }).unwrap());
+        assert!(!gameobject::colliding_point(&world, child_id, Point { x: 0.0, y: 0.0, z: 0.0 }).unwrap());
+    }
+
+    #[test]
+    fn test_transforms_saved_without_scale_load() {
+        let wrapper = <Transform as ComponentSaveLoad>::from_save_data(json!({
+            "pos": [1.0, 2.0, 3.0],
+            "rot": [0.0, 0.0, 0.0],
+            "state": { "_state": null },
+        }))
+        .unwrap();
+
+        let world = World::new();
+        let obj = GameObject::new(&world, "legacy".to_string(), vec![wrapper], GameObjectState::new(true, None, vec![]));
+        let mut scale = [0.0; 3];
+        obj.lock().unwrap().get_component_closure::<Transform>(|transform| scale = transform.scale);
+        assert_eq!(scale, [1.0; 3]);
+    }
+}
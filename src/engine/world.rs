@@ -0,0 +1,631 @@
+use crate::engine::commands::Commands;
+use crate::engine::component::{ComponentTrait, Lifecycle};
+use crate::engine::event::EventBus;
+use crate::engine::gameobject::GameObject;
+use crate::engine::pool::Pools;
+use crate::engine::storage::{ComponentStorage, Storage};
+use crate::engine::time::Time;
+use crate::engine::task::Tasks;
+use crate::engine::timer::Timers;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::any::TypeId;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+/// Generational handle to a game object. The index is reused once the object is
+/// destroyed, the generation is bumped every time that happens so a stale handle
+/// never resolves to the new occupant of the slot.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(from = "ObjectIdRepr")]
+pub struct ObjectId {
+    pub index: u32,
+    pub generation: u32,
+}
+
+impl ObjectId {
+    pub fn new(index: u32, generation: u32) -> Self {
+        Self { index, generation }
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+// Saves written before ids were generational store a plain integer.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ObjectIdRepr {
+    Legacy(u32),
+    Current { index: u32, generation: u32 },
+}
+
+impl From<ObjectIdRepr> for ObjectId {
+    fn from(repr: ObjectIdRepr) -> Self {
+        match repr {
+            ObjectIdRepr::Legacy(index) => ObjectId::new(index, 0),
+            ObjectIdRepr::Current { index, generation } => ObjectId::new(index, generation),
+        }
+    }
+}
+
+struct Slot {
+    generation: u32,
+    object: Option<Arc<Mutex<GameObject>>>,
+    // Marked by `World::destroy`, the object stops resolving until it's flushed
+    destroying: bool,
+}
+
+impl Slot {
+    fn empty() -> Self {
+        Self { generation: 0, object: None, destroying: false }
+    }
+}
+
+struct Registry {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+    // Same objects as the `destroying` slots, in the order they were destroyed
+    pending_destroy: Vec<ObjectId>,
+}
+
+impl Registry {
+    fn slot(&self, id: ObjectId) -> Option<&Slot> {
+        self.slots
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+    }
+
+    fn get(&self, id: ObjectId) -> Option<Arc<Mutex<GameObject>>> {
+        self.slot(id).and_then(|slot| slot.object.clone())
+    }
+
+    fn get_live(&self, id: ObjectId) -> Option<Arc<Mutex<GameObject>>> {
+        self.slot(id).filter(|slot| !slot.destroying).and_then(|slot| slot.object.clone())
+    }
+
+    fn is_live(&self, id: ObjectId) -> bool {
+        self.slot(id).is_some_and(|slot| slot.object.is_some() && !slot.destroying)
+    }
+}
+
+/// Object ids by name, tag, layer and parent, kept up to date by [`GameObject`]'s
+/// setters so lookups never have to lock every object.
+#[derive(Default)]
+struct Index {
+    names: HashMap<String, BTreeSet<ObjectId>>,
+    tags: HashMap<String, BTreeSet<ObjectId>>,
+    layers: HashMap<u32, BTreeSet<ObjectId>>,
+    parents: HashMap<ObjectId, ObjectId>,
+}
+
+fn index_remove<K, Q>(map: &mut HashMap<K, BTreeSet<ObjectId>>, key: &Q, id: ObjectId)
+where
+    K: std::borrow::Borrow<Q> + Hash + Eq,
+    Q: Hash + Eq + ?Sized,
+{
+    if let Some(ids) = map.get_mut(key) {
+        ids.remove(&id);
+        if ids.is_empty() {
+            map.remove(key);
+        }
+    }
+}
+
+/// Owns every game object of one engine. Replaces the old process-global registry so
+/// several engines (and tests) can live side by side in one process.
+pub struct World {
+    registry: Mutex<Registry>,
+    storages: RwLock<HashMap<TypeId, Arc<dyn ComponentStorage>>>,
+    index: Mutex<Index>,
+    events: EventBus,
+    commands: Commands,
+    // f32 bits of the interpolation factor for the current render update
+    interpolation: AtomicU32,
+    time: Mutex<Time>,
+    timers: Timers,
+    tasks: Tasks,
+    pools: Pools,
+}
+
+impl World {
+    pub fn new() -> Arc<Self> {
+        Arc::new_cyclic(|world| Self {
+            registry: Mutex::new(Registry {
+                slots: Vec::new(),
+                free: Vec::new(),
+                pending_destroy: Vec::new(),
+            }),
+            storages: RwLock::new(HashMap::new()),
+            index: Mutex::new(Index::default()),
+            events: EventBus::new(),
+            commands: Commands::new(world.clone()),
+            interpolation: AtomicU32::new(1.0f32.to_bits()),
+            time: Mutex::new(Time::default()),
+            timers: Timers::new(world.clone()),
+            tasks: Tasks::new(world.clone()),
+            pools: Pools::new(world.clone()),
+        })
+    }
+
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Changes to apply at the end of the current tick.
+    pub fn commands(&self) -> &Commands {
+        &self.commands
+    }
+
+    /// How far (0 to 1) the current render update is between the previous simulation
+    /// step and the latest one. Render components blend transforms by it.
+    pub fn interpolation(&self) -> f32 {
+        f32::from_bits(self.interpolation.load(Ordering::Relaxed))
+    }
+
+    /// Callbacks waiting for the engine clock, see [`Timers`].
+    pub fn timers(&self) -> &Timers {
+        &self.timers
+    }
+
+    /// Async tasks polled every simulation step, see [`Tasks`].
+    pub fn tasks(&self) -> &Tasks {
+        &self.tasks
+    }
+
+    /// Deactivated objects waiting to be handed out again, see [`Pools`].
+    pub fn pools(&self) -> &Pools {
+        &self.pools
+    }
+
+    /// The engine clock as of the current simulation step.
+    pub fn time(&self) -> Time {
+        *self.time.lock().unwrap()
+    }
+
+    pub fn set_time_scale(&self, scale: f32) {
+        self.time.lock().unwrap().set_scale(scale);
+    }
+
+    pub(crate) fn advance_time(&self, step: Duration) {
+        self.time.lock().unwrap().advance(step);
+    }
+
+    pub(crate) fn set_time(&self, time: Time) {
+        *self.time.lock().unwrap() = time;
+    }
+
+    pub(crate) fn set_interpolation(&self, alpha: f32) {
+        self.interpolation.store(alpha.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub(crate) fn next_id(&self) -> ObjectId {
+        let mut registry = self.registry.lock().unwrap();
+        if let Some(index) = registry.free.pop() {
+            let generation = registry.slots[index as usize].generation;
+            return ObjectId::new(index, generation);
+        }
+
+        registry.slots.push(Slot::empty());
+        ObjectId::new(registry.slots.len() as u32 - 1, 0)
+    }
+
+    /// Hands an id from [`World::next_id`] back if nothing was ever inserted under it.
+    pub(crate) fn release_id(&self, id: ObjectId) {
+        let mut registry = self.registry.lock().unwrap();
+        let unused = registry.slot(id).is_some_and(|slot| slot.object.is_none());
+        if unused && !registry.free.contains(&id.index) {
+            registry.free.push(id.index);
+        }
+    }
+
+    pub(crate) fn insert(&self, id: ObjectId, obj: Arc<Mutex<GameObject>>) {
+        let mut registry = self.registry.lock().unwrap();
+        let index = id.index as usize;
+
+        while registry.slots.len() <= index {
+            let new_index = registry.slots.len() as u32;
+            registry.slots.push(Slot::empty());
+            if new_index as usize != index {
+                registry.free.push(new_index);
+            }
+        }
+
+        registry.free.retain(|free| *free != id.index);
+        registry.slots[index] = Slot {
+            generation: id.generation,
+            object: Some(obj),
+            destroying: false,
+        };
+    }
+
+    pub fn find_by_id(&self, id: ObjectId) -> Option<Arc<Mutex<GameObject>>> {
+        let registry = self.registry.lock().unwrap();
+
+        match registry.slots.get(id.index as usize) {
+            Some(slot) if slot.generation != id.generation => {
+                warn!("Stale object id {} (slot is now at generation {})", id, slot.generation);
+                None
+            }
+            Some(slot) if slot.destroying => None,
+            Some(slot) if slot.object.is_some() => slot.object.clone(),
+            _ => {
+                debug!("No object with id {}", id);
+                None
+            }
+        }
+    }
+
+    pub fn contains(&self, id: ObjectId) -> bool {
+        self.registry.lock().unwrap().is_live(id)
+    }
+
+    pub fn is_destroyed(&self, id: ObjectId) -> bool {
+        !self.contains(id)
+    }
+
+    /// Marks the object (and, when flushed, its whole subtree) for destruction. It stops
+    /// resolving straight away, the slot is freed by [`World::flush_destroyed`].
+    pub fn destroy(&self, id: ObjectId) {
+        let mut registry = self.registry.lock().unwrap();
+        if registry.is_live(id) {
+            registry.slots[id.index as usize].destroying = true;
+            registry.pending_destroy.push(id);
+        }
+    }
+
+    /// Removes every object marked with [`World::destroy`] along with its descendants,
+    /// unlinks them from surviving parents and returns the removed ids. Must not be
+    /// called while any object of this world is locked.
+    pub fn flush_destroyed(&self) -> Vec<ObjectId> {
+        let mut removed = Vec::new();
+        let mut unlink = Vec::new();
+
+        loop {
+            let pending = std::mem::take(&mut self.registry.lock().unwrap().pending_destroy);
+            if pending.is_empty() {
+                break;
+            }
+
+            for id in pending {
+                let obj = match self.take(id) {
+                    Some(obj) => obj,
+                    None => continue,
+                };
+
+                let mut obj = obj.lock().unwrap();
+                self.unindex(&obj);
+                for storage in obj.storages().to_vec() {
+                    storage.lifecycle(id, &mut obj, Lifecycle::Destroy);
+                }
+                for storage in obj.storages() {
+                    storage.remove(id);
+                }
+                for child in obj.state.child_ids.iter() {
+                    self.destroy(*child);
+                }
+                if let Some(parent) = obj.state.parent_id {
+                    unlink.push((parent, id));
+                }
+
+                self.tasks.cancel_owned_by(id);
+                removed.push(id);
+            }
+        }
+
+        for (parent, child) in unlink {
+            let parent = self.registry.lock().unwrap().get(parent);
+            if let Some(parent) = parent {
+                parent.lock().unwrap().state.child_ids.retain(|id| *id != child);
+            }
+        }
+
+        removed
+    }
+
+    /// First object (lowest id) called `name`.
+    pub fn find_by_name(&self, name: &str) -> Option<Arc<Mutex<GameObject>>> {
+        let ids = self.index.lock().unwrap().names.get(name).cloned().unwrap_or_default();
+        self.resolve(ids).into_iter().next()
+    }
+
+    /// First object (lowest id) tagged `tag`.
+    pub fn find_by_tag(&self, tag: &str) -> Option<Arc<Mutex<GameObject>>> {
+        self.find_all_by_tag(tag).into_iter().next()
+    }
+
+    /// Every object tagged `tag`, ordered by id.
+    pub fn find_all_by_tag(&self, tag: &str) -> Vec<Arc<Mutex<GameObject>>> {
+        let ids = self.index.lock().unwrap().tags.get(tag).cloned().unwrap_or_default();
+        self.resolve(ids)
+    }
+
+    /// Every object in `layer`, ordered by id.
+    pub fn find_in_layer(&self, layer: u32) -> Vec<Arc<Mutex<GameObject>>> {
+        let ids = self.index.lock().unwrap().layers.get(&layer).cloned().unwrap_or_default();
+        self.resolve(ids)
+    }
+
+    fn resolve(&self, ids: BTreeSet<ObjectId>) -> Vec<Arc<Mutex<GameObject>>> {
+        let registry = self.registry.lock().unwrap();
+        ids.into_iter().filter_map(|id| registry.get_live(id)).collect()
+    }
+
+    pub(crate) fn index_name(&self, id: ObjectId, old: Option<&str>, new: &str) {
+        let mut index = self.index.lock().unwrap();
+        if let Some(old) = old {
+            index_remove(&mut index.names, old, id);
+        }
+        index.names.entry(new.to_string()).or_default().insert(id);
+    }
+
+    pub(crate) fn index_tag(&self, id: ObjectId, tag: &str, tagged: bool) {
+        let mut index = self.index.lock().unwrap();
+        if tagged {
+            index.tags.entry(tag.to_string()).or_default().insert(id);
+        } else {
+            index_remove(&mut index.tags, tag, id);
+        }
+    }
+
+    pub(crate) fn index_layer(&self, id: ObjectId, old: Option<u32>, new: u32) {
+        let mut index = self.index.lock().unwrap();
+        if let Some(old) = old {
+            index_remove(&mut index.layers, &old, id);
+        }
+        index.layers.entry(new).or_default().insert(id);
+    }
+
+    pub(crate) fn index_parent(&self, id: ObjectId, parent: Option<ObjectId>) {
+        let mut index = self.index.lock().unwrap();
+        match parent {
+            Some(parent) => index.parents.insert(id, parent),
+            None => index.parents.remove(&id),
+        };
+    }
+
+    /// The parent of every object that has one. Objects whose parent is gone are left
+    /// out.
+    pub fn parents(&self) -> HashMap<ObjectId, ObjectId> {
+        let parents = self.index.lock().unwrap().parents.clone();
+        let registry = self.registry.lock().unwrap();
+        parents
+            .into_iter()
+            .filter(|(id, parent)| registry.is_live(*id) && registry.is_live(*parent))
+            .collect()
+    }
+
+    fn unindex(&self, obj: &GameObject) {
+        let mut index = self.index.lock().unwrap();
+        index_remove(&mut index.names, obj.name(), obj.id());
+        for tag in obj.tags() {
+            index_remove(&mut index.tags, tag.as_str(), obj.id());
+        }
+        index_remove(&mut index.layers, &obj.layer(), obj.id());
+        index.parents.remove(&obj.id());
+    }
+
+    /// Storage holding every `T` in this world, if one was ever added.
+    pub fn storage<T: ComponentTrait>(&self) -> Option<Arc<Storage<T>>> {
+        self.storage_by_type(TypeId::of::<T>())
+            .and_then(|storage| storage.into_any_arc().downcast::<Storage<T>>().ok())
+    }
+
+    pub fn storage_by_type(&self, type_id: TypeId) -> Option<Arc<dyn ComponentStorage>> {
+        self.storages.read().unwrap().get(&type_id).cloned()
+    }
+
+    pub(crate) fn storage_or_insert_with<T: ComponentTrait>(&self, f: impl FnOnce() -> Storage<T>) -> Arc<Storage<T>> {
+        if let Some(storage) = self.storage::<T>() {
+            return storage;
+        }
+
+        let mut storages = self.storages.write().unwrap();
+        let storage = storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Arc::new(f()))
+            .clone();
+        drop(storages);
+
+        storage
+            .into_any_arc()
+            .downcast::<Storage<T>>()
+            .unwrap_or_else(|_| panic!("storage registered under the wrong type"))
+    }
+
+    fn take(&self, id: ObjectId) -> Option<Arc<Mutex<GameObject>>> {
+        let mut registry = self.registry.lock().unwrap();
+        let slot = registry.slots.get_mut(id.index as usize)?;
+        if slot.generation != id.generation {
+            return None;
+        }
+
+        let obj = slot.object.take()?;
+        slot.destroying = false;
+        slot.generation += 1;
+        registry.free.push(id.index);
+        Some(obj)
+    }
+
+    pub fn ids(&self) -> Vec<ObjectId> {
+        self.objects().into_iter().map(|(id, _)| id).collect()
+    }
+
+    /// Snapshot of all live objects. The world lock is released before returning, so
+    /// the objects can be locked freely by the caller.
+    pub fn objects(&self) -> Vec<(ObjectId, Arc<Mutex<GameObject>>)> {
+        let registry = self.registry.lock().unwrap();
+        registry
+            .slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| !slot.destroying)
+            .filter_map(|(index, slot)| {
+                let id = ObjectId::new(index as u32, slot.generation);
+                slot.object.clone().map(|obj| (id, obj))
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::component::Transform;
+    use crate::engine::gameobject::{make_base_game_object, reparent};
+
+    #[test]
+    fn test_worlds_are_independent() {
+        let world_a = World::new();
+        let world_b = World::new();
+
+        let a = make_base_game_object(&world_a, "a".to_string());
+        let b = make_base_game_object(&world_b, "b".to_string());
+
+        let a_id = a.lock().unwrap().id();
+        let b_id = b.lock().unwrap().id();
+
+        assert_eq!(a_id, b_id);
+        assert_eq!(world_a.find_by_id(a_id).unwrap().lock().unwrap().name(), "a");
+        assert_eq!(world_b.find_by_id(b_id).unwrap().lock().unwrap().name(), "b");
+        assert_eq!(world_a.len(), 1);
+        assert_eq!(world_b.len(), 1);
+    }
+
+    #[test]
+    fn test_destroyed_objects_are_hidden() {
+        let world = World::new();
+        let obj = make_base_game_object(&world, "doomed".to_string());
+        let id = obj.lock().unwrap().id();
+
+        obj.lock().unwrap().destroy();
+
+        assert!(world.find_by_id(id).is_none());
+        assert!(world.ids().is_empty());
+    }
+
+    #[test]
+    fn test_stale_ids_do_not_resolve_to_reused_slots() {
+        let world = World::new();
+        let old = make_base_game_object(&world, "old".to_string());
+        let old_id = old.lock().unwrap().id();
+
+        world.destroy(old_id);
+        assert_eq!(world.flush_destroyed(), vec![old_id]);
+
+        let new = make_base_game_object(&world, "new".to_string());
+        let new_id = new.lock().unwrap().id();
+
+        assert_eq!(new_id.index, old_id.index);
+        assert_ne!(new_id.generation, old_id.generation);
+        assert!(world.find_by_id(old_id).is_none());
+        assert_eq!(world.find_by_id(new_id).unwrap().lock().unwrap().name(), "new");
+    }
+
+    #[test]
+    fn test_destroy_cascades_and_unlinks_from_parent() {
+        let world = World::new();
+        let root = make_base_game_object(&world, "root".to_string());
+        let middle = make_base_game_object(&world, "middle".to_string());
+        let leaf = make_base_game_object(&world, "leaf".to_string());
+        let sibling = make_base_game_object(&world, "sibling".to_string());
+
+        let root_id = root.lock().unwrap().id();
+        let middle_id = middle.lock().unwrap().id();
+        let leaf_id = leaf.lock().unwrap().id();
+        let sibling_id = sibling.lock().unwrap().id();
+
+        reparent(&world, root_id, middle_id).unwrap();
+        reparent(&world, middle_id, leaf_id).unwrap();
+        reparent(&world, root_id, sibling_id).unwrap();
+
+        world.destroy(middle_id);
+        let mut removed = world.flush_destroyed();
+        removed.sort();
+
+        assert_eq!(removed, vec![middle_id, leaf_id]);
+        assert!(!world.contains(leaf_id));
+        assert!(world.contains(sibling_id));
+        assert_eq!(root.lock().unwrap().state.child_ids, vec![sibling_id]);
+        assert_eq!(world.parents(), HashMap::from([(sibling_id, root_id)]));
+    }
+
+    #[test]
+    fn test_destroy_removes_components_from_storage() {
+        let world = World::new();
+        let kept = make_base_game_object(&world, "kept".to_string());
+        let doomed = make_base_game_object(&world, "doomed".to_string());
+        let kept_id = kept.lock().unwrap().id();
+        let doomed_id = doomed.lock().unwrap().id();
+
+        world.destroy(doomed_id);
+        world.flush_destroyed();
+
+        let transforms = world.storage::<Transform>().unwrap();
+        let transforms = transforms.borrow();
+        assert_eq!(transforms.len(), 1);
+        assert!(transforms.contains(kept_id));
+        assert!(!transforms.contains(doomed_id));
+    }
+
+    #[test]
+    fn test_find_by_name_tag_and_layer() {
+        let world = World::new();
+        let ids: Vec<ObjectId> = (0..4)
+            .map(|i| {
+                let obj = make_base_game_object(&world, format!("object {}", i));
+                let mut obj = obj.lock().unwrap();
+                if i % 2 == 0 {
+                    obj.add_tag("even");
+                }
+                obj.set_layer(i % 3);
+                obj.id()
+            })
+            .collect();
+
+        let ids_of = |objects: Vec<Arc<Mutex<GameObject>>>| -> Vec<ObjectId> {
+            objects.iter().map(|obj| obj.lock().unwrap().id()).collect()
+        };
+
+        assert_eq!(ids_of(world.find_all_by_tag("even")), vec![ids[0], ids[2]]);
+        assert_eq!(world.find_by_tag("even").unwrap().lock().unwrap().id(), ids[0]);
+        assert_eq!(ids_of(world.find_in_layer(0)), vec![ids[0], ids[3]]);
+        assert_eq!(world.find_by_name("object 1").unwrap().lock().unwrap().id(), ids[1]);
+        assert!(world.find_by_tag("odd").is_none());
+
+        let first = world.find_by_id(ids[0]).unwrap();
+        first.lock().unwrap().update_name("renamed".to_string());
+        first.lock().unwrap().remove_tag("even");
+        first.lock().unwrap().set_layer(2);
+        assert!(world.find_by_name("object 0").is_none());
+        assert_eq!(world.find_by_name("renamed").unwrap().lock().unwrap().id(), ids[0]);
+        assert_eq!(ids_of(world.find_all_by_tag("even")), vec![ids[2]]);
+        assert_eq!(ids_of(world.find_in_layer(2)), vec![ids[0], ids[2]]);
+
+        world.destroy(ids[2]);
+        assert!(world.find_all_by_tag("even").is_empty());
+        world.flush_destroyed();
+        assert_eq!(world.find_in_layer(2).len(), 1);
+        assert!(world.index.lock().unwrap().tags.is_empty());
+    }
+
+    #[test]
+    fn test_legacy_integer_ids_deserialize() {
+        let id: ObjectId = serde_json::from_str("3").unwrap();
+        assert_eq!(id, ObjectId::new(3, 0));
+
+        let id: ObjectId = serde_json::from_str(&serde_json::to_string(&ObjectId::new(4, 2)).unwrap()).unwrap();
+        assert_eq!(id, ObjectId::new(4, 2));
+    }
+}
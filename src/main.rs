@@ -8,9 +8,11 @@ use std::{
 };
 
 use crate::engine::{collider::OctagonCollider, save::{EngineSaveData, self}};
+use crate::engine::prefab::Prefab;
+use crate::engine::world::World;
 #[allow(unused)]
 use crate::engine::component::ComponentTrait;
-use engine::{bounds::Bounds2D, collider::{CubeCollider, Point}, component::{CharacterController2D, ComponentState, ComponentWrapper, InputTickBehavior, RenderOutput, RenderTickBehavior, TickVariant, Transform}, components::RenderComponent, gameobject::{self, make_base_game_object, GameObject}, graphics_backend::{object::Object, primitives::{self, Primitives}}, raycast, save::Link, static_component::StaticComponent, time::OxidizedInstant};
+use engine::{bounds::Bounds2D, collider::{CubeCollider, Point}, component::{CharacterController2D, Component, ComponentState, ComponentWrapper, InputTickBehavior, RenderOutput, RenderTickBehavior, TickVariant, Transform}, components::RenderComponent, gameobject::{self, make_base_game_object, GameObject}, graphics_backend::{object::Object, primitives::{self, Primitives}}, raycast, save::Link, static_component::StaticComponent, time::OxidizedInstant, world::ObjectId};
 
 use serde::{Serialize, Deserialize};
 use crate::save::StaticComponentKey;
@@ -64,18 +66,16 @@ impl StaticComponent for Score {
 
 #[derive(Clone, Debug)]
 struct Spawner {
-    pub enemies: Vec<i32>,
     pub last_spawn: Option<OxidizedInstant>,
     pub cooldown: Duration,
-    pub player: i32,
+    pub player: ObjectId,
     pub moveamt: f32,
     pub scorer: Link<Score>
 }
 
 impl Spawner {
-    pub fn new(player: i32, scorer: Link<Score>) -> Arc<Mutex<Self>> {
+    pub fn new(player: ObjectId, scorer: Link<Score>) -> Arc<Mutex<Self>> {
         Arc::new(Mutex::new(Self {
-            enemies: Vec::new(),
             last_spawn: None,
             cooldown: Duration::from_secs_f32(0.5),
             player,
@@ -97,41 +97,63 @@ impl Spawner {
         pos.x -= bounds.x();
         pos.y -= bounds.y();
         
-        let enemy = e.add_object(make_base_game_object("enemy ".to_owned() + &self.enemies.len().to_string().to_owned()));
+        // Prefabs aren't saved, the pool is
+        if e.prefab("enemy").is_none() {
+            e.register_prefab("enemy", Self::enemy_prefab());
+        }
+        if !e.world().pools().contains("enemy") {
+            if let Err(err) = e.create_pool("enemy", 16) {
+                warn!("Couldn't fill the enemy pool: {}", err);
+            }
+        }
 
-        let c = enemy.get();
-        let mut lock = c.lock().unwrap();
+        // Enemies that were shot are reused instead of built again
+        let id = match e.acquire("enemy") {
+            Ok(id) => id,
+            Err(err) => {
+                warn!("Couldn't spawn an enemy: {}", err);
+                return;
+            }
+        };
+        let _ = gameobject::to_object(&e.world(), id, |enemy| {
+            enemy.get_component_closure::<Transform>(|trans| trans.pos = [pos.x, pos.y, 0.0]);
+        });
+    }
 
-        lock.add_component(RenderComponent::new(Primitives::Octagon(0.1, [1.0, 0.0, 0.0])));
+    fn enemy_prefab() -> Prefab {
+        let template = World::new();
+        let enemy = make_base_game_object(&template, "enemy".to_string());
+        let mut lock = enemy.lock().unwrap();
 
+        lock.add_tag("enemy");
+        lock.add_component(RenderComponent::new(Primitives::Octagon(0.1, [1.0, 0.0, 0.0]))).unwrap();
         lock.add_collider(Arc::new(Mutex::new(OctagonCollider::new(0.1))));
 
-        lock.get_component_closure::<Transform>(|trans| {
-            trans.pos[0] = pos.x;
-            trans.pos[1] = pos.y;
-        });
-
+        let id = lock.id();
         drop(lock);
 
-        self.enemies.push(enemy.get_id());
+        Prefab::from_object(&template, id).unwrap()
     }
 }
 
 impl StaticComponent for Spawner {
     fn tick(&mut self, e: &mut engine::state::Engine) {
         // { println!("spawner score: {}", self.scorer.get_data().lock().unwrap().score); };
+        let now = e.time().now();
         if let Some(last_spawn) = self.last_spawn {
-            // println!("{:?}", OxidizedInstant::now());
-            if OxidizedInstant::now() >= last_spawn + self.cooldown {
+            // println!("{:?}", now);
+            if now >= last_spawn + self.cooldown {
                 self.spawn(e, Bounds2D::new(2.7, 2.0));
-                self.last_spawn = Some(OxidizedInstant::now());
+                self.last_spawn = Some(now);
             }
         } else {
             self.spawn(e, Bounds2D::new(2.7, 2.0));
-            self.last_spawn = Some(OxidizedInstant::now());
+            self.last_spawn = Some(now);
         }
 
-        let player_obj = GameObject::find_by_id(self.player).clone();
+        let world = e.world();
+
+        let player_obj = world.find_by_id(self.player).clone();
         let player_exp = player_obj.expect("no");
         let mut player_lock = player_exp.lock().unwrap();
         let mut player_pos = [0.0, 0.0, 0.0];
@@ -142,18 +164,15 @@ impl StaticComponent for Spawner {
 
         drop(player_lock);
 
-        for enemy in self.enemies.iter() {
-            let obj = GameObject::find_by_id(*enemy).clone();
-            if obj.is_none() {
+        for enemy in world.find_all_by_tag("enemy") {
+            let mut lock = enemy.lock().unwrap();
+            if !lock.state.active() {
                 continue;
             }
 
-            let exp = obj.expect("ok");
-            let mut lock = exp.lock().unwrap();
-
             lock.get_component_closure::<Transform>(|trans| {
                 let pos = trans.pos;
-                let dt = e.dt.unwrap_or(Duration::from_secs(0));
+                let dt = e.delta();
 
                 // // Calculate direction towards player
                 let direction = [
@@ -191,44 +210,36 @@ impl StaticComponent for Spawner {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Component)]
+#[component(input, no_new)]
 struct ShootComponent {
     pub state: ComponentState,
     pub cooldown: Duration,
     pub last_pressed: Option<OxidizedInstant>,
+    #[save(link)]
     pub scorer: Link<Score>
 }
 
 impl ShootComponent {
-    pub fn new(score: Link<Score>) -> Arc<Mutex<ComponentWrapper>> {
-        let component = Arc::new(Mutex::new(Self {
+    pub fn new(score: Link<Score>) -> ComponentWrapper {
+        let component = Self {
             state: ComponentState::new(),
             cooldown: Duration::from_secs_f32(0.5),
             last_pressed: None,
             scorer: score
-        }));
-        let tick_variant = Arc::new(Mutex::new(TickVariant::Input(component.clone())));
-        Arc::new(Mutex::new(ComponentWrapper::new(component, tick_variant)))
-    }
-
-}
-
-impl ComponentTrait for ShootComponent {
-    fn name(&self) -> &str {
-        "ShootComponent"
+        };
+        component.into()
     }
 
-    fn state(&mut self) -> &mut engine::component::ComponentState {
-        &mut self.state
-    }
 }
 
 impl InputTickBehavior for ShootComponent {
     fn tick_with_input(&mut self, input: &engine::component::InputData, obj: &mut GameObject, dt: Duration) {
         // { println!("shoot score: {}", self.scorer.get_data().lock().unwrap().score); };
 
+        let now = obj.world().time().now();
         if let Some(last_pressed) = &self.last_pressed {
-            if OxidizedInstant::now() >= *last_pressed + self.cooldown {
+            if now >= *last_pressed + self.cooldown {
                 self.last_pressed = None
             } else { 
                 return;
@@ -254,34 +265,33 @@ impl InputTickBehavior for ShootComponent {
             z: pos[2]
         };
 
-        for key in input.keys_pressed.clone() {
-            match key {
-                winit::event::VirtualKeyCode::Space => {
-                    self.last_pressed = Some(OxidizedInstant::now());
-                    let cast = raycast::Raycast::send(point.clone(), rot[2] + 90.0, 1000.0, vec![obj.id()]);
-                    let mut result = cast.unwrap();
-
-                    if result.underlying.len() > 0 { 
-                        // println!("hits: {}", result.underlying.len()); 
-                        obj.get_component_closure::<BulletRenderer>(|renderer| {
-                            renderer.set_thickness_timeout(0.1, Duration::from_secs(0));
-                            renderer.tick();
-                            renderer.set_thickness_timeout(0.01, Duration::from_secs_f32(0.2));
-                        });
-
-                        for enemy in result.underlying.iter_mut() {
-                            enemy.lock().unwrap().destroy();
-                            self.scorer.get_data().lock().unwrap().score += 1;
-                        }
-                    };
-                },
-                _ => {}
-            }
+        if input.action("fire") {
+            self.last_pressed = Some(now);
+            let cast = raycast::Raycast::send(&obj.world(), point.clone(), rot[2] + 90.0, 1000.0, vec![obj.id()]);
+            let mut result = cast.unwrap();
+
+            if result.underlying.len() > 0 { 
+                // println!("hits: {}", result.underlying.len()); 
+                obj.get_component_closure::<BulletRenderer>(|renderer| {
+                    renderer.set_thickness_timeout(0.1, Duration::from_secs(0), now);
+                    renderer.tick(now);
+                    renderer.set_thickness_timeout(0.01, Duration::from_secs_f32(0.2), now);
+                });
+
+                for enemy in result.underlying.iter_mut() {
+                    let mut enemy = enemy.lock().unwrap();
+                    if let Err(err) = enemy.release() {
+                        warn!("{}", err);
+                    }
+                    self.scorer.get_data().lock().unwrap().score += 1;
+                }
+            };
         }
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Component)]
+#[component(render, no_new)]
 struct BulletRenderer {
     pub state: ComponentState,
     pub thickness: f32,
@@ -290,25 +300,24 @@ struct BulletRenderer {
 }
 
 impl BulletRenderer {
-    pub fn new() -> Arc<Mutex<ComponentWrapper>> {
-        let component = Arc::new(Mutex::new(Self {
+    pub fn new() -> ComponentWrapper {
+        let component = Self {
             state: ComponentState::new(),
             thickness: 0.01,
             to_set_thickness: 0.01,
             timeout_end: None,
-        }));
-        let tick_variant = Arc::new(Mutex::new(TickVariant::Render(component.clone())));
-        Arc::new(Mutex::new(ComponentWrapper::new(component, tick_variant)))
+        };
+        component.into()
     }
 
-    pub fn set_thickness_timeout(&mut self, thickness: f32, timeout: Duration) {
+    pub fn set_thickness_timeout(&mut self, thickness: f32, timeout: Duration, now: OxidizedInstant) {
         self.to_set_thickness = thickness;
-        self.timeout_end = Some(OxidizedInstant::now() + timeout);
+        self.timeout_end = Some(now + timeout);
     }
 
-    fn tick(&mut self) {
+    fn tick(&mut self, now: OxidizedInstant) {
         if let Some(timeout_end) = &self.timeout_end {
-            if OxidizedInstant::now() >= *timeout_end {
+            if now >= *timeout_end {
                 self.thickness = self.to_set_thickness;
                 self.timeout_end = None;
             }
@@ -316,25 +325,17 @@ impl BulletRenderer {
     }
 }
 
-impl ComponentTrait for BulletRenderer {
-    fn name(&self) -> &str {
-        "BulletRenderer"
-    }
-
-    fn state(&mut self) -> &mut engine::component::ComponentState {
-        &mut self.state
-    }
-}
-
 impl RenderTickBehavior for BulletRenderer {
     fn render_tick(&mut self, obj: &mut GameObject, dt: Duration, cam: engine::camera::Camera) -> engine::component::RenderOutput {
-        self.tick();
+        self.tick(obj.world().time().now());
         let mut _pos: Option<[f32; 3]> = None;
         let mut _rot: Option<[f32; 3]> = None;
 
+        let alpha = obj.world().interpolation();
         obj.get_component_closure::<Transform>(|trans| {
-            _pos = Some(trans.pos.clone());
-            _rot = Some(trans.rot.clone());
+            let pose = trans.interpolated(alpha);
+            _pos = Some(pose.pos);
+            _rot = Some(pose.rot);
         });
 
         if _pos.is_none() || _rot.is_none() { return RenderOutput { obj: None } };
@@ -357,27 +358,6 @@ impl RenderTickBehavior for BulletRenderer {
 async fn run() {
     save::init();
 
-    impl_save_load!(ShootComponent, ShootComponentSaveData, input, 
-        {
-            state: ComponentState, 
-            cooldown: Duration, 
-            last_pressed: Option<OxidizedInstant>
-        },
-        {
-            scorer: Score
-        }
-    );
-
-    impl_save_load!(BulletRenderer, BulletRendererSaveData, render, 
-        {
-            state: ComponentState, 
-            thickness: f32, 
-            to_set_thickness: f32, 
-            timeout_end: Option<OxidizedInstant>
-        },
-        {}
-    );
-
     impl_static_save_load!(Score, ScoreSaveData, 
         { 
             over: bool, 
@@ -388,10 +368,9 @@ async fn run() {
 
     impl_static_save_load!(Spawner, SpawnerSaveData, 
         { 
-            enemies: Vec<i32>, 
             last_spawn: Option<OxidizedInstant>, 
             cooldown: Duration, 
-            player: i32, 
+            player: ObjectId, 
             moveamt: f32 
         }, 
         { 